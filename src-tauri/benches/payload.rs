@@ -0,0 +1,53 @@
+//! Cost of the two ways a completion payload crosses the IPC boundary: as a JSON string
+//! that `stream_api_request` parses a second time, or as a JSON object that
+//! `stream_api_request_json` receives already parsed.
+//!
+//! Run with `cargo bench --bench payload`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::{json, Value};
+
+/// A chat request carrying `messages` messages of about 1 KB each
+fn request_body(messages: usize) -> Value {
+    let content = "lorem ipsum dolor sit amet ".repeat(40);
+    let messages: Vec<Value> = (0..messages)
+        .map(|i| {
+            let role = if i % 2 == 0 { "user" } else { "assistant" };
+            json!({"role": role, "content": format!("{} {}", i, content)})
+        })
+        .collect();
+    json!({"model": "gpt-4o", "stream": true, "messages": messages})
+}
+
+fn ipc_payload(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ipc_payload");
+    for messages in [100, 10_000] {
+        let body = request_body(messages);
+        // Command arguments as the webview sends them
+        let as_string = json!({"provider": "openai", "payload": body.to_string()}).to_string();
+        let as_object = json!({"provider": "openai", "payload": body}).to_string();
+        group.throughput(Throughput::Bytes(as_object.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("string", messages),
+            &as_string,
+            |b, args| {
+                b.iter(|| {
+                    let args: Value = serde_json::from_str(args).unwrap();
+                    let payload = args["payload"].as_str().unwrap();
+                    serde_json::from_str::<Value>(payload).unwrap()
+                })
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("json", messages), &as_object, |b, args| {
+            b.iter(|| {
+                let mut args: Value = serde_json::from_str(args).unwrap();
+                args["payload"].take()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, ipc_payload);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+use crate::commands::mcp_commands::invoke_tool;
+use crate::completion::stream_with_state;
+use crate::services::agent::{
+    fill_tool_result, tool_result_text, EVT_TOOL_RESULT, TOOL_RESULT_PLACEHOLDER,
+};
+use crate::services::mcp::{ServiceManager, ToolCallResponse};
+use crate::services::proxy::{ProxyState, WindowSink};
+use log::info;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, State, Window};
+
+type ServiceState<'a> = State<'a, Arc<Mutex<ServiceManager>>>;
+
+/// Call a tool and stream a completion with its result, in one round-trip.
+///
+/// The tool's text content replaces every `{{tool_result}}` in `payload_template`. The
+/// result is emitted as `ai-stream-tool-result` before the usual stream events.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn tool_then_complete(
+    window: Window,
+    service_state: ServiceState<'_>,
+    proxy_state: State<'_, ProxyState>,
+    service_name: String,
+    tool_name: String,
+    arguments: Option<Value>,
+    provider: String,
+    payload_template: Value,
+) -> Result<(), String> {
+    if !payload_template.is_object() {
+        return Err("Payload template must be a JSON object".to_string());
+    }
+
+    let (tool_result, truncated) = invoke_tool(
+        &service_state,
+        &service_name,
+        &tool_name,
+        arguments.unwrap_or_default(),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut body = payload_template;
+    let filled = fill_tool_result(&mut body, &tool_result_text(&tool_result));
+    if filled == 0 {
+        return Err(format!(
+            "Payload template has no {} placeholder",
+            TOOL_RESULT_PLACEHOLDER
+        ));
+    }
+
+    window
+        .emit(
+            EVT_TOOL_RESULT,
+            ToolCallResponse {
+                success: true,
+                result: Some(tool_result),
+                message: format!("Tool {} called successfully", tool_name),
+                truncated,
+            },
+        )
+        .map_err(|e| format!("Failed to emit {} event: {}", EVT_TOOL_RESULT, e))?;
+
+    info!(
+        "Streaming {} completion with result of {}/{}",
+        provider, service_name, tool_name
+    );
+    let options = proxy_state.stream_options();
+    let sink = WindowSink::new(window);
+    stream_with_state(&proxy_state, &provider, body, options, &sink)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,82 @@
+use crate::commands::mcp_commands::launch_service;
+use crate::services::config::{AppConfig, ConfigImportResponse, REDACTED};
+use crate::services::mcp::{lock_services, ServiceManager};
+use crate::services::proxy::ProxyState;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tauri::{Runtime, State};
+
+type ServiceState<'a> = State<'a, Arc<Mutex<ServiceManager>>>;
+
+#[tauri::command]
+pub fn export_config(
+    service_state: ServiceState<'_>,
+    proxy_state: State<'_, ProxyState>,
+) -> Result<AppConfig, String> {
+    let services = lock_services(&service_state);
+    Ok(AppConfig::collect(&services, &proxy_state))
+}
+
+/// Restore non-secret settings and start services that are not already running.
+///
+/// Services whose arguments or environment were redacted on export are skipped, since they can't be
+/// launched without the original secret.
+#[tauri::command]
+pub async fn import_config<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    service_state: ServiceState<'_>,
+    proxy_state: State<'_, ProxyState>,
+    config: AppConfig,
+) -> Result<ConfigImportResponse, String> {
+    config
+        .apply_proxy(&proxy_state)
+        .map_err(|e| e.to_string())?;
+
+    let running = lock_services(&service_state).list_services();
+
+    let mut services_started = Vec::new();
+    let mut services_skipped = BTreeMap::new();
+    for (name, service_config) in config.services.clone() {
+        if running.contains(&name) {
+            services_skipped.insert(name, "already running".to_string());
+            continue;
+        }
+        let redacted = service_config
+            .args
+            .iter()
+            .chain(service_config.env.values())
+            .any(|value| value.contains(REDACTED));
+        if redacted {
+            services_skipped.insert(
+                name,
+                "launch parameters contain redacted secrets".to_string(),
+            );
+            continue;
+        }
+
+        match launch_service(&app, &name, service_config).await {
+            Ok(_) => services_started.push(name),
+            Err(e) => {
+                warn!("Failed to start imported service {}: {}", name, e);
+                services_skipped.insert(name, e.to_string());
+            }
+        }
+    }
+
+    let missing_keys = config.missing_keys(&proxy_state);
+    info!(
+        "Imported config: {} services started, {} skipped, {} providers missing keys",
+        services_started.len(),
+        services_skipped.len(),
+        missing_keys.len()
+    );
+
+    Ok(ConfigImportResponse {
+        success: true,
+        message: format!("Started {} services", services_started.len()),
+        services_started,
+        services_skipped,
+        missing_keys,
+    })
+}
@@ -1,11 +1,33 @@
-use rmcp::{model::CallToolRequestParam, transport::TokioChildProcess, ServiceExt};
+use futures_util::future::join_all;
+use futures_util::{stream, StreamExt};
+use log::{debug, error, info, warn};
+use rmcp::{
+    model::{
+        ArgumentInfo, CallToolRequestParam, CallToolResult, CompleteRequestParam, CompletionInfo,
+        ErrorCode, LoggingLevel, PaginatedRequestParamInner, Reference, SetLevelRequestParam, Tool,
+    },
+    ServiceError, ServiceExt,
+};
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::process::Stdio;
 use std::sync::{Arc, Mutex};
-use tauri::{Manager, Runtime, State};
-use tokio::process::Command;
+use std::time::Duration;
+use tauri::{Emitter, Manager, Runtime, State};
+use tokio::process::{Child, Command};
 
+use crate::services::mcp::command_line::parse_command_line;
+use crate::services::mcp::namespace::{namespaced_tool_name, split_namespaced};
+use crate::services::mcp::process::spawn_monitor;
+use crate::services::mcp::truncate::truncate_tool_output;
 use crate::services::mcp::{
-    McpError, ServiceManager, ServiceResponse, ToolCallResponse, ToolsResponse,
+    lock_services, AutostartConfig, AutostartSummary, CapabilitiesResponse, Compatibility,
+    CompatibilityResponse, CompletionResponse, Direction, JsonRpcTrace, LogCallback, McpClient,
+    McpError, McpService, ResourceTemplatesResponse, ServiceCapabilities, ServiceConfig,
+    ServiceDetail, ServiceExit, ServiceInfo, ServiceManager, ServiceResponse, ServiceRestartResult,
+    ShutdownReport, Tap, ToolCallResponse, ToolCalls, ToolsPageResponse, ToolsResponse,
+    TracedMessage, DEFAULT_DRAIN_TIMEOUT, EVT_AUTOSTART_COMPLETE, EVT_SERVER_LOG,
+    EVT_SERVICE_EXITED, EVT_TOOL_CANCELLED,
 };
 
 type ServiceState<'a> = State<'a, Arc<Mutex<ServiceManager>>>;
@@ -16,25 +38,56 @@ pub async fn start_service<R: Runtime>(
     service_name: String,
     executable: String,
     args: Vec<String>,
+    handshake_timeout_secs: Option<u64>,
 ) -> Result<ServiceResponse, String> {
-    let result = async {
-        let child_process =
-            TokioChildProcess::new(Command::new(executable).args(args)).map_err(McpError::from)?;
+    if handshake_timeout_secs == Some(0) {
+        return Err(McpError::InvalidArguments(
+            "handshake_timeout_secs must be greater than zero".to_string(),
+        )
+        .to_string());
+    }
 
-        let service = ().serve(child_process).await.map_err(McpError::from)?;
+    let config = ServiceConfig {
+        executable,
+        args,
+        handshake_timeout_secs,
+        ..Default::default()
+    };
 
-        let server_info = service.peer_info();
-        println!("Server info for {}: {:?}", service_name, server_info);
+    let compatibility = launch_service(&app, &service_name, config)
+        .await
+        .map_err(|e| e.to_string())?;
 
-        let service_manager = app.state::<Arc<Mutex<ServiceManager>>>();
-        {
-            let mut state = service_manager.lock()?;
-            state.add_service(service_name.clone(), service);
-        }
+    Ok(ServiceResponse {
+        success: true,
+        message: format!("Service {} started successfully", service_name),
+        warning: compatibility.warning(),
+    })
+}
+
+/// Start a service from a shell-style command line such as `npx -y @scope/server`
+#[tauri::command]
+pub async fn start_service_from_command<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    service_name: String,
+    command_line: String,
+    env: Option<BTreeMap<String, String>>,
+) -> Result<ServiceResponse, String> {
+    let result = async {
+        let parsed = parse_command_line(&command_line)?;
+        let config = ServiceConfig {
+            executable: parsed.executable,
+            args: parsed.args,
+            env: env.unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let compatibility = launch_service(&app, &service_name, config).await?;
 
         Ok(ServiceResponse {
             success: true,
             message: format!("Service {} started successfully", service_name),
+            warning: compatibility.warning(),
         })
     }
     .await;
@@ -42,6 +95,184 @@ pub async fn start_service<R: Runtime>(
     result.map_err(|e: McpError| e.to_string())
 }
 
+/// Start every configured service concurrently, at most `concurrency` at a time, and
+/// emit `mcp-autostart-complete` with the outcome. A failing service doesn't hold up
+/// the others.
+pub(crate) async fn autostart_services<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    config: AutostartConfig,
+) -> AutostartSummary {
+    let concurrency = config.concurrency();
+    let mut results = stream::iter(config.services)
+        .map(|(name, service_config)| async move {
+            let result = launch_service(app, &name, service_config).await;
+            (name, result)
+        })
+        .buffer_unordered(concurrency);
+
+    let mut summary = AutostartSummary::default();
+    while let Some((name, result)) = results.next().await {
+        match result {
+            Ok(_) => summary.started.push(name),
+            Err(e) => {
+                warn!("Failed to auto-start service {}: {}", name, e);
+                summary.failed.insert(name, e.to_string());
+            }
+        }
+    }
+    summary.started.sort();
+
+    info!(
+        "Auto-started {} services, {} failed",
+        summary.started.len(),
+        summary.failed.len()
+    );
+    if let Err(e) = app.emit(EVT_AUTOSTART_COMPLETE, &summary) {
+        warn!("Failed to emit {} event: {}", EVT_AUTOSTART_COMPLETE, e);
+    }
+    summary
+}
+
+/// Spawn an MCP service from its configuration and register it with the manager.
+///
+/// The start can be aborted with [`cancel_start_service`] until the service is registered.
+pub(crate) async fn launch_service<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    service_name: &str,
+    config: ServiceConfig,
+) -> Result<Compatibility, McpError> {
+    let service_manager = app.state::<Arc<Mutex<ServiceManager>>>();
+    let token = lock_services(&service_manager)
+        .begin_start(service_name)
+        .ok_or_else(|| {
+            McpError::InvalidArguments(format!("Service {} is already starting", service_name))
+        })?;
+
+    let started = token
+        .run_until_cancelled(spawn_service(app, service_name, &config))
+        .await;
+
+    let mut state = lock_services(&service_manager);
+    state.finish_start(service_name);
+    // A cancel racing with the end of the handshake still wins; dropping the
+    // process kills it
+    let (service, process) = match started {
+        Some(started) if !token.is_cancelled() => started?,
+        _ => return Err(McpError::Cancelled(service_name.to_string())),
+    };
+    let process = spawn_monitor(service_name, process, {
+        let app = app.clone();
+        move |exit| on_service_exit(&app, exit)
+    });
+    let compatibility = state.add_service(service_name.to_string(), service, config, Some(process));
+    if let Some(warning) = compatibility.warning() {
+        warn!("Service {} protocol mismatch: {}", service_name, warning);
+    }
+    Ok(compatibility)
+}
+
+/// Record how a service's process ended. A crash, as opposed to an exit we caused by
+/// stopping, killing or restarting the service, also removes the service and is
+/// reported to the frontend.
+fn on_service_exit<R: Runtime>(app: &tauri::AppHandle<R>, exit: ServiceExit) {
+    let service_manager = app.state::<Arc<Mutex<ServiceManager>>>();
+    let mut state = lock_services(&service_manager);
+    let removed = if exit.expected {
+        None
+    } else {
+        state.remove_service(&exit.service)
+    };
+    state.record_exit(exit.clone());
+    drop(state);
+    drop(removed);
+
+    if exit.expected {
+        info!(
+            "Service {} exited (code {:?}, signal {:?})",
+            exit.service, exit.code, exit.signal
+        );
+        return;
+    }
+    error!(
+        "Service {} exited unexpectedly (code {:?}, signal {:?})",
+        exit.service, exit.code, exit.signal
+    );
+    if let Err(e) = app.emit(EVT_SERVICE_EXITED, &exit) {
+        warn!("Failed to emit {} event: {}", EVT_SERVICE_EXITED, e);
+    }
+}
+
+/// Spawn the service's process and complete the MCP handshake
+async fn spawn_service<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    service_name: &str,
+    config: &ServiceConfig,
+) -> Result<(McpService, Child), McpError> {
+    // Dropping the child (e.g. on handshake timeout) must not leave the process running
+    let mut process = Command::new(&config.executable)
+        .args(&config.args)
+        .envs(&config.env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = process
+        .stdout
+        .take()
+        .ok_or_else(|| McpError::IoError("Child process has no stdout".to_string()))?;
+    let stdin = process
+        .stdin
+        .take()
+        .ok_or_else(|| McpError::IoError("Child process has no stdin".to_string()))?;
+
+    let log_app = app.clone();
+    let on_log: LogCallback = Arc::new(move |message| {
+        if let Err(e) = log_app.emit(EVT_SERVER_LOG, &message) {
+            warn!("Failed to emit server log for {}: {}", message.service, e);
+        }
+    });
+    let trace = app.state::<Arc<JsonRpcTrace>>().inner().clone();
+    let transport = (
+        Tap::new(stdout, trace.clone(), service_name, Direction::Incoming),
+        Tap::new(stdin, trace, service_name, Direction::Outgoing),
+    );
+    let timeout = config.handshake_timeout();
+    let service = tokio::time::timeout(
+        timeout,
+        McpClient::new(service_name, Some(on_log)).serve(transport),
+    )
+    .await
+    .map_err(|_| McpError::HandshakeTimeout(service_name.to_string(), timeout))??;
+
+    let server_info = service.peer_info();
+    info!(
+        "Server info for {} (pid {:?}): {:?}",
+        service_name,
+        process.id(),
+        server_info
+    );
+
+    Ok((service, process))
+}
+
+/// Abort a `start_service` that is still spawning or handshaking
+#[tauri::command]
+pub fn cancel_start_service(
+    service_state: ServiceState<'_>,
+    service_name: String,
+) -> Result<ServiceResponse, String> {
+    let cancelled = lock_services(&service_state).cancel_start(&service_name);
+    Ok(ServiceResponse {
+        success: cancelled,
+        message: if cancelled {
+            format!("Cancelled starting service {}", service_name)
+        } else {
+            format!("Service {} is not starting", service_name)
+        },
+        warning: None,
+    })
+}
+
 #[tauri::command]
 pub async fn list_tools(
     service_state: ServiceState<'_>,
@@ -49,7 +280,7 @@ pub async fn list_tools(
 ) -> Result<ToolsResponse, String> {
     let result = async {
         let peer = {
-            let state = service_state.lock()?;
+            let state = lock_services(&service_state);
             let server = state
                 .get_service(&service_name)
                 .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
@@ -57,9 +288,10 @@ pub async fn list_tools(
         };
 
         let tools = peer.list_all_tools().await.map_err(McpError::from)?;
+        lock_services(&service_state).cache_tools(&service_name, tools.clone());
 
         let tools_count = tools.len();
-        println!("Found {} tools for {}", tools_count, service_name);
+        debug!("Found {} tools for {}", tools_count, service_name);
 
         Ok(ToolsResponse {
             success: true,
@@ -72,45 +304,350 @@ pub async fn list_tools(
     result.map_err(|e: McpError| e.to_string())
 }
 
+/// Tools of every running service. With `namespaced`, names are qualified as
+/// `service__tool` so that tools sharing a name stay distinguishable; those names are
+/// accepted by [`call_tool_auto`]. Services whose listing fails are skipped.
 #[tauri::command]
-pub async fn call_tool(
+pub async fn list_all_tools(
+    service_state: ServiceState<'_>,
+    namespaced: Option<bool>,
+) -> Result<ToolsResponse, String> {
+    let namespaced = namespaced.unwrap_or(false);
+    let mut all_tools = Vec::new();
+    for (service_name, tools) in service_tools(&service_state).await {
+        all_tools.extend(tools.into_iter().map(|mut tool| {
+            if namespaced {
+                tool.name = Cow::Owned(namespaced_tool_name(&service_name, &tool.name));
+            }
+            tool
+        }));
+    }
+
+    let tools_count = all_tools.len();
+    debug!("Found {} tools across all services", tools_count);
+    Ok(ToolsResponse {
+        success: true,
+        tools: all_tools,
+        message: format!("Found {} tools", tools_count),
+    })
+}
+
+/// Tool lists of every running service, from the cache where possible, sorted by service
+async fn service_tools(services: &Mutex<ServiceManager>) -> Vec<(String, Vec<Tool>)> {
+    let (cached, to_list) = {
+        let state = lock_services(services);
+        let mut cached = Vec::new();
+        let mut to_list = Vec::new();
+        for service_name in state.list_services() {
+            match state.cached_tools(&service_name) {
+                Some(tools) => cached.push((service_name, tools.to_vec())),
+                None => {
+                    if let Some(server) = state.get_service(&service_name) {
+                        to_list.push((service_name, server.peer().clone()));
+                    }
+                }
+            }
+        }
+        (cached, to_list)
+    };
+
+    let mut all = cached;
+    for (service_name, peer) in to_list {
+        match peer.list_all_tools().await {
+            Ok(tools) => {
+                lock_services(services).cache_tools(&service_name, tools.clone());
+                all.push((service_name, tools));
+            }
+            Err(e) => warn!("Failed to list tools for {}: {}", service_name, e),
+        }
+    }
+    all.sort_by(|(a, _), (b, _)| a.cmp(b));
+    all
+}
+
+/// Find the service offering a tool, given a `service__tool` name or a raw name offered
+/// by exactly one service
+async fn resolve_tool(
+    services: &Mutex<ServiceManager>,
+    tool_name: &str,
+) -> Result<(String, String), McpError> {
+    if let Some((service_name, tool)) = split_namespaced(tool_name) {
+        if lock_services(services).get_service(service_name).is_some() {
+            return Ok((service_name.to_string(), tool.to_string()));
+        }
+    }
+
+    let offering: Vec<String> = service_tools(services)
+        .await
+        .into_iter()
+        .filter(|(_, tools)| tools.iter().any(|tool| tool.name == tool_name))
+        .map(|(service_name, _)| service_name)
+        .collect();
+    match offering.as_slice() {
+        [service_name] => Ok((service_name.clone(), tool_name.to_string())),
+        [] => Err(McpError::InvalidArguments(format!(
+            "No running service offers tool {}",
+            tool_name
+        ))),
+        _ => Err(McpError::InvalidArguments(format!(
+            "Tool {} is offered by several services ({}); call it as service__tool",
+            tool_name,
+            offering.join(", ")
+        ))),
+    }
+}
+
+/// Call a tool without naming its service, by `service__tool` name or by a raw name
+/// that only one service offers
+#[tauri::command]
+pub async fn call_tool_auto(
     service_state: ServiceState<'_>,
-    service_name: String,
     tool_name: String,
-    arguments: serde_json::Value,
+    arguments: Option<serde_json::Value>,
 ) -> Result<ToolCallResponse, String> {
     let result = async {
-        let args = match arguments {
-            serde_json::Value::Object(map) => Some(map),
-            _ => {
-                return Err(McpError::InvalidArguments(
-                    "Arguments must be a valid JSON object".to_string(),
-                ))
+        let (service_name, tool) = resolve_tool(&service_state, &tool_name).await?;
+        let (tool_result, truncated) = invoke_tool(
+            &service_state,
+            &service_name,
+            &tool,
+            arguments.unwrap_or_default(),
+        )
+        .await?;
+        Ok(ToolCallResponse {
+            success: true,
+            result: Some(tool_result),
+            message: format!("Tool {} called on {}", tool, service_name),
+            truncated,
+        })
+    }
+    .await;
+
+    result.map_err(|e: McpError| e.to_string())
+}
+
+/// Whether a service offers a tool, answered from the tools cache when possible.
+///
+/// Unknown services and failed listings report `false`.
+#[tauri::command]
+pub async fn has_tool(
+    service_state: ServiceState<'_>,
+    service_name: String,
+    tool_name: String,
+) -> Result<bool, String> {
+    let peer = {
+        let state = lock_services(&service_state);
+        if let Some(tools) = state.cached_tools(&service_name) {
+            return Ok(tools.iter().any(|tool| tool.name == tool_name));
+        }
+        match state.get_service(&service_name) {
+            Some(server) => server.peer().clone(),
+            None => return Ok(false),
+        }
+    };
+
+    match peer.list_all_tools().await {
+        Ok(tools) => {
+            let found = tools.iter().any(|tool| tool.name == tool_name);
+            lock_services(&service_state).cache_tools(&service_name, tools);
+            Ok(found)
+        }
+        Err(e) => {
+            warn!("Failed to list tools for {}: {}", service_name, e);
+            Ok(false)
+        }
+    }
+}
+
+/// Fetch one page of tools using the server's cursor-based pagination.
+///
+/// Servers choose their own page size, so `limit` is a target: pages are combined until
+/// at least `limit` tools are collected or the server runs out, and one server page is
+/// returned when it is absent.
+#[tauri::command]
+pub async fn list_tools_page(
+    service_state: ServiceState<'_>,
+    service_name: String,
+    cursor: Option<String>,
+    limit: Option<usize>,
+) -> Result<ToolsPageResponse, String> {
+    let result = async {
+        if limit == Some(0) {
+            return Err(McpError::InvalidArguments(
+                "limit must be greater than zero".to_string(),
+            ));
+        }
+
+        let peer = {
+            let state = lock_services(&service_state);
+            let server = state
+                .get_service(&service_name)
+                .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
+            server.peer().clone()
+        };
+
+        let mut tools = Vec::new();
+        let mut next_cursor = cursor;
+        loop {
+            let page = peer
+                .list_tools(Some(PaginatedRequestParamInner {
+                    cursor: next_cursor.take(),
+                }))
+                .await
+                .map_err(McpError::from)?;
+            tools.extend(page.tools);
+            next_cursor = page.next_cursor;
+
+            if next_cursor.is_none() || tools.len() >= limit.unwrap_or(0) {
+                break;
             }
+        }
+
+        let tools_count = tools.len();
+        debug!(
+            "Found {} tools for {} (more: {})",
+            tools_count,
+            service_name,
+            next_cursor.is_some()
+        );
+
+        Ok(ToolsPageResponse {
+            success: true,
+            tools,
+            next_cursor,
+            message: format!("Found {} tools", tools_count),
+        })
+    }
+    .await;
+
+    result.map_err(|e: McpError| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_resource_templates(
+    service_state: ServiceState<'_>,
+    service_name: String,
+) -> Result<ResourceTemplatesResponse, String> {
+    let result = async {
+        let peer = {
+            let state = lock_services(&service_state);
+            let server = state
+                .get_service(&service_name)
+                .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
+            server.peer().clone()
         };
 
+        let resource_templates = peer
+            .list_all_resource_templates()
+            .await
+            .map_err(McpError::from)?;
+
+        let templates_count = resource_templates.len();
+        debug!(
+            "Found {} resource templates for {}",
+            templates_count, service_name
+        );
+
+        Ok(ResourceTemplatesResponse {
+            success: true,
+            resource_templates,
+            message: format!("Found {} resource templates", templates_count),
+        })
+    }
+    .await;
+
+    result.map_err(|e: McpError| e.to_string())
+}
+
+#[tauri::command]
+pub async fn complete_argument(
+    service_state: ServiceState<'_>,
+    service_name: String,
+    reference: Reference,
+    argument: String,
+    value: String,
+) -> Result<CompletionResponse, String> {
+    let result = async {
         let peer = {
-            let state = service_state.lock()?;
+            let state = lock_services(&service_state);
             let server = state
                 .get_service(&service_name)
                 .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
             server.peer().clone()
         };
 
-        let tool_result = peer
-            .call_tool(CallToolRequestParam {
-                name: Cow::Owned(tool_name.clone()),
-                arguments: args,
+        let completion = match peer
+            .complete(CompleteRequestParam {
+                r#ref: reference,
+                argument: ArgumentInfo {
+                    name: argument.clone(),
+                    value,
+                },
             })
             .await
-            .map_err(McpError::from)?;
+        {
+            Ok(result) => result.completion,
+            Err(ServiceError::McpError(e)) if e.code == ErrorCode::METHOD_NOT_FOUND => {
+                // Servers without the completion capability reject the method outright
+                debug!("Service {} does not support completion", service_name);
+                CompletionInfo {
+                    values: Vec::new(),
+                    total: None,
+                    has_more: None,
+                }
+            }
+            Err(e) => return Err(McpError::from(e)),
+        };
+
+        Ok(CompletionResponse {
+            success: true,
+            message: format!(
+                "Found {} completions for {}",
+                completion.values.len(),
+                argument
+            ),
+            completion,
+        })
+    }
+    .await;
 
-        println!("Tool {} called successfully.", tool_name);
+    result.map_err(|e: McpError| e.to_string())
+}
 
+/// Call a tool on a service.
+///
+/// The call is tracked under `call_id`, generated when absent, so `cancel_tool_call`
+/// can stop it. Calls made on behalf of another running call name it as
+/// `parent_call_id`, and are cancelled with it when the cancel cascades.
+#[tauri::command]
+pub async fn call_tool(
+    service_state: ServiceState<'_>,
+    tool_calls: State<'_, ToolCalls>,
+    service_name: String,
+    tool_name: String,
+    arguments: Option<serde_json::Value>,
+    call_id: Option<String>,
+    parent_call_id: Option<String>,
+) -> Result<ToolCallResponse, String> {
+    let result = async {
+        let call = tool_calls.register(call_id, parent_call_id, &service_name, &tool_name)?;
+        let invoked = invoke_tool(
+            &service_state,
+            &service_name,
+            &tool_name,
+            arguments.unwrap_or_default(),
+        );
+        // Dropping the call also gives up its place in the service's queue
+        let (tool_result, truncated) = call
+            .cancelled()
+            .run_until_cancelled(invoked)
+            .await
+            .ok_or_else(|| McpError::ToolCallCancelled(call.id().to_string()))??;
         Ok(ToolCallResponse {
             success: true,
             result: Some(tool_result),
             message: format!("Tool {} called successfully", tool_name),
+            truncated,
         })
     }
     .await;
@@ -118,33 +655,351 @@ pub async fn call_tool(
     result.map_err(|e: McpError| e.to_string())
 }
 
+/// Cancel a running tool call and, unless `cascade` is false, every call made under it
+/// through `parent_call_id`. Emits `mcp-tool-cancelled` for each cancelled call and
+/// returns their ids, parents first.
 #[tauri::command]
-pub fn get_services(service_state: ServiceState<'_>) -> Result<Vec<String>, String> {
+pub fn cancel_tool_call<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    tool_calls: State<'_, ToolCalls>,
+    call_id: String,
+    cascade: Option<bool>,
+) -> Result<Vec<String>, String> {
+    let cancelled = tool_calls
+        .cancel(&call_id, cascade.unwrap_or(true))
+        .ok_or_else(|| format!("Tool call {} is not running", call_id))?;
+
+    let mut ids = Vec::with_capacity(cancelled.len());
+    for call in cancelled {
+        info!(
+            "Cancelled tool call {} ({}/{})",
+            call.call_id, call.service, call.tool
+        );
+        if let Err(e) = app.emit(EVT_TOOL_CANCELLED, &call) {
+            warn!("Failed to emit {} event: {}", EVT_TOOL_CANCELLED, e);
+        }
+        ids.push(call.call_id);
+    }
+    Ok(ids)
+}
+
+/// Tool arguments as a JSON object; `null` stands for no arguments, since frontends
+/// send `null` and `{}` interchangeably for tools that take none
+fn tool_arguments(
+    arguments: serde_json::Value,
+) -> Result<serde_json::Map<String, serde_json::Value>, McpError> {
+    match arguments {
+        serde_json::Value::Object(map) => Ok(map),
+        serde_json::Value::Null => Ok(serde_json::Map::new()),
+        other => {
+            let kind = match other {
+                serde_json::Value::Array(_) => "an array",
+                serde_json::Value::String(_) => "a string",
+                serde_json::Value::Number(_) => "a number",
+                _ => "a boolean",
+            };
+            Err(McpError::InvalidArguments(format!(
+                "Arguments must be a JSON object or null, got {}",
+                kind
+            )))
+        }
+    }
+}
+
+/// Call a tool on a running service, queueing behind its concurrency limit and
+/// truncating its text output to the service's output limit. Returns the result and
+/// whether it was truncated.
+pub(crate) async fn invoke_tool(
+    services: &Mutex<ServiceManager>,
+    service_name: &str,
+    tool_name: &str,
+    arguments: serde_json::Value,
+) -> Result<(CallToolResult, bool), McpError> {
+    let args = Some(tool_arguments(arguments)?);
+
+    let (peer, call_limiter, output_limit) = {
+        let mut state = lock_services(services);
+        let peer = state
+            .get_service(service_name)
+            .ok_or_else(|| McpError::ServiceNotFound(service_name.to_string()))?
+            .peer()
+            .clone();
+        state.mark_used(service_name);
+        (
+            peer,
+            state.call_limiter(service_name),
+            state.output_limit(service_name),
+        )
+    };
+
+    // Queue behind in-flight calls when the service has a concurrency limit
+    let _permit = match call_limiter {
+        Some(semaphore) => Some(
+            semaphore
+                .acquire_owned()
+                .await
+                .map_err(|e| McpError::LockError(e.to_string()))?,
+        ),
+        None => None,
+    };
+
+    let mut tool_result = peer
+        .call_tool(CallToolRequestParam {
+            name: Cow::Owned(tool_name.to_string()),
+            arguments: args,
+        })
+        .await
+        .map_err(McpError::from)?;
+
+    debug!("Tool {} called successfully.", tool_name);
+    let truncated = truncate_tool_output(&mut tool_result, output_limit);
+    if truncated {
+        warn!(
+            "Output of {}/{} truncated to {} bytes",
+            service_name, tool_name, output_limit
+        );
+    }
+    Ok((tool_result, truncated))
+}
+
+/// Limit the text a tool result may carry, for one service or, without
+/// `service_name`, for every service that doesn't set its own. `None` removes the
+/// limit's override, falling back to the app-wide limit or the 1 MiB default.
+#[tauri::command]
+pub fn set_tool_output_limit(
+    service_state: ServiceState<'_>,
+    service_name: Option<String>,
+    max_bytes: Option<usize>,
+) -> Result<ServiceResponse, String> {
+    let result = (|| {
+        if max_bytes == Some(0) {
+            return Err(McpError::InvalidArguments(
+                "Output limit must be greater than zero".to_string(),
+            ));
+        }
+
+        let mut state = lock_services(&service_state);
+        let scope = match &service_name {
+            Some(service_name) => {
+                if !state.set_output_limit(service_name, max_bytes) {
+                    return Err(McpError::ServiceNotFound(service_name.clone()));
+                }
+                format!("Service {}", service_name)
+            }
+            None => {
+                state.set_default_output_limit(max_bytes);
+                "Services without their own limit".to_string()
+            }
+        };
+
+        Ok(ServiceResponse {
+            success: true,
+            message: match max_bytes {
+                Some(max_bytes) => {
+                    format!("{} limited to {} bytes of tool output", scope, max_bytes)
+                }
+                None => format!("{} use the default tool output limit", scope),
+            },
+            warning: None,
+        })
+    })();
+
+    result.map_err(|e: McpError| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_service_concurrency(
+    service_state: ServiceState<'_>,
+    service_name: String,
+    limit: Option<usize>,
+) -> Result<ServiceResponse, String> {
+    let result = (|| {
+        if limit == Some(0) {
+            return Err(McpError::InvalidArguments(
+                "Concurrency limit must be greater than zero".to_string(),
+            ));
+        }
+
+        let mut state = lock_services(&service_state);
+        if !state.set_concurrency(&service_name, limit) {
+            return Err(McpError::ServiceNotFound(service_name.clone()));
+        }
+
+        Ok(ServiceResponse {
+            success: true,
+            message: match limit {
+                Some(limit) => format!(
+                    "Service {} limited to {} concurrent tool calls",
+                    service_name, limit
+                ),
+                None => format!("Service {} tool calls are unlimited", service_name),
+            },
+            warning: None,
+        })
+    })();
+
+    result.map_err(|e: McpError| e.to_string())
+}
+
+/// Record JSON-RPC traffic with every service for [`get_jsonrpc_log`]; turning it off
+/// discards what was recorded
+#[tauri::command]
+pub fn set_mcp_tracing(trace: State<'_, Arc<JsonRpcTrace>>, enabled: bool) {
+    info!(
+        "MCP JSON-RPC tracing {}",
+        if enabled { "enabled" } else { "disabled" }
+    );
+    trace.set_enabled(enabled);
+}
+
+/// Recent JSON-RPC messages exchanged with a service, oldest first, while tracing is on
+#[tauri::command]
+pub fn get_jsonrpc_log(
+    trace: State<'_, Arc<JsonRpcTrace>>,
+    service_name: String,
+) -> Vec<TracedMessage> {
+    trace.log(&service_name)
+}
+
+/// Protocol version negotiated with a service and what the mismatch, if any, limits
+#[tauri::command]
+pub fn get_compatibility(
+    service_state: ServiceState<'_>,
+    service_name: String,
+) -> Result<CompatibilityResponse, String> {
     let result = (|| {
-        let state = service_state.lock()?;
-        Ok(state.list_services())
+        let state = lock_services(&service_state);
+        let compatibility = state
+            .compatibility(&service_name)
+            .cloned()
+            .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
+
+        Ok(CompatibilityResponse {
+            success: true,
+            message: format!(
+                "Service {} speaks MCP {}",
+                service_name, compatibility.server_version
+            ),
+            compatibility,
+        })
     })();
 
     result.map_err(|e: McpError| e.to_string())
 }
 
+#[tauri::command]
+pub fn get_capabilities(
+    service_state: ServiceState<'_>,
+    service_name: String,
+) -> Result<CapabilitiesResponse, String> {
+    let result = (|| {
+        let state = lock_services(&service_state);
+        let raw = state
+            .get_capabilities(&service_name)
+            .cloned()
+            .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
+
+        Ok(CapabilitiesResponse {
+            success: true,
+            capabilities: ServiceCapabilities::from_advertised(&raw),
+            raw,
+            message: format!("Capabilities for {}", service_name),
+        })
+    })();
+
+    result.map_err(|e: McpError| e.to_string())
+}
+
+/// Ask a service to change its log verbosity (`logging/setLevel`)
+#[tauri::command]
+pub async fn set_log_level(
+    service_state: ServiceState<'_>,
+    service_name: String,
+    level: String,
+) -> Result<ServiceResponse, String> {
+    let result = async {
+        let level: LoggingLevel =
+            serde_json::from_value(serde_json::Value::String(level.to_lowercase()))
+                .map_err(|_| McpError::InvalidArguments(format!("Unknown log level: {}", level)))?;
+
+        let peer = {
+            let state = lock_services(&service_state);
+            let server = state
+                .get_service(&service_name)
+                .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
+            server.peer().clone()
+        };
+
+        let message = format!("Service {} log level set to {:?}", service_name, level);
+        peer.set_level(SetLevelRequestParam { level })
+            .await
+            .map_err(McpError::from)?;
+
+        Ok(ServiceResponse {
+            success: true,
+            message,
+            warning: None,
+        })
+    }
+    .await;
+
+    result.map_err(|e: McpError| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_services(service_state: ServiceState<'_>) -> Result<Vec<String>, String> {
+    Ok(lock_services(&service_state).list_services())
+}
+
+/// Live state of every running service: process, start time, tool count, last use and
+/// config. Tool counts are known once the service's tools have been listed.
+#[tauri::command]
+pub fn get_services_detailed(
+    service_state: ServiceState<'_>,
+) -> Result<Vec<ServiceDetail>, String> {
+    Ok(lock_services(&service_state).details())
+}
+
+/// Process details of a service, including how it last exited. Also answers for a
+/// service that is no longer running, as long as it has exited since the app started.
+#[tauri::command]
+pub fn get_service_info(
+    service_state: ServiceState<'_>,
+    service_name: String,
+) -> Result<ServiceInfo, String> {
+    let state = lock_services(&service_state);
+    let config = state.get_config(&service_name).cloned();
+    let last_exit = state.last_exit(&service_name).cloned();
+    if config.is_none() && last_exit.is_none() {
+        return Err(McpError::ServiceNotFound(service_name).to_string());
+    }
+
+    Ok(ServiceInfo {
+        running: config.is_some(),
+        pid: state.pid(&service_name),
+        config,
+        last_exit,
+        name: service_name,
+    })
+}
+
 #[tauri::command]
 pub async fn stop_service(
     service_state: ServiceState<'_>,
     service_name: String,
 ) -> Result<ServiceResponse, String> {
     let maybe_service = {
-        let mut service_manager = service_state
-            .lock()
-            .map_err(|e| McpError::LockError(e.to_string()))?;
+        let mut service_manager = lock_services(&service_state);
         service_manager.remove_service(&service_name)
     };
 
-    if let Some(service) = maybe_service {
+    // The child is held until cancellation finishes, then killed if it is still running
+    if let Some((service, _process)) = maybe_service {
         match service.cancel().await {
             Ok(_) => Ok(ServiceResponse {
                 success: true,
                 message: format!("Service {} stopped successfully", service_name),
+                warning: None,
             }),
             Err(e) => Err(McpError::from(e).to_string()),
         }
@@ -152,6 +1007,289 @@ pub async fn stop_service(
         Ok(ServiceResponse {
             success: false,
             message: format!("Service {} not found", service_name),
+            warning: None,
         })
     }
 }
+
+/// Stop every service at once, giving each `drain_timeout_secs` (default 5) to shut down
+/// gracefully before its process is killed, and report how each one ended.
+///
+/// A service whose shutdown fails is still removed, and its process killed.
+#[tauri::command]
+pub async fn stop_all_services(
+    service_state: ServiceState<'_>,
+    drain_timeout_secs: Option<u64>,
+) -> Result<ShutdownReport, String> {
+    let drain_timeout = drain_timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_DRAIN_TIMEOUT);
+    let services = lock_services(&service_state).remove_all();
+    info!(
+        "Stopping {} services with a {:?} drain timeout",
+        services.len(),
+        drain_timeout
+    );
+
+    let outcomes = join_all(
+        services
+            .into_iter()
+            .map(|(name, service, process)| async move {
+                // Ok(true) when the process had to be killed
+                let outcome = match tokio::time::timeout(drain_timeout, service.cancel()).await {
+                    Ok(Ok(_)) => Ok(false),
+                    Ok(Err(e)) => Err(McpError::from(e).to_string()),
+                    Err(_) => match &process {
+                        Some(process) => {
+                            warn!(
+                                "Service {} didn't stop within {:?}, killing it",
+                                name, drain_timeout
+                            );
+                            process.start_kill();
+                            Ok(true)
+                        }
+                        None => Err(format!("Timed out after {:?}", drain_timeout)),
+                    },
+                };
+                (name, outcome)
+            }),
+    )
+    .await;
+
+    let mut report = ShutdownReport::default();
+    for (name, outcome) in outcomes {
+        match outcome {
+            Ok(false) => report.stopped.push(name),
+            Ok(true) => report.forced.push(name),
+            Err(e) => {
+                error!("Failed to stop service {}: {}", name, e);
+                report.failed.push((name, e));
+            }
+        }
+    }
+    report.stopped.sort();
+    report.forced.sort();
+    report.failed.sort();
+
+    info!(
+        "Stopped {} services, {} forced, {} failed",
+        report.stopped.len(),
+        report.forced.len(),
+        report.failed.len()
+    );
+    Ok(report)
+}
+
+/// Forcibly kill a service's process and forget it, without graceful cancellation.
+///
+/// A last resort for a wedged server: the MCP session is dropped rather than shut down,
+/// so the server gets no chance to clean up its resources (temp files, locks, children).
+#[tauri::command]
+pub fn kill_service(
+    service_state: ServiceState<'_>,
+    service_name: String,
+) -> Result<ServiceResponse, String> {
+    let result = (|| {
+        let mut state = lock_services(&service_state);
+        let pid = state.pid(&service_name);
+        let (service, process) = state
+            .remove_service(&service_name)
+            .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
+        drop(state);
+
+        // The kill itself happens on the service's monitor task, which logs a failure
+        let killed = match process {
+            Some(process) => {
+                process.start_kill();
+                true
+            }
+            None => false,
+        };
+        drop(service);
+
+        Ok(ServiceResponse {
+            success: killed,
+            message: if killed {
+                format!("Service {} (pid {:?}) killed", service_name, pid)
+            } else {
+                format!(
+                    "Service {} removed, but its process could not be killed",
+                    service_name
+                )
+            },
+            warning: None,
+        })
+    })();
+
+    result.map_err(|e: McpError| e.to_string())
+}
+
+/// Restart every service whose executable contains `executable_substring`, using each
+/// service's saved config. A failure only affects that service's entry in the result.
+#[tauri::command]
+pub async fn restart_services_matching<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    service_state: ServiceState<'_>,
+    executable_substring: String,
+) -> Result<Vec<ServiceRestartResult>, String> {
+    if executable_substring.trim().is_empty() {
+        return Err(McpError::InvalidArguments(
+            "executable_substring must not be empty".to_string(),
+        )
+        .to_string());
+    }
+
+    let matching: Vec<(String, ServiceConfig)> = {
+        let state = lock_services(&service_state);
+        state
+            .configs()
+            .filter(|(_, config)| config.executable.contains(&executable_substring))
+            .map(|(name, config)| (name.clone(), config.clone()))
+            .collect()
+    };
+    info!(
+        "Restarting {} services matching {}",
+        matching.len(),
+        executable_substring
+    );
+
+    let mut results = Vec::with_capacity(matching.len());
+    for (service_name, config) in matching {
+        let restarted = restart_service(&app, &service_state, &service_name, config).await;
+        results.push(ServiceRestartResult {
+            success: restarted.is_ok(),
+            message: match restarted {
+                Ok(()) => format!("Service {} restarted", service_name),
+                Err(e) => e.to_string(),
+            },
+            service_name,
+        });
+    }
+
+    Ok(results)
+}
+
+async fn restart_service<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    service_state: &ServiceState<'_>,
+    service_name: &str,
+    config: ServiceConfig,
+) -> Result<(), McpError> {
+    let removed = lock_services(service_state).remove_service(service_name);
+    if let Some((service, _process)) = removed {
+        // A failed cancel still leaves the old process to be killed when dropped
+        if let Err(e) = service.cancel().await {
+            warn!("Failed to cancel {} before restart: {}", service_name, e);
+        }
+    }
+    launch_service(app, service_name, config).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether the process is gone, or a zombie only waiting to be reaped
+    #[cfg(target_os = "linux")]
+    fn is_dead(pid: &str) -> bool {
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            Ok(stat) => stat
+                .rsplit_once(')')
+                .is_some_and(|(_, rest)| rest.trim_start().starts_with('Z')),
+            Err(_) => true,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn kills_a_service_that_never_completes_the_handshake() {
+        let app = tauri::test::mock_app();
+        app.manage(Arc::new(JsonRpcTrace::default()));
+        let pid_file =
+            std::env::temp_dir().join(format!("robin-handshake-{}.pid", std::process::id()));
+        let config = ServiceConfig {
+            executable: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                format!("echo $$ > '{}'; exec sleep 30", pid_file.display()),
+            ],
+            handshake_timeout_secs: Some(1),
+            ..Default::default()
+        };
+
+        let started = std::time::Instant::now();
+        let result = spawn_service(app.handle(), "silent", &config).await;
+        assert!(matches!(
+            result,
+            Err(McpError::HandshakeTimeout(name, timeout))
+                if name == "silent" && timeout == Duration::from_secs(1)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let _ = std::fs::remove_file(&pid_file);
+        let mut waited = Duration::ZERO;
+        while !is_dead(pid.trim()) && waited < Duration::from_secs(5) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            waited += Duration::from_millis(50);
+        }
+        assert!(
+            is_dead(pid.trim()),
+            "service process {} still running",
+            pid.trim()
+        );
+    }
+
+    #[test]
+    fn uses_the_default_handshake_timeout_unless_configured() {
+        let config = ServiceConfig::default();
+        assert_eq!(
+            config.handshake_timeout(),
+            crate::services::mcp::service::DEFAULT_HANDSHAKE_TIMEOUT
+        );
+        let config = ServiceConfig {
+            handshake_timeout_secs: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(config.handshake_timeout(), Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_namespaced_tool_of_a_service_that_is_not_running() {
+        let services = Mutex::new(ServiceManager::default());
+        let result = resolve_tool(&services, "files__search").await;
+        assert!(matches!(
+            result,
+            Err(McpError::InvalidArguments(message))
+                if message == "No running service offers tool files__search"
+        ));
+    }
+
+    #[test]
+    fn takes_an_object_or_null_as_tool_arguments() {
+        let arguments = tool_arguments(serde_json::json!({"query": "rust"})).unwrap();
+        assert_eq!(arguments.get("query"), Some(&serde_json::json!("rust")));
+
+        assert!(tool_arguments(serde_json::Value::Null).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_tool_arguments_that_are_not_an_object() {
+        for (arguments, kind) in [
+            (serde_json::json!(["rust"]), "an array"),
+            (serde_json::json!("rust"), "a string"),
+            (serde_json::json!(3), "a number"),
+            (serde_json::json!(true), "a boolean"),
+        ] {
+            let message = match tool_arguments(arguments) {
+                Err(McpError::InvalidArguments(message)) => message,
+                other => panic!("expected invalid arguments, got {:?}", other),
+            };
+            assert_eq!(
+                message,
+                format!("Arguments must be a JSON object or null, got {}", kind)
+            );
+        }
+    }
+}
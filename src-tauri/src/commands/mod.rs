@@ -1,2 +1,5 @@
+pub mod agent_commands;
+pub mod config_commands;
 pub mod mcp_commands;
 pub mod proxy_commands;
+pub mod status_commands;
@@ -1,14 +1,51 @@
-use crate::services::proxy::get_provider;
-use log::info;
+use crate::completion::{self, stream_with_state, CompletionResult};
+use crate::services::proxy::keychain;
+use crate::services::proxy::limiter::DEFAULT_MAX_QUEUE_DEPTH;
+use crate::services::proxy::middleware::{
+    RedactSecrets, SystemPromptOverride, REDACTION_MIDDLEWARE, SYSTEM_PROMPT_MIDDLEWARE,
+};
+use crate::services::proxy::models::{fetch_context_window, fetch_models, static_models};
+use crate::services::proxy::trim;
+#[cfg(feature = "ws-server")]
+use crate::services::proxy::ws;
+use crate::services::proxy::{
+    build_extra_headers, emit_end, key_var, load_api_key, provider_with_key,
+    reload_env as reload_env_file, set_key_env_var as set_key_var, ActiveStreamInfo,
+    CancelledStream, ChannelEvent, ChannelSink, EventSink, FanoutSink, FinishReason,
+    MetricsSnapshot, ModelAlias, ModelInfo, ProxyState, RetryPolicy, StreamHandle, StreamOptions,
+    UserUsage, WindowSink, STREAM_PROTOCOL_VERSION,
+};
+use futures_util::{stream, StreamExt};
+use log::{info, warn};
 use serde_json::Value;
-use tauri::Window;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::ipc::Channel;
+#[cfg(feature = "ws-server")]
+use tauri::AppHandle;
+use tauri::{Manager, ResourceId, Runtime, State, Window};
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn stream_api_request(
     window: Window,
-    provider: String,
+    proxy_state: State<'_, ProxyState>,
+    provider: Option<String>,
     payload: String,
+    extra_headers: Option<HashMap<String, String>>,
+    stream_id: Option<String>,
+    user_id: Option<String>,
+    metadata: Option<Value>,
+    auto_trim: Option<bool>,
+    window_labels: Option<Vec<String>>,
+    expected_role: Option<String>,
+    max_messages: Option<usize>,
+    anthropic_beta: Option<Vec<String>>,
+    keep_partial: Option<bool>,
 ) -> Result<(), String> {
+    // May be left out when the payload's model is an alias
+    let provider = provider.unwrap_or_default();
     info!("Received stream request for provider: {}", provider);
 
     let body_json: Value = match serde_json::from_str(&payload) {
@@ -19,13 +56,988 @@ pub async fn stream_api_request(
         }
     };
 
-    let provider_impl = match get_provider(&provider) {
-        Ok(p) => p,
-        Err(e) => return Err(e.to_string()),
+    run_stream(
+        target_sink(window, window_labels, metadata)?,
+        &proxy_state,
+        &provider,
+        body_json,
+        extra_headers,
+        stream_id,
+        user_id,
+        auto_trim.unwrap_or(false),
+        expected_role,
+        max_messages,
+        anthropic_beta.unwrap_or_default(),
+        keep_partial.unwrap_or(false),
+    )
+    .await
+}
+
+/// Like [`stream_api_request`], but takes the payload as JSON straight from the IPC layer,
+/// skipping the second parse of a string payload; `benches/payload.rs` measures the
+/// difference for large requests
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_api_request_json(
+    window: Window,
+    proxy_state: State<'_, ProxyState>,
+    provider: Option<String>,
+    payload: Value,
+    extra_headers: Option<HashMap<String, String>>,
+    stream_id: Option<String>,
+    user_id: Option<String>,
+    metadata: Option<Value>,
+    auto_trim: Option<bool>,
+    window_labels: Option<Vec<String>>,
+    expected_role: Option<String>,
+    max_messages: Option<usize>,
+    anthropic_beta: Option<Vec<String>>,
+    keep_partial: Option<bool>,
+) -> Result<(), String> {
+    // May be left out when the payload's model is an alias
+    let provider = provider.unwrap_or_default();
+    info!("Received JSON stream request for provider: {}", provider);
+
+    if !payload.is_object() {
+        return Err("Payload must be a JSON object".to_string());
+    }
+
+    run_stream(
+        target_sink(window, window_labels, metadata)?,
+        &proxy_state,
+        &provider,
+        payload,
+        extra_headers,
+        stream_id,
+        user_id,
+        auto_trim.unwrap_or(false),
+        expected_role,
+        max_messages,
+        anthropic_beta.unwrap_or_default(),
+        keep_partial.unwrap_or(false),
+    )
+    .await
+}
+
+/// Like [`stream_api_request_json`], but sends typed [`ChannelEvent`]s through `channel`
+/// instead of emitting window events, and returns once the stream finishes. Cancel it
+/// with `cancel_stream` and the stream id.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_api_request_channel(
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    payload: Value,
+    channel: Channel<ChannelEvent>,
+    extra_headers: Option<HashMap<String, String>>,
+    stream_id: Option<String>,
+    user_id: Option<String>,
+    metadata: Option<Value>,
+    auto_trim: Option<bool>,
+    expected_role: Option<String>,
+    max_messages: Option<usize>,
+    anthropic_beta: Option<Vec<String>>,
+    keep_partial: Option<bool>,
+) -> Result<(), String> {
+    info!("Received channel stream request for provider: {}", provider);
+
+    if !payload.is_object() {
+        return Err("Payload must be a JSON object".to_string());
+    }
+
+    run_stream(
+        Box::new(channel_sink(channel, metadata)?),
+        &proxy_state,
+        &provider,
+        payload,
+        extra_headers,
+        stream_id,
+        user_id,
+        auto_trim.unwrap_or(false),
+        expected_role,
+        max_messages,
+        anthropic_beta.unwrap_or_default(),
+        keep_partial.unwrap_or(false),
+    )
+    .await
+}
+
+/// Run independent non-streaming completions, at most `concurrency` at a time, and
+/// return their results in the order of `payloads`. Each request still waits for a
+/// slot under the provider's stream limit, so a large batch queues rather than
+/// flooding the provider.
+#[tauri::command]
+pub async fn complete_batch(
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    payloads: Vec<Value>,
+    concurrency: usize,
+) -> Result<Vec<Result<CompletionResult, String>>, String> {
+    if concurrency == 0 {
+        return Err("concurrency must be greater than zero".to_string());
+    }
+    info!(
+        "Running batch of {} {} completions, {} at a time",
+        payloads.len(),
+        provider,
+        concurrency
+    );
+
+    let proxy_state = &*proxy_state;
+    let provider = provider.as_str();
+    let results = stream::iter(payloads)
+        .map(|payload| async move {
+            if !payload.is_object() {
+                return Err("Payload must be a JSON object".to_string());
+            }
+            completion::complete(proxy_state, provider, payload, proxy_state.stream_options())
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .buffered(concurrency)
+        .collect()
+        .await;
+    Ok(results)
+}
+
+/// Number of messages in a payload's `messages` array, to check a history against a
+/// `max_messages` limit before sending it
+#[tauri::command]
+pub fn count_messages(payload: Value) -> Result<usize, String> {
+    trim::count_messages(&payload).ok_or_else(|| "Payload has no messages array".to_string())
+}
+
+/// Build the sink for a stream: the calling window, or every window in `window_labels`.
+/// Labels that don't match a window are skipped with a warning.
+fn target_sink(
+    window: Window,
+    window_labels: Option<Vec<String>>,
+    metadata: Option<Value>,
+) -> Result<Box<dyn EventSink>, String> {
+    let Some(labels) = window_labels else {
+        return Ok(Box::new(metadata_sink(window, metadata)?));
+    };
+
+    let mut sinks: Vec<Box<dyn EventSink>> = Vec::new();
+    for label in &labels {
+        if window.get_webview_window(label).is_none() {
+            warn!("No window labelled {}, not streaming to it", label);
+            continue;
+        }
+        let sink = metadata_sink(window.clone(), metadata.clone())?.with_target(label.clone());
+        sinks.push(Box::new(sink));
+    }
+    if sinks.is_empty() {
+        return Err(format!("None of the windows {:?} exist", labels));
+    }
+    Ok(Box::new(FanoutSink::new(sinks)))
+}
+
+/// Largest serialized request metadata accepted, since it is repeated on every event
+const MAX_METADATA_BYTES: usize = 4096;
+
+/// Build the window sink, rejecting metadata too large to echo on every event
+fn metadata_sink(window: Window, metadata: Option<Value>) -> Result<WindowSink, String> {
+    check_metadata(metadata.as_ref())?;
+    Ok(WindowSink::new(window).with_metadata(metadata))
+}
+
+/// Build the channel sink, rejecting metadata too large to echo on every event
+fn channel_sink(
+    channel: Channel<ChannelEvent>,
+    metadata: Option<Value>,
+) -> Result<ChannelSink, String> {
+    check_metadata(metadata.as_ref())?;
+    Ok(ChannelSink::new(channel).with_metadata(metadata))
+}
+
+/// Reject metadata too large to echo on every event
+fn check_metadata(metadata: Option<&Value>) -> Result<(), String> {
+    let Some(metadata) = metadata else {
+        return Ok(());
+    };
+    let size = serde_json::to_vec(metadata)
+        .map_err(|e| format!("Failed to serialize metadata: {}", e))?
+        .len();
+    if size > MAX_METADATA_BYTES {
+        return Err(format!(
+            "Metadata is {} bytes, exceeding the {} byte limit",
+            size, MAX_METADATA_BYTES
+        ));
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_stream(
+    sink: Box<dyn EventSink>,
+    proxy_state: &ProxyState,
+    provider: &str,
+    body: Value,
+    extra_headers: Option<HashMap<String, String>>,
+    stream_id: Option<String>,
+    user_id: Option<String>,
+    auto_trim: bool,
+    expected_role: Option<String>,
+    max_messages: Option<usize>,
+    anthropic_beta: Vec<String>,
+    keep_partial: bool,
+) -> Result<(), String> {
+    let options = command_options(
+        proxy_state,
+        extra_headers,
+        stream_id,
+        user_id,
+        auto_trim,
+        expected_role,
+        max_messages,
+        anthropic_beta,
+        keep_partial,
+    )?;
+    stream_with_state(proxy_state, provider, body, options, sink.as_ref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Stream options for the streaming commands, which all take the same options
+#[allow(clippy::too_many_arguments)]
+fn command_options(
+    proxy_state: &ProxyState,
+    extra_headers: Option<HashMap<String, String>>,
+    stream_id: Option<String>,
+    user_id: Option<String>,
+    auto_trim: bool,
+    expected_role: Option<String>,
+    max_messages: Option<usize>,
+    anthropic_beta: Vec<String>,
+    keep_partial: bool,
+) -> Result<StreamOptions, String> {
+    Ok(StreamOptions {
+        auto_trim,
+        expected_role,
+        max_messages,
+        anthropic_beta,
+        keep_partial,
+        ..request_options(proxy_state, extra_headers, stream_id, user_id)?
+    })
+}
+
+/// Stream options for a request from the frontend
+fn request_options(
+    proxy_state: &ProxyState,
+    extra_headers: Option<HashMap<String, String>>,
+    stream_id: Option<String>,
+    user_id: Option<String>,
+) -> Result<StreamOptions, String> {
+    let extra_headers = match extra_headers {
+        Some(headers) => build_extra_headers(&headers).map_err(|e| e.to_string())?,
+        None => Default::default(),
+    };
+
+    Ok(StreamOptions {
+        extra_headers,
+        stream_id,
+        user_id,
+        ..proxy_state.stream_options()
+    })
+}
+
+/// Like [`stream_api_request_json`], but delivers typed [`ChannelEvent`]s over `on_event`
+/// and returns at once with the id of a resource owning the stream.
+///
+/// Streams can be cancelled two ways: `cancel_stream` with the stream id, or closing
+/// the returned resource, which needs no id and is released with the window's
+/// resources. Both may be used on the same stream; the first to act wins and the
+/// other becomes a no-op. A dropped channel also aborts the stream. The resource is
+/// released once the stream ends.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn stream_api_request_with_channel<R: Runtime>(
+    window: Window<R>,
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    payload: Value,
+    on_event: Channel<ChannelEvent>,
+    extra_headers: Option<HashMap<String, String>>,
+    stream_id: Option<String>,
+    user_id: Option<String>,
+    metadata: Option<Value>,
+    auto_trim: Option<bool>,
+    expected_role: Option<String>,
+    max_messages: Option<usize>,
+    anthropic_beta: Option<Vec<String>>,
+    keep_partial: Option<bool>,
+) -> Result<ResourceId, String> {
+    info!("Received channel stream request for provider: {}", provider);
+
+    if !payload.is_object() {
+        return Err("Payload must be a JSON object".to_string());
+    }
+
+    let sink = channel_sink(on_event, metadata)?;
+    let options = command_options(
+        &proxy_state,
+        extra_headers,
+        stream_id,
+        user_id,
+        auto_trim.unwrap_or(false),
+        expected_role,
+        max_messages,
+        anthropic_beta.unwrap_or_default(),
+        keep_partial.unwrap_or(false),
+    )?;
+    let handle = StreamHandle::default();
+    let cancel = handle.cancel_token();
+    let rid = window.resources_table().add(handle);
+    let window = window.clone();
+    tauri::async_runtime::spawn(async move {
+        let proxy_state = window.state::<ProxyState>();
+        let streamed = cancel
+            .run_until_cancelled(stream_with_state(
+                &proxy_state,
+                &provider,
+                payload,
+                options,
+                &sink,
+            ))
+            .await;
+        let result = match streamed {
+            Some(result) => result,
+            None => {
+                info!("Channel stream for {} cancelled by its handle", provider);
+                emit_end(&sink, Some(FinishReason::Other("cancelled".to_string())))
+            }
+        };
+        if let Err(e) = result {
+            warn!("Channel stream for {} failed: {}", provider, e);
+        }
+        // Already gone if the frontend closed it to cancel the stream
+        let _ = window.resources_table().close(rid);
+    });
+
+    Ok(rid)
+}
+
+/// Stream from each provider in order with its matching payload, falling back to the next
+/// only if one fails before streaming anything. An `ai-stream-fallback` event names the
+/// provider that served the request.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_with_fallback(
+    window: Window,
+    proxy_state: State<'_, ProxyState>,
+    providers: Vec<String>,
+    payloads: Vec<Value>,
+    extra_headers: Option<HashMap<String, String>>,
+    stream_id: Option<String>,
+    user_id: Option<String>,
+    metadata: Option<Value>,
+) -> Result<(), String> {
+    info!(
+        "Received fallback stream request for providers: {:?}",
+        providers
+    );
+
+    if providers.is_empty() {
+        return Err("At least one provider is required".to_string());
+    }
+    if providers.len() != payloads.len() {
+        return Err(format!(
+            "Got {} providers but {} payloads",
+            providers.len(),
+            payloads.len()
+        ));
+    }
+    if !payloads.iter().all(Value::is_object) {
+        return Err("Every payload must be a JSON object".to_string());
+    }
+
+    let sink = metadata_sink(window, metadata)?;
+    let options = request_options(&proxy_state, extra_headers, stream_id, user_id)?;
+    completion::stream_with_fallback(
+        &proxy_state,
+        providers.into_iter().zip(payloads).collect(),
+        options,
+        &sink,
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Models the provider can serve, cached for an hour unless `refresh` is set
+#[tauri::command]
+pub async fn list_models(
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    refresh: Option<bool>,
+) -> Result<Vec<ModelInfo>, String> {
+    // Overrides set after the list was cached still apply
+    let with_windows = |mut models: Vec<ModelInfo>| {
+        for model in &mut models {
+            model.context_window = proxy_state.context_windows.lookup(&model.id);
+        }
+        models
+    };
+    if let Some(models) = static_models(&provider) {
+        return Ok(with_windows(models));
+    }
+    if !refresh.unwrap_or(false) {
+        if let Some(models) = proxy_state.models.get(&provider) {
+            return Ok(with_windows(models));
+        }
+    }
+
+    let api_key = match proxy_state.keys.candidates(&provider).into_iter().next() {
+        Some(key) => key,
+        None => load_api_key(&provider).map_err(|e| e.to_string())?,
+    };
+    let models = fetch_models(&provider, &api_key)
+        .await
+        .map_err(|e| e.to_string())?;
+    proxy_state.models.insert(&provider, models.clone());
+    Ok(with_windows(models))
+}
+
+/// Context window of a model in tokens: a user override, the built-in table, or else
+/// what the provider's models endpoint reports, cached after the first ask. `None` when
+/// no source knows it.
+#[tauri::command]
+pub async fn get_context_window(
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    model: String,
+) -> Result<Option<u64>, String> {
+    if let Some(size) = proxy_state.context_windows.lookup(&model) {
+        return Ok(Some(size));
+    }
+    if let Some(size) = proxy_state.context_windows.probed(&model) {
+        return Ok(size);
+    }
+    // Providers with a curated list have no endpoint to ask
+    if static_models(&provider).is_some() {
+        return Ok(None);
+    }
+
+    let api_key = match proxy_state.keys.candidates(&provider).into_iter().next() {
+        Some(key) => key,
+        None => load_api_key(&provider).map_err(|e| e.to_string())?,
     };
+    let size = fetch_context_window(&provider, &api_key, &model)
+        .await
+        .map_err(|e| e.to_string())?;
+    proxy_state.context_windows.insert_probed(&model, size);
+    Ok(size)
+}
+
+/// Set the context window of a model, for models the built-in table doesn't know or
+/// gets wrong; `None` removes the override. Used by auto-trim and model listings.
+#[tauri::command]
+pub fn set_context_window(
+    proxy_state: State<'_, ProxyState>,
+    model: String,
+    size: Option<u64>,
+) -> Result<(), String> {
+    if size == Some(0) {
+        return Err("Context window must be greater than zero".to_string());
+    }
+    match size {
+        Some(size) => info!("Context window for {} set to {}", model, size),
+        None => info!("Context window override for {} removed", model),
+    }
+    proxy_state.context_windows.set_override(&model, size);
+    Ok(())
+}
+
+/// Totals accumulated across all streams, including previous runs
+#[tauri::command]
+pub fn get_metrics(proxy_state: State<'_, ProxyState>) -> MetricsSnapshot {
+    proxy_state.metrics.snapshot()
+}
+
+#[tauri::command]
+pub fn reset_metrics(proxy_state: State<'_, ProxyState>) {
+    info!("Resetting metrics");
+    proxy_state.metrics.reset();
+}
+
+/// Token usage accumulated for a user across their tagged streams
+#[tauri::command]
+pub fn get_user_usage(proxy_state: State<'_, ProxyState>, user_id: String) -> UserUsage {
+    proxy_state.usage.get(&user_id)
+}
+
+/// Streams that are queued or in flight
+#[tauri::command]
+pub fn list_active_streams(proxy_state: State<'_, ProxyState>) -> Vec<ActiveStreamInfo> {
+    proxy_state.active.list()
+}
+
+/// Cancel an active stream; `cancelled` is false if it already finished.
+///
+/// The upstream connection is closed immediately, so no further tokens are generated.
+/// Streams started with `keep_partial` also return the text generated so far, so the
+/// UI can keep the partial message.
+#[tauri::command]
+pub fn cancel_stream(proxy_state: State<'_, ProxyState>, stream_id: String) -> CancelledStream {
+    proxy_state.active.cancel(&stream_id)
+}
+
+/// Re-emit the recent events of an active stream to the calling window, e.g. after the
+/// UI reloaded mid-stream. Returns the number of events replayed.
+#[tauri::command]
+pub fn replay_stream(
+    window: Window,
+    proxy_state: State<'_, ProxyState>,
+    stream_id: String,
+) -> Result<usize, String> {
+    let events = proxy_state
+        .active
+        .replay(&stream_id)
+        .ok_or_else(|| format!("Stream {} is not active", stream_id))?;
+    info!("Replaying {} events of stream {}", events.len(), stream_id);
+
+    let sink = WindowSink::new(window);
+    let count = events.len();
+    for event in events {
+        sink.emit(event).map_err(|e| e.to_string())?;
+    }
+    Ok(count)
+}
+
+/// Move an active stream to the window labelled `new_window_label`, e.g. when a reload
+/// recreated the window it was streaming to. The new window first receives the events
+/// buffered so far, then the rest of the stream. Request metadata isn't carried over.
+/// Returns the number of events replayed.
+#[tauri::command]
+pub fn reattach_stream(
+    window: Window,
+    proxy_state: State<'_, ProxyState>,
+    stream_id: String,
+    new_window_label: String,
+) -> Result<usize, String> {
+    if window.get_webview_window(&new_window_label).is_none() {
+        return Err(format!("No window labelled {}", new_window_label));
+    }
+    let sink = WindowSink::new(window).with_target(new_window_label.clone());
+    let replayed = proxy_state
+        .active
+        .reattach(&stream_id, Box::new(sink))
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Stream {} is not active", stream_id))?;
+    info!(
+        "Stream {} reattached to {} after replaying {} events",
+        stream_id, new_window_label, replayed
+    );
+    Ok(replayed)
+}
+
+/// Hold back a stream's events without cancelling it, returning false if it isn't active
+#[tauri::command]
+pub fn pause_stream(proxy_state: State<'_, ProxyState>, stream_id: String) -> bool {
+    proxy_state.active.pause(&stream_id)
+}
 
-    match provider_impl.stream(window, body_json).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e.to_string()),
+/// Deliver a paused stream's held-back events and continue, returning false if it
+/// isn't active
+#[tauri::command]
+pub fn resume_stream(proxy_state: State<'_, ProxyState>, stream_id: String) -> bool {
+    proxy_state.active.resume(&stream_id)
+}
+
+#[tauri::command]
+pub fn set_stream_limit(
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    max_concurrent: usize,
+    max_queue_depth: Option<usize>,
+) -> Result<(), String> {
+    proxy_state
+        .limiter
+        .set_limit(
+            &provider,
+            max_concurrent,
+            max_queue_depth.unwrap_or(DEFAULT_MAX_QUEUE_DEPTH),
+        )
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_proxy_logging(proxy_state: State<'_, ProxyState>, enabled: bool) {
+    info!(
+        "Proxy debug logging {}",
+        if enabled { "enabled" } else { "disabled" }
+    );
+    proxy_state.set_proxy_logging(enabled);
+}
+
+/// Set the `max_tokens` sent to a provider when a request omits it; `None` removes the
+/// default. Anthropic requests without either fail locally.
+#[tauri::command]
+pub fn set_default_max_tokens(
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    max_tokens: Option<u64>,
+) -> Result<(), String> {
+    if key_var(&provider).is_none() {
+        return Err(format!("Unsupported provider: {}", provider));
+    }
+    if max_tokens == Some(0) {
+        return Err("max_tokens must be greater than zero".to_string());
+    }
+    match max_tokens {
+        Some(max_tokens) => info!("Default max_tokens for {} set to {}", provider, max_tokens),
+        None => info!("Default max_tokens for {} removed", provider),
+    }
+    proxy_state.set_default_max_tokens(&provider, max_tokens);
+    Ok(())
+}
+
+/// Scrub anything shaped like an API key from requests before they are sent
+#[tauri::command]
+pub fn set_request_redaction(proxy_state: State<'_, ProxyState>, enabled: bool) {
+    info!(
+        "Request redaction {}",
+        if enabled { "enabled" } else { "disabled" }
+    );
+    if enabled {
+        proxy_state
+            .middlewares
+            .register(REDACTION_MIDDLEWARE, Arc::new(RedactSecrets));
+    } else {
+        proxy_state.middlewares.remove(REDACTION_MIDDLEWARE);
+    }
+}
+
+/// Replace the system prompt of every request with `prompt`; `None` stops overriding
+#[tauri::command]
+pub fn set_system_prompt_override(proxy_state: State<'_, ProxyState>, prompt: Option<String>) {
+    match prompt {
+        Some(prompt) => {
+            info!("System prompt overridden ({} bytes)", prompt.len());
+            proxy_state.middlewares.register(
+                SYSTEM_PROMPT_MIDDLEWARE,
+                Arc::new(SystemPromptOverride::new(prompt)),
+            );
+        }
+        None => {
+            info!("System prompt override removed");
+            proxy_state.middlewares.remove(SYSTEM_PROMPT_MIDDLEWARE);
+        }
+    }
+}
+
+/// Names of the active middlewares, in the order they run
+#[tauri::command]
+pub fn list_middlewares(proxy_state: State<'_, ProxyState>) -> Vec<String> {
+    proxy_state.middlewares.names()
+}
+
+/// Point a model alias, such as `fast`, at a provider's model. Requests naming the
+/// alias as their `model` go to that provider with the model id swapped in, whatever
+/// provider the caller passed.
+#[tauri::command]
+pub fn set_model_alias(
+    proxy_state: State<'_, ProxyState>,
+    alias: String,
+    provider: String,
+    model: String,
+) -> Result<(), String> {
+    if provider_with_key(&provider, String::new()).is_err() {
+        return Err(format!("Unsupported provider: {}", provider));
+    }
+    if alias.is_empty() || model.is_empty() {
+        return Err("Alias and model must not be empty".to_string());
+    }
+    info!("Model alias {} set to {} on {}", alias, model, provider);
+    proxy_state
+        .aliases
+        .set(&alias, ModelAlias { provider, model });
+    Ok(())
+}
+
+/// Remove a model alias, returning whether it existed
+#[tauri::command]
+pub fn remove_model_alias(proxy_state: State<'_, ProxyState>, alias: String) -> bool {
+    info!("Removing model alias {}", alias);
+    proxy_state.aliases.remove(&alias)
+}
+
+/// Every model alias with the provider and model it stands for
+#[tauri::command]
+pub fn list_model_aliases(proxy_state: State<'_, ProxyState>) -> Vec<(String, ModelAlias)> {
+    proxy_state.aliases.list()
+}
+
+/// Set how long to wait for a provider's response headers before failing the request
+#[tauri::command]
+pub fn set_request_timeout(
+    proxy_state: State<'_, ProxyState>,
+    timeout_secs: u64,
+) -> Result<(), String> {
+    if timeout_secs == 0 {
+        return Err("timeout_secs must be greater than zero".to_string());
+    }
+    info!("Upstream request timeout set to {}s", timeout_secs);
+    proxy_state.set_request_timeout(Duration::from_secs(timeout_secs));
+    Ok(())
+}
+
+/// Serve proxy streaming over WebSocket for remote frontends, replacing any server
+/// already running. Clients authenticate with `token`; see [`ws`] for the frame
+/// protocol. Binds to `host`, `127.0.0.1` unless set, and returns the bound address.
+#[cfg(feature = "ws-server")]
+#[tauri::command]
+pub async fn start_ws_server<R: Runtime>(
+    app: AppHandle<R>,
+    proxy_state: State<'_, ProxyState>,
+    port: u16,
+    token: String,
+    host: Option<String>,
+) -> Result<String, String> {
+    proxy_state.replace_ws_server(None);
+    let host = host.unwrap_or_else(|| ws::DEFAULT_WS_HOST.to_string());
+    let server = ws::start(app, &host, port, token)
+        .await
+        .map_err(|e| e.to_string())?;
+    let addr = server.local_addr().to_string();
+    proxy_state.replace_ws_server(Some(server));
+    Ok(addr)
+}
+
+#[cfg(not(feature = "ws-server"))]
+#[tauri::command]
+pub fn start_ws_server() -> Result<String, String> {
+    Err(WS_SERVER_DISABLED.to_string())
+}
+
+/// Stop the WebSocket server, cancelling its streams. Returns whether one was running.
+#[cfg(feature = "ws-server")]
+#[tauri::command]
+pub fn stop_ws_server(proxy_state: State<'_, ProxyState>) -> Result<bool, String> {
+    Ok(proxy_state.replace_ws_server(None).is_some())
+}
+
+#[cfg(not(feature = "ws-server"))]
+#[tauri::command]
+pub fn stop_ws_server() -> Result<bool, String> {
+    Err(WS_SERVER_DISABLED.to_string())
+}
+
+#[cfg(not(feature = "ws-server"))]
+const WS_SERVER_DISABLED: &str =
+    "WebSocket server support is not enabled in this build (feature `ws-server`)";
+
+/// Set the `anthropic-version` header sent to Anthropic; `None` restores the default
+/// (`2023-06-01`)
+#[tauri::command]
+pub fn set_anthropic_version(
+    proxy_state: State<'_, ProxyState>,
+    version: Option<String>,
+) -> Result<(), String> {
+    if let Some(version) = &version {
+        if version.is_empty() || !version.chars().all(|c| c.is_ascii_graphic()) {
+            return Err(format!("Invalid anthropic-version: {:?}", version));
+        }
+    }
+    info!(
+        "anthropic-version set to {}",
+        version.as_deref().unwrap_or("the default")
+    );
+    proxy_state.set_anthropic_version(version);
+    Ok(())
+}
+
+/// Set headers sent with every request to a provider, such as `OpenAI-Organization`
+/// or `OpenAI-Project`. Per-request extra headers take precedence; credential and
+/// framing headers are rejected. An empty map clears them.
+#[tauri::command]
+pub fn set_provider_headers(
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    headers: HashMap<String, String>,
+) -> Result<(), String> {
+    if key_var(&provider).is_none() {
+        return Err(format!("Unsupported provider: {}", provider));
+    }
+    let headers = build_extra_headers(&headers).map_err(|e| e.to_string())?;
+    info!("Setting {} default headers for {}", headers.len(), provider);
+    proxy_state.set_provider_headers(&provider, headers);
+    Ok(())
+}
+
+/// Turn the `Idempotency-Key` header on or off for a provider. It is on by default:
+/// each request gets a fresh key that its retries reuse, so the provider can discard a
+/// duplicate when a retry races a slow first attempt.
+#[tauri::command]
+pub fn set_idempotency_keys(
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    enabled: bool,
+) -> Result<(), String> {
+    if key_var(&provider).is_none() {
+        return Err(format!("Unsupported provider: {}", provider));
+    }
+    info!(
+        "Idempotency keys {} for {}",
+        if enabled { "enabled" } else { "disabled" },
+        provider
+    );
+    proxy_state.set_idempotency_keys(&provider, enabled);
+    Ok(())
+}
+
+/// Cap the size of a single server-sent event; larger events abort the stream
+#[tauri::command]
+pub fn set_max_event_size(
+    proxy_state: State<'_, ProxyState>,
+    max_bytes: usize,
+) -> Result<(), String> {
+    if max_bytes == 0 {
+        return Err("max_bytes must be greater than zero".to_string());
+    }
+    info!("Maximum server-sent event size set to {} bytes", max_bytes);
+    proxy_state.set_max_event_bytes(max_bytes);
+    Ok(())
+}
+
+/// Cap the bytes read from a streamed response; a stream over the cap is cut off and
+/// its connection closed, so the provider stops generating. `None` removes the cap.
+#[tauri::command]
+pub fn set_max_output_size(
+    proxy_state: State<'_, ProxyState>,
+    max_bytes: Option<usize>,
+) -> Result<(), String> {
+    match max_bytes {
+        Some(0) => return Err("max_bytes must be greater than zero".to_string()),
+        Some(max_bytes) => info!("Maximum stream output size set to {} bytes", max_bytes),
+        None => info!("Stream output size limit removed"),
+    }
+    proxy_state.set_max_output_bytes(max_bytes);
+    Ok(())
+}
+
+/// Choose which upstream status codes are retried and how
+#[tauri::command]
+pub fn set_retry_policy(
+    proxy_state: State<'_, ProxyState>,
+    codes: Vec<u16>,
+    max_attempts: u32,
+    base_delay_ms: u64,
+) -> Result<(), String> {
+    let policy = RetryPolicy::new(codes, max_attempts, base_delay_ms).map_err(|e| e.to_string())?;
+    info!("Retry policy set to {:?}", policy);
+    proxy_state.set_retry_policy(policy);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_circuit_breaker(
+    proxy_state: State<'_, ProxyState>,
+    failure_threshold: u32,
+    cooldown_secs: u64,
+) -> Result<(), String> {
+    proxy_state
+        .circuits
+        .configure(failure_threshold, Duration::from_secs(cooldown_secs))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn reload_env() -> Result<Vec<String>, String> {
+    reload_env_file().map_err(|e| e.to_string())
+}
+
+/// Read a provider's API key from a differently named environment variable, such as
+/// `CLAUDE_KEY` instead of `ANTHROPIC_API_KEY`. The OS keychain still takes precedence.
+#[tauri::command]
+pub fn set_key_env_var(provider: String, var_name: String) -> Result<(), String> {
+    set_key_var(&provider, &var_name).map_err(|e| e.to_string())
+}
+
+/// Add an API key to the provider's pool, returning the pool size
+#[tauri::command]
+pub fn add_api_key(
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    key: String,
+) -> Result<usize, String> {
+    proxy_state
+        .keys
+        .add(&provider, key)
+        .map_err(|e| e.to_string())
+}
+
+/// Remove an API key from the provider's pool, returning whether it was present
+#[tauri::command]
+pub fn remove_api_key(
+    proxy_state: State<'_, ProxyState>,
+    provider: String,
+    key: String,
+) -> Result<bool, String> {
+    proxy_state
+        .keys
+        .remove(&provider, &key)
+        .map_err(|e| e.to_string())
+}
+
+/// Store a provider's API key in the OS keychain, where it takes precedence over `.env`
+#[tauri::command]
+pub fn store_api_key_secure(provider: String, key: String) -> Result<(), String> {
+    keychain::store_key(&provider, &key).map_err(|e| e.to_string())
+}
+
+/// Remove a provider's API key from the OS keychain, returning whether one was stored
+#[tauri::command]
+pub fn delete_api_key_secure(provider: String) -> Result<bool, String> {
+    keychain::delete_key(&provider).map_err(|e| e.to_string())
+}
+
+/// Version of the stream event schema this backend speaks
+#[tauri::command]
+pub fn get_protocol_version() -> u32 {
+    STREAM_PROTOCOL_VERSION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn releases_the_stream_resource_when_the_stream_ends() {
+        let app = tauri::test::mock_app();
+        app.manage(ProxyState::default());
+        let webview = tauri::WebviewWindowBuilder::new(&app, "main", Default::default())
+            .build()
+            .unwrap();
+        let window = webview.as_ref().window();
+
+        let rid = stream_api_request_with_channel(
+            window.clone(),
+            app.state::<ProxyState>(),
+            "mock".to_string(),
+            json!({"model": "mock", "text": "Hello there", "delay_ms": 0}),
+            Channel::new(|_| Ok(())),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let mut released = false;
+        for _ in 0..100 {
+            if window.resources_table().get::<StreamHandle>(rid).is_err() {
+                released = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(released, "stream resource {} was never released", rid);
     }
 }
@@ -0,0 +1,112 @@
+use crate::services::mcp::ServiceManager;
+use crate::services::proxy::{key_env_var, CircuitState, ProxyState, PROVIDER_KEY_VARS};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tauri::State;
+
+type ServiceState<'a> = State<'a, Arc<Mutex<ServiceManager>>>;
+
+/// When the app started, for reporting uptime
+pub struct StartTime(Instant);
+
+impl Default for StartTime {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderStatus {
+    pub id: String,
+    /// Whether a key is pooled or set in the environment; `None` if the key pool was
+    /// busy and no environment key was found
+    pub key_present: Option<bool>,
+    /// `None` if the circuit breakers were busy
+    pub circuit_state: Option<CircuitState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub alive: bool,
+    pub pid: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AppStatus {
+    pub providers: Vec<ProviderStatus>,
+    /// Running services, then services that have exited; `None` if the service manager
+    /// was busy
+    pub services: Option<Vec<ServiceStatus>>,
+    /// `None` if the stream registry was busy
+    pub active_streams: Option<usize>,
+    pub uptime_secs: u64,
+}
+
+/// One-call overview of providers, services and streams for monitoring.
+///
+/// Makes no network calls and never waits on a lock: anything that is locked elsewhere
+/// is reported as `null`. Keys stored only in the OS keychain aren't checked, since
+/// reading the keychain can block on an unlock prompt.
+#[tauri::command]
+pub fn get_status(
+    service_state: ServiceState<'_>,
+    proxy_state: State<'_, ProxyState>,
+    start_time: State<'_, StartTime>,
+) -> AppStatus {
+    let providers = PROVIDER_KEY_VARS
+        .iter()
+        .map(|(provider, _)| {
+            let in_env = key_env_var(provider)
+                .and_then(|var| env::var(var).ok())
+                .is_some_and(|key| !key.is_empty());
+            let key_present = if in_env {
+                Some(true)
+            } else {
+                proxy_state
+                    .keys
+                    .try_key_count(provider)
+                    .map(|count| count > 0)
+            };
+            ProviderStatus {
+                id: provider.to_string(),
+                key_present,
+                circuit_state: proxy_state.circuits.try_state(provider),
+            }
+        })
+        .collect();
+
+    let services = service_state.try_lock().ok().map(|state| {
+        let mut services: Vec<ServiceStatus> = state
+            .list_services()
+            .into_iter()
+            .map(|name| ServiceStatus {
+                pid: state.pid(&name),
+                alive: true,
+                name,
+            })
+            .collect();
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut exited: Vec<ServiceStatus> = state
+            .exits()
+            .filter(|exit| state.get_config(&exit.service).is_none())
+            .map(|exit| ServiceStatus {
+                name: exit.service.clone(),
+                alive: false,
+                pid: None,
+            })
+            .collect();
+        exited.sort_by(|a, b| a.name.cmp(&b.name));
+        services.extend(exited);
+        services
+    });
+
+    AppStatus {
+        providers,
+        services,
+        active_streams: proxy_state.active.try_count(),
+        uptime_secs: start_time.0.elapsed().as_secs(),
+    }
+}
@@ -0,0 +1,519 @@
+//! Streaming completions decoupled from the Tauri shell.
+//!
+//! The provider parsing delivers typed [`StreamEvent`]s to an [`EventSink`], so the
+//! same logic can back the desktop commands, a CLI, or a server.
+
+use crate::services::proxy::alias::ModelAliasSink;
+use crate::services::proxy::events::{AttemptSink, RoleGuardSink, StreamIdSink};
+use crate::services::proxy::idempotency::attach_idempotency_key;
+use crate::services::proxy::metrics::MetricsSink;
+use crate::services::proxy::middleware::MiddlewareSink;
+use crate::services::proxy::normalize::{apply_max_tokens, normalize_token_limit};
+use crate::services::proxy::partial::PartialTextSink;
+use crate::services::proxy::pause::PauseSink;
+use crate::services::proxy::redirect::RedirectSink;
+use crate::services::proxy::replay::ReplaySink;
+use crate::services::proxy::stats::StatsSink;
+use crate::services::proxy::trim::{count_messages, trim_to_context, trim_to_count};
+use crate::services::proxy::usage::{tag_user, UserUsageSink};
+use crate::services::proxy::{
+    emit_circuit_state, emit_end, emit_fallback, emit_queued, emit_trimmed, emit_warning,
+    get_provider,
+};
+use crate::services::proxy::{load_api_key, provider_with_key, validate_payload};
+use crate::services::proxy::{CircuitState, ProxyResult, ProxyState, RetryPolicy, StreamFallback};
+use crate::services::proxy::{StreamUsage, ToolCall};
+use futures_util::future::{select, Either};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::pin::pin;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+pub use crate::services::proxy::{
+    CallbackSink, EventSink, FinishReason, ProxyError, StreamEvent, StreamOptions,
+};
+
+/// Longest delay we are willing to wait before a retry; beyond this the error is
+/// surfaced instead
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(60);
+
+/// Stream a completion from `provider`, delivering each event to `on_event`.
+///
+/// This runs without the shared limiter and circuit breaker; use
+/// [`stream_with_state`] to share them with other streams.
+pub async fn stream_completion<F>(provider: &str, body: Value, on_event: F) -> ProxyResult<()>
+where
+    F: FnMut(StreamEvent) + Send,
+{
+    let sink = CallbackSink::new(on_event);
+    let provider_impl = get_provider(provider)?;
+    provider_impl
+        .stream(&sink, body, StreamOptions::default())
+        .await
+}
+
+/// Stream a completion through the shared proxy state (circuit breaker, stream limiter,
+/// active stream registry and middlewares)
+pub async fn stream_with_state(
+    state: &ProxyState,
+    provider: &str,
+    mut body: Value,
+    mut options: StreamOptions,
+    client: &dyn EventSink,
+) -> ProxyResult<()> {
+    // An alias names the provider too, overriding the one the caller passed
+    let alias = body
+        .get("model")
+        .and_then(Value::as_str)
+        .and_then(|model| Some((model.to_string(), state.aliases.resolve(model)?)));
+    let provider = match &alias {
+        Some((name, target)) => {
+            info!(
+                "Resolved model alias {} to {} on {}",
+                name, target.model, target.provider
+            );
+            body["model"] = Value::String(target.model.clone());
+            target.provider.as_str()
+        }
+        None if provider.is_empty() => {
+            return Err(ProxyError::InvalidPayload(
+                "No provider given and the model is not an alias".to_string(),
+            ))
+        }
+        None => provider,
+    };
+
+    let middlewares = state.middlewares.pipeline();
+    for middleware in &middlewares {
+        middleware.before(provider, &mut body);
+    }
+    let early_sink = MiddlewareSink::new(client, &middlewares);
+    let sink: &dyn EventSink = &early_sink;
+
+    if let Some(user_id) = &options.user_id {
+        tag_user(provider, &mut body, user_id);
+    }
+    if let Some(warning) = normalize_token_limit(provider, &mut body) {
+        emit_warning(sink, warning)?;
+    }
+    apply_max_tokens(provider, &mut body, state.default_max_tokens(provider))?;
+    validate_payload(provider, &body)?;
+    state.circuits.check(provider)?;
+    // Per-request headers override the provider's standing headers
+    let mut headers = state.provider_headers(provider);
+    headers.extend(std::mem::take(&mut options.extra_headers));
+    options.extra_headers = headers;
+    // One key for all retries of this request, so the provider can drop duplicates
+    if state.idempotency_keys(provider) {
+        attach_idempotency_key(&mut options.extra_headers);
+    }
+    let count = count_messages(&body).unwrap_or_default();
+    if let Some(max) = options.max_messages.filter(|max| count > *max) {
+        if !options.auto_trim {
+            return Err(ProxyError::InvalidPayload(format!(
+                "Payload has {} messages, over the limit of {}",
+                count, max
+            )));
+        }
+        let dropped = trim_to_count(&mut body, max);
+        emit_warning(
+            sink,
+            format!(
+                "Dropped {} of {} messages to stay within the limit of {}",
+                dropped, count, max
+            ),
+        )?;
+    }
+    if options.auto_trim {
+        if let Some(trimmed) = trim_to_context(&mut body, &state.context_windows) {
+            emit_trimmed(sink, trimmed)?;
+        }
+    }
+
+    // Pooled keys take precedence over the environment key
+    let mut keys = state.keys.candidates(provider);
+    if keys.is_empty() {
+        keys.push(load_api_key(provider)?);
+    }
+
+    let model = body.get("model").and_then(Value::as_str);
+    let registration = state
+        .active
+        .register(options.stream_id.take(), provider, model)?;
+    options.bytes_streamed = Some(registration.bytes_streamed());
+    // Middlewares see events before the redirect, so they keep seeing them after a
+    // reattach
+    let redirect_sink = RedirectSink::new(client, registration.redirect_target());
+    let middleware_sink = MiddlewareSink::new(&redirect_sink, &middlewares);
+    let sink: &dyn EventSink = &middleware_sink;
+    let user_sink = options
+        .user_id
+        .as_deref()
+        .map(|user_id| UserUsageSink::new(sink, &state.usage, user_id));
+    let sink: &dyn EventSink = match &user_sink {
+        Some(user_sink) => user_sink,
+        None => sink,
+    };
+    let metrics_sink = MetricsSink::new(sink, &state.metrics);
+    let replay_sink = ReplaySink::new(&metrics_sink, registration.replay_buffer());
+    let sink: &dyn EventSink = &replay_sink;
+    let alias_sink = alias
+        .as_ref()
+        .map(|(name, target)| ModelAliasSink::new(sink, name, &target.model));
+    let sink: &dyn EventSink = match &alias_sink {
+        Some(alias_sink) => alias_sink,
+        None => sink,
+    };
+    let sink = StreamIdSink::new(sink, registration.id());
+    let pause_sink = PauseSink::new(&sink, registration.pause_control());
+    // Text is collected ahead of the pause, so the partial text of a cancelled stream
+    // includes what a pause held back
+    let partial_sink = options
+        .keep_partial
+        .then(|| PartialTextSink::new(&pause_sink, registration.partial_text()));
+    let expected_role = options.expected_role.take();
+    let guard_sink = match &partial_sink {
+        Some(partial_sink) => RoleGuardSink::new(partial_sink, expected_role),
+        None => RoleGuardSink::new(&pause_sink, expected_role),
+    };
+
+    // Cancelling drops this future, and with it the upstream response body. An
+    // unfinished body is never returned to the connection pool, so the connection is
+    // closed at once and the provider stops generating (and billing) tokens.
+    let streamed = registration
+        .cancel_token()
+        .run_until_cancelled(async {
+            let stream = pin!(stream_registered(
+                state,
+                provider,
+                keys,
+                body,
+                options,
+                &guard_sink,
+            ));
+            let result = match select(stream, pin!(pause_sink.flush_on_resume())).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => unreachable!("flush_on_resume never returns"),
+            };
+            // Events held back by a pause are delivered once the stream is resumed
+            pause_sink.control().resumed().await;
+            pause_sink.flush()?;
+            result
+        })
+        .await;
+
+    state.metrics.record(|totals| {
+        totals.streams += 1;
+        if matches!(streamed, Some(Err(_))) {
+            totals.failed_streams += 1;
+        }
+    });
+
+    match streamed {
+        Some(result) => result,
+        None => {
+            info!(
+                "Stream {} cancelled after {} bytes, upstream connection closed",
+                registration.id(),
+                registration.bytes_streamed().load(Ordering::Relaxed)
+            );
+            // Deliver what a pause held back before ending the stream
+            pause_sink.flush()?;
+            emit_end(&sink, Some(FinishReason::Other("cancelled".to_string())))
+        }
+    }
+}
+
+/// A completion run to the end, assembled from its stream events
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CompletionResult {
+    pub text: String,
+    /// Set when the model declined the request
+    pub refusal: Option<String>,
+    pub tool_calls: Vec<ToolCall>,
+    pub usage: Option<StreamUsage>,
+    pub finish_reason: Option<FinishReason>,
+}
+
+/// Run a completion without streaming and return the whole result.
+///
+/// Goes through [`stream_with_state`], so it shares the stream limiter, circuit breaker
+/// and retries with streamed requests.
+pub async fn complete(
+    state: &ProxyState,
+    provider: &str,
+    mut body: Value,
+    options: StreamOptions,
+) -> ProxyResult<CompletionResult> {
+    if let Some(fields) = body.as_object_mut() {
+        fields.insert("stream".to_string(), Value::Bool(false));
+        // Rejected by OpenAI on non-streaming requests
+        fields.remove("stream_options");
+    }
+
+    let mut result = CompletionResult::default();
+    let mut error = None;
+    let sink = CallbackSink::new(|event| match event {
+        StreamEvent::Text { text } => result.text.push_str(&text),
+        StreamEvent::Refusal { text } => result.refusal = Some(text),
+        StreamEvent::ToolCall(call) => result.tool_calls.push(call),
+        StreamEvent::Usage(usage) => result.usage = Some(usage),
+        StreamEvent::End { finish_reason } => result.finish_reason = finish_reason,
+        StreamEvent::Error(e) => error = Some(e.message),
+        _ => {}
+    });
+    stream_with_state(state, provider, body, options, &sink).await?;
+    drop(sink);
+
+    match error {
+        Some(message) => Err(ProxyError::UnexpectedResponse(message)),
+        None => Ok(result),
+    }
+}
+
+/// Stream from each `(provider, body)` in turn, falling back to the next provider only
+/// if the previous one failed before streaming anything (connection, auth or rate-limit
+/// errors). Once content has reached the client there is no fallback.
+///
+/// Emits a fallback event naming the provider that served the request.
+pub async fn stream_with_fallback(
+    state: &ProxyState,
+    attempts: Vec<(String, Value)>,
+    options: StreamOptions,
+    sink: &dyn EventSink,
+) -> ProxyResult<()> {
+    let providers: Vec<String> = attempts.iter().map(|(name, _)| name.clone()).collect();
+    let mut result = Err(ProxyError::InvalidPayload(
+        "Fallback chain has no providers".to_string(),
+    ));
+
+    for (i, (provider, body)) in attempts.into_iter().enumerate() {
+        let attempt_sink = AttemptSink::new(sink);
+        result = stream_with_state(state, &provider, body, options.clone(), &attempt_sink).await;
+
+        let served = result.is_ok() || attempt_sink.forwarded_any();
+        let Some(next) = providers.get(i + 1).filter(|_| !served) else {
+            attempt_sink.flush()?;
+            if served {
+                emit_fallback(
+                    sink,
+                    StreamFallback {
+                        provider,
+                        failed: providers[..i].to_vec(),
+                    },
+                )?;
+            }
+            break;
+        };
+
+        let reason = result
+            .as_ref()
+            .err()
+            .map(|e| e.to_string())
+            .unwrap_or_default();
+        let message = format!(
+            "{} request failed ({}), falling back to {}",
+            provider, reason, next
+        );
+        if let Err(e) = emit_warning(sink, message) {
+            warn!("{}", e);
+        }
+    }
+
+    result
+}
+
+/// Queue for a slot and run the stream, retrying per the retry policy and tracking the
+/// circuit
+async fn stream_registered(
+    state: &ProxyState,
+    provider: &str,
+    keys: Vec<String>,
+    mut body: Value,
+    options: StreamOptions,
+    sink: &dyn EventSink,
+) -> ProxyResult<()> {
+    let _permit = state
+        .limiter
+        .acquire(provider, || {
+            if let Err(e) = emit_queued(sink, provider) {
+                warn!("{}", e);
+            }
+        })
+        .await?;
+
+    let stats_sink = StatsSink::new(sink, provider);
+    let policy = state.retry_policy();
+    let mut retries = 0;
+    let result = loop {
+        let retry = (retries < policy.retries()).then_some((&policy, retries));
+        let attempt_body = if retry.is_some() {
+            body.clone()
+        } else {
+            std::mem::take(&mut body)
+        };
+
+        let (result, retry_after) = stream_with_keys(
+            state,
+            provider,
+            keys.clone(),
+            attempt_body,
+            options.clone(),
+            &stats_sink,
+            retry,
+        )
+        .await;
+
+        let Some(delay) = retry_after else {
+            break result;
+        };
+        retries += 1;
+        let reason = result.err().map(|e| e.to_string()).unwrap_or_default();
+        let message = format!(
+            "{} request failed ({}), retrying in {:.1}s",
+            provider,
+            reason,
+            delay.as_secs_f64()
+        );
+        if let Err(e) = emit_warning(sink, message) {
+            warn!("{}", e);
+        }
+        tokio::time::sleep(delay).await;
+    };
+
+    let transitioned = match &result {
+        Ok(_) => state.circuits.record_success(provider),
+        Err(e) if e.is_provider_failure() => state.circuits.record_failure(provider),
+        Err(_) => false,
+    };
+    if transitioned {
+        let circuit_state = if result.is_ok() {
+            CircuitState::Closed
+        } else {
+            CircuitState::Open
+        };
+        if let Err(e) = emit_circuit_state(sink, provider, circuit_state) {
+            warn!("{}", e);
+        }
+    }
+
+    result
+}
+
+/// Try each key in turn, failing over to the next on a key-specific error (401/429)
+/// as long as nothing has been emitted to the client yet.
+///
+/// When `retry` holds the policy and retry number, and the last key failed in a way the
+/// policy retries, the error is withheld and the delay before retrying is returned
+/// alongside it.
+async fn stream_with_keys(
+    state: &ProxyState,
+    provider: &str,
+    keys: Vec<String>,
+    mut body: Value,
+    options: StreamOptions,
+    sink: &dyn EventSink,
+    retry: Option<(&RetryPolicy, u32)>,
+) -> (ProxyResult<()>, Option<Duration>) {
+    let total = keys.len();
+    let mut result = Ok(());
+
+    for (attempt, api_key) in keys.into_iter().enumerate() {
+        let remaining = total - attempt - 1;
+        let provider_impl = match provider_with_key(provider, api_key.clone()) {
+            Ok(provider_impl) => provider_impl,
+            Err(e) => return (Err(e), None),
+        };
+        let attempt_body = if remaining > 0 {
+            body.clone()
+        } else {
+            std::mem::take(&mut body)
+        };
+
+        let attempt_sink = AttemptSink::new(sink);
+        result = provider_impl
+            .stream(&attempt_sink, attempt_body, options.clone())
+            .await;
+
+        let key_failed = matches!(&result, Err(e) if e.is_key_failure());
+        if key_failed {
+            state.keys.record_failure(provider, &api_key);
+        } else if result.is_ok() {
+            state.keys.record_success(provider, &api_key);
+        }
+
+        if key_failed && remaining > 0 && !attempt_sink.forwarded_any() {
+            warn!(
+                "{} key {} of {} rejected, failing over to the next key",
+                provider,
+                attempt + 1,
+                total
+            );
+            continue;
+        }
+
+        if let (Err(e), Some((policy, retry))) = (&result, retry) {
+            let delay = policy.retry_delay(e, retry);
+            if let Some(delay) = delay {
+                if !attempt_sink.forwarded_any() && delay <= MAX_RETRY_WAIT {
+                    return (result, Some(delay));
+                }
+            }
+        }
+
+        if let Err(e) = attempt_sink.flush() {
+            return (Err(e), None);
+        }
+        break;
+    }
+
+    (result, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::proxy::test_support::RecordingSink;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn delivers_text_held_by_a_pause_when_cancelled() {
+        let state = ProxyState::default();
+        let sink = RecordingSink::default();
+        let body = json!({
+            "model": "mock",
+            "chunks": ["Hello", {"text": " world", "delay_ms": 50}, {"text": "!", "delay_ms": 60_000}],
+            "delay_ms": 0,
+        });
+        let options = StreamOptions {
+            stream_id: Some("paused".to_string()),
+            keep_partial: true,
+            ..Default::default()
+        };
+
+        let control = async {
+            while sink.text().is_empty() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            assert!(state.active.pause("paused"));
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert_eq!(sink.text(), "Hello");
+            state.active.cancel("paused")
+        };
+        let (streamed, cancelled) = tokio::join!(
+            stream_with_state(&state, "mock", body, options, &sink),
+            control
+        );
+        streamed.unwrap();
+
+        assert_eq!(cancelled.partial_text.as_deref(), Some("Hello world"));
+        assert_eq!(sink.text(), "Hello world");
+        assert!(matches!(
+            sink.events().last(),
+            Some(StreamEvent::End { finish_reason: Some(FinishReason::Other(reason)) })
+                if reason == "cancelled"
+        ));
+    }
+}
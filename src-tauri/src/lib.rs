@@ -1,14 +1,40 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-use log::LevelFilter;
+use log::{warn, LevelFilter};
 use std::sync::{Arc, Mutex};
-use tauri::Manager;
+use tauri::{AppHandle, Manager, RunEvent};
 
 pub mod commands;
+pub mod completion;
 pub mod services;
 
-use commands::mcp_commands::{call_tool, get_services, list_tools, start_service, stop_service};
-use commands::proxy_commands::stream_api_request;
-use services::mcp::ServiceManager;
+use commands::agent_commands::tool_then_complete;
+use commands::config_commands::{export_config, import_config};
+use commands::mcp_commands::{
+    autostart_services, call_tool, call_tool_auto, cancel_start_service, cancel_tool_call,
+    complete_argument, get_capabilities, get_compatibility, get_jsonrpc_log, get_service_info,
+    get_services, get_services_detailed, has_tool, kill_service, list_all_tools,
+    list_resource_templates, list_tools, list_tools_page, restart_services_matching, set_log_level,
+    set_mcp_tracing, set_service_concurrency, set_tool_output_limit, start_service,
+    start_service_from_command, stop_all_services, stop_service,
+};
+use commands::proxy_commands::{
+    add_api_key, cancel_stream, complete_batch, count_messages, delete_api_key_secure,
+    get_context_window, get_metrics, get_protocol_version, get_user_usage, list_active_streams,
+    list_middlewares, list_model_aliases, list_models, pause_stream, reattach_stream, reload_env,
+    remove_api_key, remove_model_alias, replay_stream, reset_metrics, resume_stream,
+    set_anthropic_version, set_circuit_breaker, set_context_window, set_default_max_tokens,
+    set_idempotency_keys, set_key_env_var, set_max_event_size, set_max_output_size,
+    set_model_alias, set_provider_headers, set_proxy_logging, set_request_redaction,
+    set_request_timeout, set_retry_policy, set_stream_limit, set_system_prompt_override,
+    start_ws_server, stop_ws_server, store_api_key_secure, stream_api_request,
+    stream_api_request_channel, stream_api_request_json, stream_api_request_with_channel,
+    stream_with_fallback,
+};
+use commands::status_commands::{get_status, StartTime};
+use services::mcp::autostart::AUTOSTART_FILE;
+use services::mcp::{AutostartConfig, JsonRpcTrace, ServiceManager, ToolCalls};
+use services::proxy::metrics::METRICS_FILE;
+use services::proxy::{Metrics, ProxyState, TRANSCRIPTS_DIR};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -26,13 +52,86 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_opener::init())
         .manage(Arc::new(Mutex::new(ServiceManager::default())))
+        .manage(ToolCalls::default())
+        .manage(Arc::new(JsonRpcTrace::default()))
+        .manage(ProxyState::default())
+        .manage(StartTime::default())
         .invoke_handler(tauri::generate_handler![
             start_service,
             list_tools,
             call_tool,
             get_services,
             stop_service,
+            list_resource_templates,
+            complete_argument,
+            set_service_concurrency,
+            list_tools_page,
+            get_capabilities,
+            set_log_level,
+            start_service_from_command,
+            kill_service,
+            cancel_start_service,
+            restart_services_matching,
+            has_tool,
+            get_compatibility,
+            set_mcp_tracing,
+            get_jsonrpc_log,
+            list_all_tools,
+            call_tool_auto,
+            get_service_info,
+            stop_all_services,
+            set_tool_output_limit,
+            get_services_detailed,
+            cancel_tool_call,
             stream_api_request,
+            set_stream_limit,
+            set_proxy_logging,
+            set_circuit_breaker,
+            reload_env,
+            add_api_key,
+            remove_api_key,
+            get_protocol_version,
+            stream_api_request_json,
+            store_api_key_secure,
+            delete_api_key_secure,
+            list_active_streams,
+            cancel_stream,
+            get_user_usage,
+            set_request_timeout,
+            pause_stream,
+            resume_stream,
+            set_retry_policy,
+            list_models,
+            set_max_event_size,
+            set_max_output_size,
+            replay_stream,
+            get_metrics,
+            reset_metrics,
+            stream_with_fallback,
+            set_provider_headers,
+            stream_api_request_with_channel,
+            set_key_env_var,
+            stream_api_request_channel,
+            complete_batch,
+            count_messages,
+            set_idempotency_keys,
+            set_anthropic_version,
+            reattach_stream,
+            start_ws_server,
+            stop_ws_server,
+            set_request_redaction,
+            set_system_prompt_override,
+            list_middlewares,
+            set_default_max_tokens,
+            get_context_window,
+            set_context_window,
+            set_model_alias,
+            remove_model_alias,
+            list_model_aliases,
+            export_config,
+            import_config,
+            tool_then_complete,
+            get_status,
         ])
         .setup(move |app| {
             #[cfg(debug_assertions)]
@@ -41,9 +140,49 @@ pub fn run() {
                     window.open_devtools();
                 }
             }
-            
+
+            let metrics_path = app.path().app_data_dir()?.join(METRICS_FILE);
+            match Metrics::load(&metrics_path) {
+                Ok(snapshot) => app.state::<ProxyState>().metrics.restore(snapshot),
+                Err(e) => warn!("Failed to load metrics: {}", e),
+            }
+            app.state::<ProxyState>()
+                .set_transcript_dir(app.path().app_data_dir()?.join(TRANSCRIPTS_DIR));
+
+            // Warm-start configured services without blocking the window from opening
+            let autostart_path = app.path().app_config_dir()?.join(AUTOSTART_FILE);
+            match AutostartConfig::load(&autostart_path) {
+                Ok(config) if !config.services.is_empty() => {
+                    let handle = app.handle().clone();
+                    tauri::async_runtime::spawn(async move {
+                        autostart_services(&handle, config).await;
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to read {}: {}", autostart_path.display(), e),
+            }
+
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                save_metrics(app);
+            }
+        });
+}
+
+/// Persist the accumulated metrics so the next run continues from them
+fn save_metrics(app: &AppHandle) {
+    let path = match app.path().app_data_dir() {
+        Ok(dir) => dir.join(METRICS_FILE),
+        Err(e) => {
+            warn!("Failed to resolve app data directory: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = app.state::<ProxyState>().metrics.save(&path) {
+        warn!("Failed to save metrics: {}", e);
+    }
 }
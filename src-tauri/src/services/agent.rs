@@ -0,0 +1,48 @@
+use rmcp::model::CallToolResult;
+use serde_json::Value;
+
+/// Placeholder in a completion payload template that is replaced with a tool's result
+pub const TOOL_RESULT_PLACEHOLDER: &str = "{{tool_result}}";
+/// Emitted with the `ToolCallResponse` before the completion starts streaming
+pub const EVT_TOOL_RESULT: &str = "ai-stream-tool-result";
+
+/// Text of a tool result, joining its text content items with newlines.
+///
+/// Non-text content (images, embedded resources) is included as JSON so the model
+/// still sees something for it.
+pub fn tool_result_text(result: &CallToolResult) -> String {
+    let content = serde_json::to_value(&result.content).unwrap_or(Value::Null);
+    content
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| match item.get("text").and_then(Value::as_str) {
+                    Some(text) => text.to_string(),
+                    None => item.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default()
+}
+
+/// Replace every occurrence of [`TOOL_RESULT_PLACEHOLDER`] in the template's strings
+/// with `text`, returning how many strings were filled
+pub fn fill_tool_result(template: &mut Value, text: &str) -> usize {
+    match template {
+        Value::String(s) if s.contains(TOOL_RESULT_PLACEHOLDER) => {
+            *s = s.replace(TOOL_RESULT_PLACEHOLDER, text);
+            1
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .map(|item| fill_tool_result(item, text))
+            .sum(),
+        Value::Object(map) => map
+            .values_mut()
+            .map(|value| fill_tool_result(value, text))
+            .sum(),
+        _ => 0,
+    }
+}
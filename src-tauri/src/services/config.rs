@@ -0,0 +1,130 @@
+use crate::services::mcp::{ServiceConfig, ServiceManager};
+use crate::services::proxy::{
+    load_api_key, redact_secrets, ProxyResult, ProxyState, PROVIDER_KEY_VARS,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Placeholder substituted for secrets in an exported config
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Snapshot of the runtime configuration, safe to share: it never contains API keys
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AppConfig {
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderConfig>,
+    #[serde(default)]
+    pub proxy_logging: bool,
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// MCP services and their launch parameters, with secrets in arguments and all
+    /// environment values redacted
+    #[serde(default)]
+    pub services: BTreeMap<String, ServiceConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderConfig {
+    pub max_concurrent: usize,
+    pub max_queue_depth: usize,
+    /// Whether a key was available on export; informational only
+    #[serde(default)]
+    pub api_key_configured: bool,
+    /// Number of keys in the provider's pool on export; informational only
+    #[serde(default)]
+    pub pooled_keys: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    pub failure_threshold: u32,
+    pub cooldown_secs: u64,
+}
+
+impl AppConfig {
+    /// Assemble the configuration from the managed states
+    pub fn collect(services: &ServiceManager, proxy: &ProxyState) -> Self {
+        let providers = PROVIDER_KEY_VARS
+            .iter()
+            .map(|(provider, _)| {
+                let (max_concurrent, max_queue_depth) = proxy.limiter.limit(provider);
+                let config = ProviderConfig {
+                    max_concurrent,
+                    max_queue_depth,
+                    api_key_configured: load_api_key(provider).is_ok(),
+                    pooled_keys: proxy.keys.key_count(provider),
+                };
+                (provider.to_string(), config)
+            })
+            .collect();
+
+        let (failure_threshold, cooldown) = proxy.circuits.config();
+
+        let services = services
+            .configs()
+            .map(|(name, config)| {
+                let mut config = config.clone();
+                config.args = config
+                    .args
+                    .iter()
+                    .map(|arg| redact_secrets(arg, ""))
+                    .collect();
+                // Environment variables are how services usually receive credentials
+                for value in config.env.values_mut() {
+                    *value = REDACTED.to_string();
+                }
+                (name.clone(), config)
+            })
+            .collect();
+
+        Self {
+            providers,
+            proxy_logging: proxy.proxy_logging(),
+            circuit_breaker: Some(CircuitBreakerConfig {
+                failure_threshold,
+                cooldown_secs: cooldown.as_secs(),
+            }),
+            services,
+        }
+    }
+
+    /// Restore the proxy settings. Keys are not part of the config and must be re-entered.
+    pub fn apply_proxy(&self, proxy: &ProxyState) -> ProxyResult<()> {
+        for (provider, config) in &self.providers {
+            proxy
+                .limiter
+                .set_limit(provider, config.max_concurrent, config.max_queue_depth)?;
+        }
+        if let Some(circuit) = &self.circuit_breaker {
+            proxy.circuits.configure(
+                circuit.failure_threshold,
+                Duration::from_secs(circuit.cooldown_secs),
+            )?;
+        }
+        proxy.set_proxy_logging(self.proxy_logging);
+        Ok(())
+    }
+
+    /// Providers that have no key available and need the user to supply one
+    pub fn missing_keys(&self, proxy: &ProxyState) -> Vec<String> {
+        self.providers
+            .keys()
+            .filter(|provider| {
+                proxy.keys.key_count(provider) == 0 && load_api_key(provider).is_err()
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigImportResponse {
+    pub success: bool,
+    pub services_started: Vec<String>,
+    /// Services that were not started, with the reason
+    pub services_skipped: BTreeMap<String, String>,
+    /// Providers that need an API key before they can be used
+    pub missing_keys: Vec<String>,
+    pub message: String,
+}
@@ -0,0 +1,48 @@
+use crate::services::mcp::{McpError, ServiceConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Window event sent once every auto-start service has started or failed
+pub const EVT_AUTOSTART_COMPLETE: &str = "mcp-autostart-complete";
+/// File in the app config directory listing the services to start at launch
+pub const AUTOSTART_FILE: &str = "autostart.json";
+/// Services started at once when the config doesn't set a bound
+pub const DEFAULT_AUTOSTART_CONCURRENCY: usize = 4;
+
+/// Services to start when the app launches
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AutostartConfig {
+    /// Maximum number of services spawning and handshaking at the same time
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+    #[serde(default)]
+    pub services: BTreeMap<String, ServiceConfig>,
+}
+
+impl AutostartConfig {
+    /// Read the config at `path`; a missing file means nothing to auto-start
+    pub fn load(path: &Path) -> Result<Self, McpError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| McpError::SerializationError(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+            .unwrap_or(DEFAULT_AUTOSTART_CONCURRENCY)
+            .max(1)
+    }
+}
+
+/// Payload of the autostart-complete event
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AutostartSummary {
+    pub started: Vec<String>,
+    /// Services that failed to start, with the error
+    pub failed: BTreeMap<String, String>,
+}
@@ -0,0 +1,257 @@
+use crate::services::mcp::McpError;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use tokio_util::sync::CancellationToken;
+
+/// Window event sent for each tool call cancelled by `cancel_tool_call`
+pub const EVT_TOOL_CANCELLED: &str = "mcp-tool-cancelled";
+
+/// Payload of the tool cancelled event
+#[derive(Serialize, Debug, Clone)]
+pub struct ToolCallCancelled {
+    pub call_id: String,
+    pub service: String,
+    pub tool: String,
+    pub parent_call_id: Option<String>,
+}
+
+struct ToolCallEntry {
+    service: String,
+    tool: String,
+    parent: Option<String>,
+    cancel: CancellationToken,
+}
+
+/// Tool calls in flight, linked to the call that spawned them so a whole subtree of an
+/// agent's calls can be cancelled at once
+#[derive(Default)]
+pub struct ToolCalls {
+    next_id: AtomicU64,
+    calls: Mutex<HashMap<String, ToolCallEntry>>,
+}
+
+impl ToolCalls {
+    /// Register a call under `call_id`, generated when absent. The parent must still be
+    /// running, so a child can't outlive a cancelled parent.
+    pub fn register(
+        &self,
+        call_id: Option<String>,
+        parent: Option<String>,
+        service: &str,
+        tool: &str,
+    ) -> Result<ToolCallRegistration<'_>, McpError> {
+        let id = call_id.unwrap_or_else(|| {
+            format!("call-{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        });
+        let mut calls = self.lock();
+        if calls.contains_key(&id) {
+            return Err(McpError::InvalidArguments(format!(
+                "Tool call {} is already running",
+                id
+            )));
+        }
+        if let Some(parent) = &parent {
+            let running = calls
+                .get(parent)
+                .is_some_and(|entry| !entry.cancel.is_cancelled());
+            if !running {
+                return Err(McpError::InvalidArguments(format!(
+                    "Parent tool call {} is not running",
+                    parent
+                )));
+            }
+        }
+
+        let cancel = CancellationToken::new();
+        calls.insert(
+            id.clone(),
+            ToolCallEntry {
+                service: service.to_string(),
+                tool: tool.to_string(),
+                parent,
+                cancel: cancel.clone(),
+            },
+        );
+        Ok(ToolCallRegistration {
+            calls: self,
+            id,
+            cancel,
+        })
+    }
+
+    /// Cancel a call and, with `cascade`, every call descended from it. Returns the
+    /// cancelled calls, parents before their children, or `None` if the call isn't running.
+    pub fn cancel(&self, call_id: &str, cascade: bool) -> Option<Vec<ToolCallCancelled>> {
+        let calls = self.lock();
+        calls.get(call_id)?;
+
+        let mut targets = vec![call_id.to_string()];
+        let mut next = 0;
+        while cascade && next < targets.len() {
+            let parent = targets[next].clone();
+            targets.extend(
+                calls
+                    .iter()
+                    .filter(|(_, entry)| entry.parent.as_deref() == Some(parent.as_str()))
+                    .map(|(id, _)| id.clone())
+                    // A reused id could otherwise link a call back to its own descendant
+                    .filter(|id| !targets.contains(id))
+                    .collect::<Vec<_>>(),
+            );
+            next += 1;
+        }
+
+        let cancelled = targets
+            .into_iter()
+            .filter_map(|id| {
+                let entry = calls.get(&id)?;
+                entry.cancel.cancel();
+                Some(ToolCallCancelled {
+                    call_id: id,
+                    service: entry.service.clone(),
+                    tool: entry.tool.clone(),
+                    parent_call_id: entry.parent.clone(),
+                })
+            })
+            .collect();
+        Some(cancelled)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, ToolCallEntry>> {
+        self.calls
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A running tool call; it leaves the registry when dropped
+pub struct ToolCallRegistration<'a> {
+    calls: &'a ToolCalls,
+    id: String,
+    cancel: CancellationToken,
+}
+
+impl ToolCallRegistration<'_> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Cancelled when the call, or with a cascade one of its ancestors, is cancelled
+    pub fn cancelled(&self) -> &CancellationToken {
+        &self.cancel
+    }
+}
+
+impl Drop for ToolCallRegistration<'_> {
+    fn drop(&mut self) {
+        self.calls.lock().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(cancelled: &[ToolCallCancelled]) -> Vec<&str> {
+        cancelled.iter().map(|call| call.call_id.as_str()).collect()
+    }
+
+    #[test]
+    fn cascades_to_every_descendant_parents_first() {
+        let calls = ToolCalls::default();
+        let root = calls
+            .register(Some("root".into()), None, "agent", "plan")
+            .unwrap();
+        let search = calls
+            .register(Some("search".into()), Some("root".into()), "web", "search")
+            .unwrap();
+        let fetch = calls
+            .register(Some("fetch".into()), Some("search".into()), "web", "fetch")
+            .unwrap();
+        let write = calls
+            .register(Some("write".into()), Some("root".into()), "fs", "write")
+            .unwrap();
+        let other = calls.register(None, None, "fs", "read").unwrap();
+
+        let cancelled = calls.cancel("root", true).unwrap();
+        let order = ids(&cancelled);
+        assert_eq!(order.len(), 4);
+        assert_eq!(order[0], "root");
+        let position = |id| order.iter().position(|call| *call == id).unwrap();
+        assert!(position("search") < position("fetch"));
+        assert!(position("write") > 0);
+
+        let fetch_event = &cancelled[position("fetch")];
+        assert_eq!(fetch_event.service, "web");
+        assert_eq!(fetch_event.tool, "fetch");
+        assert_eq!(fetch_event.parent_call_id.as_deref(), Some("search"));
+
+        for call in [&root, &search, &fetch, &write] {
+            assert!(
+                call.cancelled().is_cancelled(),
+                "{} kept running",
+                call.id()
+            );
+        }
+        assert!(!other.cancelled().is_cancelled());
+    }
+
+    #[test]
+    fn cancels_only_the_call_without_a_cascade() {
+        let calls = ToolCalls::default();
+        let root = calls
+            .register(Some("root".into()), None, "agent", "plan")
+            .unwrap();
+        let child = calls
+            .register(Some("child".into()), Some("root".into()), "web", "search")
+            .unwrap();
+
+        let cancelled = calls.cancel("root", false).unwrap();
+        assert_eq!(ids(&cancelled), ["root"]);
+        assert!(root.cancelled().is_cancelled());
+        assert!(!child.cancelled().is_cancelled());
+    }
+
+    #[test]
+    fn rejects_a_child_of_a_call_that_is_not_running() {
+        let calls = ToolCalls::default();
+        assert!(matches!(
+            calls.register(None, Some("missing".into()), "web", "search"),
+            Err(McpError::InvalidArguments(_))
+        ));
+
+        let _root = calls
+            .register(Some("root".into()), None, "agent", "plan")
+            .unwrap();
+        calls.cancel("root", false).unwrap();
+        assert!(matches!(
+            calls.register(None, Some("root".into()), "web", "search"),
+            Err(McpError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_an_id_already_running() {
+        let calls = ToolCalls::default();
+        let _first = calls
+            .register(Some("call".into()), None, "web", "search")
+            .unwrap();
+        assert!(matches!(
+            calls.register(Some("call".into()), None, "web", "fetch"),
+            Err(McpError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn forgets_a_call_once_it_finishes() {
+        let calls = ToolCalls::default();
+        let call = calls.register(None, None, "web", "search").unwrap();
+        let id = call.id().to_string();
+        assert!(id.starts_with("call-"));
+
+        drop(call);
+        assert!(calls.cancel(&id, true).is_none());
+    }
+}
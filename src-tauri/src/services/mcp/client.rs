@@ -0,0 +1,69 @@
+use rmcp::{
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+    service::{Peer, RoleClient, RunningService},
+    ClientHandler,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Window event carrying log messages sent by MCP servers
+pub const EVT_SERVER_LOG: &str = "mcp-server-log";
+
+/// A running MCP service with our client handler
+pub type McpService = RunningService<RoleClient, McpClient>;
+
+/// Callback receiving the log messages a server sends
+pub type LogCallback = Arc<dyn Fn(ServerLogMessage) + Send + Sync>;
+
+/// A `notifications/message` log event from a server
+#[derive(Serialize, Debug, Clone)]
+pub struct ServerLogMessage {
+    pub service: String,
+    pub level: LoggingLevel,
+    pub logger: Option<String>,
+    pub data: Value,
+}
+
+/// Client-side handler for server notifications
+pub struct McpClient {
+    service_name: String,
+    on_log: Option<LogCallback>,
+    peer: Option<Peer<RoleClient>>,
+}
+
+impl McpClient {
+    pub fn new(service_name: impl Into<String>, on_log: Option<LogCallback>) -> Self {
+        Self {
+            service_name: service_name.into(),
+            on_log,
+            peer: None,
+        }
+    }
+}
+
+impl ClientHandler for McpClient {
+    fn on_logging_message(
+        &self,
+        params: LoggingMessageNotificationParam,
+    ) -> impl Future<Output = ()> + Send + '_ {
+        if let Some(on_log) = &self.on_log {
+            on_log(ServerLogMessage {
+                service: self.service_name.clone(),
+                level: params.level,
+                logger: params.logger,
+                data: params.data,
+            });
+        }
+        std::future::ready(())
+    }
+
+    fn get_peer(&self) -> Option<Peer<RoleClient>> {
+        self.peer.clone()
+    }
+
+    fn set_peer(&mut self, peer: Peer<RoleClient>) {
+        self.peer = Some(peer);
+    }
+}
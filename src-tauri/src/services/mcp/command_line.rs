@@ -0,0 +1,178 @@
+use crate::services::mcp::McpError;
+
+/// Shell operators we refuse to pass through, since services are spawned without a shell
+const FORBIDDEN_OPERATORS: &[char] = &['|', '&', ';', '<', '>', '`'];
+
+/// Executable and arguments parsed from a shell-style command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedCommand {
+    pub executable: String,
+    pub args: Vec<String>,
+}
+
+/// Split a command line into words, honouring single quotes, double quotes and
+/// backslash escapes.
+///
+/// Unquoted pipes, redirects, command separators and substitutions are rejected rather
+/// than passed along as literal arguments.
+pub fn split_command_line(command_line: &str) -> Result<Vec<String>, McpError> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = command_line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(unterminated("single")),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') if matches!(chars.peek(), Some('"' | '\\')) => {
+                            current.extend(chars.next());
+                        }
+                        Some('`') => return Err(forbidden('`')),
+                        Some('$') if chars.peek() == Some(&'(') => return Err(forbidden('$')),
+                        Some(c) => current.push(c),
+                        None => return Err(unterminated("double")),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                match chars.next() {
+                    Some(c) => current.push(c),
+                    None => current.push('\\'),
+                }
+            }
+            '$' if chars.peek() == Some(&'(') => return Err(forbidden('$')),
+            c if FORBIDDEN_OPERATORS.contains(&c) => return Err(forbidden(c)),
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+
+    Ok(words)
+}
+
+/// Parse a command line such as `npx -y @scope/server` into an executable and arguments,
+/// resolving package runners for the current platform.
+pub fn parse_command_line(command_line: &str) -> Result<ParsedCommand, McpError> {
+    let mut words = split_command_line(command_line)?.into_iter();
+    let executable = words
+        .next()
+        .ok_or_else(|| McpError::InvalidArguments("Command line is empty".to_string()))?;
+
+    Ok(resolve_for_platform(executable, words.collect()))
+}
+
+/// On Windows, Node's package runners are `.cmd` scripts that can't be spawned
+/// directly, so they run through `cmd /C`. `uvx` ships as an `.exe` and needs no help.
+fn resolve_for_platform(executable: String, args: Vec<String>) -> ParsedCommand {
+    let is_node_runner = matches!(
+        executable.to_ascii_lowercase().as_str(),
+        "npx" | "npm" | "pnpm" | "yarn" | "bunx"
+    );
+
+    if cfg!(windows) && is_node_runner {
+        let mut cmd_args = vec!["/C".to_string(), executable];
+        cmd_args.extend(args);
+        return ParsedCommand {
+            executable: "cmd".to_string(),
+            args: cmd_args,
+        };
+    }
+
+    ParsedCommand { executable, args }
+}
+
+fn unterminated(kind: &str) -> McpError {
+    McpError::InvalidArguments(format!("Unterminated {} quote in command line", kind))
+}
+
+fn forbidden(operator: char) -> McpError {
+    McpError::InvalidArguments(format!(
+        "Shell operator '{}' is not allowed in a service command line",
+        operator
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_quoted_and_escaped_words() {
+        let words =
+            split_command_line(r#"node "my server.js" --name='a b' c\ d "say \"hi\"""#).unwrap();
+        assert_eq!(
+            words,
+            ["node", "my server.js", "--name=a b", "c d", r#"say "hi""#]
+        );
+        assert_eq!(split_command_line("  a   '' b ").unwrap(), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn rejects_shell_operators_and_unterminated_quotes() {
+        for command_line in [
+            "server | tee log",
+            "server > out",
+            "a; b",
+            "a && b",
+            "echo `id`",
+            "echo $(id)",
+            "echo \"$(id)\"",
+            "echo 'open",
+            "echo \"open",
+        ] {
+            assert!(
+                split_command_line(command_line).is_err(),
+                "accepted {:?}",
+                command_line
+            );
+        }
+        // Quoted operators are ordinary characters
+        assert_eq!(split_command_line("echo 'a|b'").unwrap(), ["echo", "a|b"]);
+    }
+
+    #[test]
+    fn parses_executable_and_arguments() {
+        assert!(parse_command_line("   ").is_err());
+        let parsed = parse_command_line("uvx mcp-server-fetch --timeout 5").unwrap();
+        assert_eq!(parsed.executable, "uvx");
+        assert_eq!(parsed.args, ["mcp-server-fetch", "--timeout", "5"]);
+    }
+
+    #[test]
+    fn runs_node_package_runners_through_cmd_on_windows() {
+        let parsed = parse_command_line("npx -y @scope/server").unwrap();
+        if cfg!(windows) {
+            assert_eq!(parsed.executable, "cmd");
+            assert_eq!(parsed.args, ["/C", "npx", "-y", "@scope/server"]);
+        } else {
+            assert_eq!(parsed.executable, "npx");
+            assert_eq!(parsed.args, ["-y", "@scope/server"]);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// MCP protocol revision spoken by the bundled rmcp client
+pub const CLIENT_PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Protocol revisions newer than the client's, with the features they added.
+/// Revisions are dates, so they order as strings.
+const NEWER_REVISIONS: &[(&str, &str)] = &[
+    (
+        "2025-03-26",
+        "tool annotations, audio content and streamable HTTP transport",
+    ),
+    (
+        "2025-06-18",
+        "elicitation, structured tool output and resource links",
+    ),
+];
+
+/// Outcome of comparing a server's protocol revision with the client's
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Compatibility {
+    /// Revision the server reported in its `initialize` result
+    pub server_version: String,
+    pub client_version: String,
+    /// Functionality limited by the mismatch, empty when the versions match
+    pub caveats: Vec<String>,
+}
+
+impl Compatibility {
+    pub fn check(server_version: &str) -> Self {
+        let client = CLIENT_PROTOCOL_VERSION;
+        let mut caveats = Vec::new();
+
+        if server_version.is_empty() {
+            caveats.push("Server did not report a protocol version".to_string());
+        } else if server_version > client {
+            caveats.push(format!(
+                "Server speaks MCP {}, newer than the client's {}; newer features are unavailable",
+                server_version, client
+            ));
+            caveats.extend(
+                NEWER_REVISIONS
+                    .iter()
+                    .filter(|(revision, _)| *revision <= server_version)
+                    .map(|(revision, features)| {
+                        format!("Unsupported from {}: {}", revision, features)
+                    }),
+            );
+        } else if server_version < client {
+            caveats.push(format!(
+                "Server speaks older MCP {}; features added since may be missing",
+                server_version
+            ));
+        }
+
+        Self {
+            server_version: server_version.to_string(),
+            client_version: client.to_string(),
+            caveats,
+        }
+    }
+
+    /// Summary of the caveats for display, if there are any
+    pub fn warning(&self) -> Option<String> {
+        (!self.caveats.is_empty()).then(|| self.caveats.join("; "))
+    }
+}
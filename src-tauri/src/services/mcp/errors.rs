@@ -2,6 +2,7 @@ use rmcp::{model::JsonRpcError, ServiceError};
 use std::error::Error;
 use std::fmt;
 use std::io;
+use std::time::Duration;
 use tokio::task::JoinError;
 
 #[derive(Debug)]
@@ -14,6 +15,9 @@ pub enum McpError {
     InvalidArguments(String),
     JsonRpcError(JsonRpcError),
     TaskJoinError(String),
+    HandshakeTimeout(String, Duration),
+    Cancelled(String),
+    ToolCallCancelled(String),
 }
 
 impl fmt::Display for McpError {
@@ -27,6 +31,13 @@ impl fmt::Display for McpError {
             McpError::InvalidArguments(msg) => write!(f, "Invalid arguments: {}", msg),
             McpError::JsonRpcError(err) => write!(f, "JSON-RPC error: {:?}", err),
             McpError::TaskJoinError(msg) => write!(f, "Task join/cancellation error: {}", msg),
+            McpError::HandshakeTimeout(name, timeout) => write!(
+                f,
+                "Service {} did not complete the MCP handshake within {:?}",
+                name, timeout
+            ),
+            McpError::Cancelled(name) => write!(f, "Starting service {} was cancelled", name),
+            McpError::ToolCallCancelled(id) => write!(f, "Tool call {} was cancelled", id),
         }
     }
 }
@@ -1,6 +1,30 @@
+pub mod autostart;
+pub mod calls;
+pub mod client;
+pub mod command_line;
+pub mod compat;
 pub mod errors;
+pub mod namespace;
+pub mod process;
+pub mod reconnect;
 pub mod service;
+pub mod trace;
+pub mod truncate;
 
+pub use autostart::{AutostartConfig, AutostartSummary, EVT_AUTOSTART_COMPLETE};
+pub use calls::{ToolCallCancelled, ToolCalls, EVT_TOOL_CANCELLED};
+pub use client::{LogCallback, McpClient, McpService, ServerLogMessage, EVT_SERVER_LOG};
+pub use compat::{Compatibility, CLIENT_PROTOCOL_VERSION};
 pub use errors::McpError;
-pub use service::ServiceManager;
-pub use service::{ServiceResponse, ToolCallResponse, ToolsResponse};
+pub use process::{ServiceExit, ServiceProcess, EVT_SERVICE_EXITED};
+pub use reconnect::{ReconnectPolicy, ReconnectingEvent, EVT_SERVICE_RECONNECTING};
+pub use service::{
+    lock_services, ServiceCapabilities, ServiceConfig, ServiceManager, DEFAULT_DRAIN_TIMEOUT,
+};
+pub use service::{
+    CapabilitiesResponse, CompatibilityResponse, CompletionResponse, ResourceTemplatesResponse,
+    ServiceDetail, ServiceInfo, ServiceResponse, ServiceRestartResult, ShutdownReport,
+    ToolCallResponse, ToolsPageResponse, ToolsResponse,
+};
+pub use trace::{Direction, JsonRpcTrace, Tap, TracedMessage};
+pub use truncate::DEFAULT_MAX_TOOL_OUTPUT_BYTES;
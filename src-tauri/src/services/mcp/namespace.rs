@@ -0,0 +1,42 @@
+/// Separator between service and tool in a namespaced tool name, as in `files__search`
+pub const TOOL_NAMESPACE_SEPARATOR: &str = "__";
+
+/// Qualify a tool name with its service so names stay unique across services
+pub fn namespaced_tool_name(service_name: &str, tool_name: &str) -> String {
+    format!("{}{}{}", service_name, TOOL_NAMESPACE_SEPARATOR, tool_name)
+}
+
+/// Split a namespaced tool name into service and tool at the first separator.
+///
+/// Services whose own name contains the separator can't be addressed this way.
+pub fn split_namespaced(name: &str) -> Option<(&str, &str)> {
+    name.split_once(TOOL_NAMESPACE_SEPARATOR)
+        .filter(|(service, tool)| !service.is_empty() && !tool.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_a_namespaced_name_back_into_service_and_tool() {
+        let name = namespaced_tool_name("files", "search");
+        assert_eq!(name, "files__search");
+        assert_eq!(split_namespaced(&name), Some(("files", "search")));
+    }
+
+    #[test]
+    fn splits_at_the_first_separator() {
+        assert_eq!(
+            split_namespaced("files__search__recent"),
+            Some(("files", "search__recent"))
+        );
+    }
+
+    #[test]
+    fn rejects_names_without_both_parts() {
+        assert_eq!(split_namespaced("search"), None);
+        assert_eq!(split_namespaced("__search"), None);
+        assert_eq!(split_namespaced("files__"), None);
+    }
+}
@@ -0,0 +1,174 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::process::Child;
+use tokio_util::sync::CancellationToken;
+
+/// App event sent when a service's process exits without being stopped
+pub const EVT_SERVICE_EXITED: &str = "mcp-service-exited";
+
+/// How a service's process ended
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceExit {
+    pub service: String,
+    /// Exit code, if the process exited normally
+    pub code: Option<i32>,
+    /// Signal that terminated the process (Unix only)
+    pub signal: Option<i32>,
+    /// Whether we stopped or killed the service, as opposed to a crash
+    pub expected: bool,
+    /// Unix time in milliseconds when the exit was observed
+    pub exited_at_ms: u64,
+}
+
+impl ServiceExit {
+    fn new(service: String, status: Option<ExitStatus>, expected: bool) -> Self {
+        #[cfg(unix)]
+        let signal = {
+            use std::os::unix::process::ExitStatusExt;
+            status.and_then(|status| status.signal())
+        };
+        #[cfg(not(unix))]
+        let signal = None;
+
+        Self {
+            service,
+            code: status.and_then(|status| status.code()),
+            signal,
+            expected,
+            exited_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis() as u64)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// A service's child process, owned by its monitor task.
+///
+/// The process is killed when this handle is dropped.
+pub struct ServiceProcess {
+    pid: Option<u32>,
+    kill: CancellationToken,
+    /// Set once we shut the service down, so its exit isn't reported as a crash
+    expected: Arc<AtomicBool>,
+    /// Set by the monitor once the process has exited
+    exited: Arc<AtomicBool>,
+}
+
+impl ServiceProcess {
+    /// OS process id, if the process hadn't exited when it was spawned
+    pub fn id(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// Whether the process is still running
+    pub fn is_running(&self) -> bool {
+        !self.exited.load(Ordering::SeqCst)
+    }
+
+    /// Mark the coming exit as a shutdown we asked for
+    pub fn expect_exit(&self) {
+        self.expected.store(true, Ordering::SeqCst);
+    }
+
+    /// Ask the monitor to kill the process, without waiting for it to exit
+    pub fn start_kill(&self) {
+        self.expect_exit();
+        self.kill.cancel();
+    }
+}
+
+impl Drop for ServiceProcess {
+    fn drop(&mut self) {
+        self.start_kill();
+    }
+}
+
+/// Hand a service's child process to a task that waits for it to exit, then calls
+/// `on_exit` with how it ended
+pub fn spawn_monitor<F>(service: &str, mut child: Child, on_exit: F) -> ServiceProcess
+where
+    F: FnOnce(ServiceExit) + Send + 'static,
+{
+    let kill = CancellationToken::new();
+    let expected = Arc::new(AtomicBool::new(false));
+    let exited = Arc::new(AtomicBool::new(false));
+    let process = ServiceProcess {
+        pid: child.id(),
+        kill: kill.clone(),
+        expected: expected.clone(),
+        exited: exited.clone(),
+    };
+
+    let service = service.to_string();
+    tauri::async_runtime::spawn(async move {
+        let status = match kill.run_until_cancelled(child.wait()).await {
+            Some(status) => status,
+            None => {
+                if let Err(e) = child.start_kill() {
+                    warn!("Failed to kill {}: {}", service, e);
+                }
+                child.wait().await
+            }
+        };
+        exited.store(true, Ordering::SeqCst);
+        let status = match status {
+            Ok(status) => Some(status),
+            Err(e) => {
+                warn!("Failed to wait for {} to exit: {}", service, e);
+                None
+            }
+        };
+        on_exit(ServiceExit::new(
+            service,
+            status,
+            expected.load(Ordering::SeqCst),
+        ));
+    });
+    process
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::process::Command;
+    use tokio::sync::oneshot;
+
+    /// Monitor `sh -c script`, returning the process and a receiver for its exit
+    fn monitor(script: &str) -> (ServiceProcess, oneshot::Receiver<ServiceExit>) {
+        let child = Command::new("sh").args(["-c", script]).spawn().unwrap();
+        let (sender, receiver) = oneshot::channel();
+        let process = spawn_monitor("test", child, move |exit| {
+            let _ = sender.send(exit);
+        });
+        (process, receiver)
+    }
+
+    #[tokio::test]
+    async fn reports_the_exit_code_of_a_crash() {
+        let (process, exited) = monitor("exit 3");
+        let exit = exited.await.unwrap();
+
+        assert_eq!(exit.service, "test");
+        assert_eq!(exit.code, Some(3));
+        assert_eq!(exit.signal, None);
+        assert!(!exit.expected);
+        assert!(!process.is_running());
+    }
+
+    #[tokio::test]
+    async fn reports_a_dropped_process_as_an_expected_kill() {
+        let (process, exited) = monitor("exec sleep 30");
+        assert!(process.is_running());
+        drop(process);
+        let exit = exited.await.unwrap();
+
+        assert_eq!(exit.code, None);
+        assert_eq!(exit.signal, Some(9));
+        assert!(exit.expected);
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Window event sent before each reconnection attempt
+pub const EVT_SERVICE_RECONNECTING: &str = "mcp-service-reconnecting";
+
+/// Capped exponential backoff with jitter between reconnection attempts
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    /// Delay before the first retry
+    pub base_ms: u64,
+    /// Upper bound on any single delay
+    pub max_ms: u64,
+    /// Growth factor applied per attempt
+    pub multiplier: f64,
+    /// Attempts before giving up (`None` retries forever)
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            base_ms: 500,
+            max_ms: 30_000,
+            multiplier: 2.0,
+            max_attempts: Some(10),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before `attempt` (1-based) without jitter: `base * multiplier^(attempt - 1)`,
+    /// capped at `max_ms`
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay_ms = self.base_ms as f64 * self.multiplier.max(1.0).powi(exponent);
+        Duration::from_millis(delay_ms.min(self.max_ms as f64) as u64)
+    }
+
+    /// Delay before `attempt` with jitter, or `None` once attempts are exhausted.
+    ///
+    /// Uses "equal jitter": half the backoff is fixed and half is random, so delays still
+    /// grow while concurrent clients spread out instead of retrying in lockstep.
+    pub fn next_delay(&self, attempt: u32) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempt > max) {
+            return None;
+        }
+        let backoff = self.backoff(attempt);
+        let half = backoff / 2;
+        Some(half + half.mul_f64(jitter_fraction()))
+    }
+}
+
+/// Random fraction in `[0, 1)`, drawn from std's per-process random hasher keys
+fn jitter_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Payload of the reconnecting event
+#[derive(Serialize, Debug, Clone)]
+pub struct ReconnectingEvent {
+    pub service: String,
+    pub attempt: u32,
+    pub max_attempts: Option<u32>,
+    pub next_delay_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_backoff_exponentially_up_to_the_cap() {
+        let policy = ReconnectPolicy {
+            base_ms: 100,
+            max_ms: 1_000,
+            multiplier: 2.0,
+            max_attempts: None,
+        };
+        let delays: Vec<u64> = (1..=6)
+            .map(|attempt| policy.backoff(attempt).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1_000, 1_000]);
+        // Huge attempt counts saturate instead of overflowing
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(1_000));
+    }
+
+    #[test]
+    fn jitters_within_the_upper_half_of_the_backoff() {
+        let policy = ReconnectPolicy::default();
+        for attempt in 1..=10 {
+            let backoff = policy.backoff(attempt);
+            let delay = policy.next_delay(attempt).unwrap();
+            assert!(
+                delay >= backoff / 2,
+                "{:?} below half of {:?}",
+                delay,
+                backoff
+            );
+            assert!(delay <= backoff, "{:?} above {:?}", delay, backoff);
+        }
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let policy = ReconnectPolicy {
+            max_attempts: Some(3),
+            ..Default::default()
+        };
+        assert!(policy.next_delay(3).is_some());
+        assert!(policy.next_delay(4).is_none());
+
+        let forever = ReconnectPolicy {
+            max_attempts: None,
+            ..Default::default()
+        };
+        assert!(forever.next_delay(1_000).is_some());
+    }
+}
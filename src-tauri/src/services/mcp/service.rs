@@ -1,30 +1,371 @@
-use rmcp::{
-    model::{CallToolResult, Tool},
-    service::{RoleClient, RunningService},
-};
+use crate::services::mcp::client::McpService;
+use crate::services::mcp::compat::Compatibility;
+use crate::services::mcp::process::{ServiceExit, ServiceProcess};
+use crate::services::mcp::truncate::DEFAULT_MAX_TOOL_OUTPUT_BYTES;
+use log::warn;
+use rmcp::model::{CallToolResult, CompletionInfo, ResourceTemplate, Tool};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
+
+/// Launch parameters and settings for an MCP service
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServiceConfig {
+    pub executable: String,
+    pub args: Vec<String>,
+    /// Extra environment variables for the process
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Maximum number of tool calls dispatched to the service at once (`None` is unlimited)
+    #[serde(default)]
+    pub max_concurrent_calls: Option<usize>,
+    /// Seconds to wait for the MCP handshake before killing the process (`None` uses the default)
+    #[serde(default)]
+    pub handshake_timeout_secs: Option<u64>,
+    /// Most bytes of text a tool result may carry before it is truncated (`None` uses
+    /// the app-wide limit)
+    #[serde(default)]
+    pub max_output_bytes: Option<usize>,
+}
+
+/// Default time allowed for a service to complete the MCP handshake
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default time a service is given to shut down gracefully before it is killed
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl ServiceConfig {
+    pub fn handshake_timeout(&self) -> Duration {
+        self.handshake_timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_HANDSHAKE_TIMEOUT)
+    }
+}
+
+/// Capabilities advertised by an MCP server during the handshake
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServiceCapabilities {
+    pub tools: bool,
+    pub resources: bool,
+    pub prompts: bool,
+    pub sampling: bool,
+    pub logging: bool,
+    pub completions: bool,
+    /// Experimental and otherwise unrecognized capabilities, as advertised
+    pub extra: Map<String, Value>,
+}
+
+impl ServiceCapabilities {
+    /// Normalize the capabilities object from the server's `initialize` result
+    pub fn from_advertised(advertised: &Value) -> Self {
+        let mut capabilities = Self::default();
+        let Some(advertised) = advertised.as_object() else {
+            return capabilities;
+        };
+
+        for (name, value) in advertised {
+            if value.is_null() {
+                continue;
+            }
+            match name.as_str() {
+                "tools" => capabilities.tools = true,
+                "resources" => capabilities.resources = true,
+                "prompts" => capabilities.prompts = true,
+                "sampling" => capabilities.sampling = true,
+                "logging" => capabilities.logging = true,
+                "completions" => capabilities.completions = true,
+                "experimental" => {
+                    if let Some(experimental) = value.as_object() {
+                        capabilities.extra.extend(experimental.clone());
+                    }
+                }
+                _ => {
+                    capabilities.extra.insert(name.clone(), value.clone());
+                }
+            }
+        }
+        capabilities
+    }
+}
+
+struct ManagedService {
+    service: McpService,
+    config: ServiceConfig,
+    call_limiter: Option<Arc<Semaphore>>,
+    /// Raw capabilities object from the handshake
+    capabilities: Value,
+    /// The server's child process, if we spawned it
+    process: Option<ServiceProcess>,
+    /// Full tool list from the last `list_tools`, if any
+    tools: Option<Vec<Tool>>,
+    /// Protocol version check from the handshake
+    compatibility: Compatibility,
+    /// Unix time in milliseconds when the service was added
+    started_at_ms: u64,
+    /// Unix time in milliseconds of the last tool call
+    last_used_ms: Option<u64>,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
 
 #[derive(Default)]
 pub struct ServiceManager {
-    services: HashMap<String, RunningService<RoleClient, ()>>,
+    services: HashMap<String, ManagedService>,
+    /// Cancellation tokens for services that are still starting up
+    pending_starts: HashMap<String, CancellationToken>,
+    /// App-wide tool output limit, for services that don't set their own
+    max_output_bytes: Option<usize>,
+    /// How each service's process last exited
+    exits: HashMap<String, ServiceExit>,
+}
+
+/// Lock the shared service manager, recovering if a previous holder panicked.
+///
+/// The manager's maps stay consistent across a panic in a command, so a poisoned
+/// lock is cleared rather than failing every later command until restart.
+pub fn lock_services(services: &Mutex<ServiceManager>) -> MutexGuard<'_, ServiceManager> {
+    services.lock().unwrap_or_else(|poisoned| {
+        warn!("Service manager lock was poisoned by a panic; recovering");
+        services.clear_poison();
+        poisoned.into_inner()
+    })
 }
 
 impl ServiceManager {
-    pub fn add_service(&mut self, name: String, service: RunningService<RoleClient, ()>) {
-        self.services.insert(name, service);
+    pub fn add_service(
+        &mut self,
+        name: String,
+        service: McpService,
+        config: ServiceConfig,
+        process: Option<ServiceProcess>,
+    ) -> Compatibility {
+        let call_limiter = config
+            .max_concurrent_calls
+            .map(|limit| Arc::new(Semaphore::new(limit)));
+        let capabilities =
+            serde_json::to_value(&service.peer_info().capabilities).unwrap_or(Value::Null);
+        let server_version = serde_json::to_value(&service.peer_info().protocol_version)
+            .ok()
+            .and_then(|version| version.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let compatibility = Compatibility::check(&server_version);
+        self.services.insert(
+            name,
+            ManagedService {
+                service,
+                config,
+                call_limiter,
+                capabilities,
+                process,
+                tools: None,
+                compatibility: compatibility.clone(),
+                started_at_ms: now_ms(),
+                last_used_ms: None,
+            },
+        );
+        compatibility
+    }
+
+    pub fn get_service(&self, name: &str) -> Option<&McpService> {
+        self.services.get(name).map(|managed| &managed.service)
+    }
+
+    pub fn get_config(&self, name: &str) -> Option<&ServiceConfig> {
+        self.services.get(name).map(|managed| &managed.config)
+    }
+
+    /// Raw capabilities the service advertised during the handshake
+    pub fn get_capabilities(&self, name: &str) -> Option<&Value> {
+        self.services.get(name).map(|managed| &managed.capabilities)
+    }
+
+    /// Protocol version check made when the service started
+    pub fn compatibility(&self, name: &str) -> Option<&Compatibility> {
+        self.services
+            .get(name)
+            .map(|managed| &managed.compatibility)
     }
 
-    pub fn get_service(&self, name: &str) -> Option<&RunningService<RoleClient, ()>> {
-        self.services.get(name)
+    /// Tools cached from the last full listing
+    pub fn cached_tools(&self, name: &str) -> Option<&[Tool]> {
+        self.services
+            .get(name)
+            .and_then(|managed| managed.tools.as_deref())
+    }
+
+    /// Cache the full tool list of a service; ignored if the service is gone
+    pub fn cache_tools(&mut self, name: &str, tools: Vec<Tool>) {
+        if let Some(managed) = self.services.get_mut(name) {
+            managed.tools = Some(tools);
+        }
+    }
+
+    /// Semaphore limiting concurrent tool calls, if the service has a limit
+    pub fn call_limiter(&self, name: &str) -> Option<Arc<Semaphore>> {
+        self.services
+            .get(name)
+            .and_then(|managed| managed.call_limiter.clone())
+    }
+
+    /// Set the concurrent tool call limit for a service, returning false if it is unknown.
+    ///
+    /// Calls already holding a permit finish under the old limit.
+    pub fn set_concurrency(&mut self, name: &str, limit: Option<usize>) -> bool {
+        match self.services.get_mut(name) {
+            Some(managed) => {
+                managed.config.max_concurrent_calls = limit;
+                managed.call_limiter = limit.map(|limit| Arc::new(Semaphore::new(limit)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Most bytes of text a tool result from the service may carry
+    pub fn output_limit(&self, name: &str) -> usize {
+        self.get_config(name)
+            .and_then(|config| config.max_output_bytes)
+            .or(self.max_output_bytes)
+            .unwrap_or(DEFAULT_MAX_TOOL_OUTPUT_BYTES)
+    }
+
+    /// Set a service's tool output limit, returning false if it is unknown
+    pub fn set_output_limit(&mut self, name: &str, max_bytes: Option<usize>) -> bool {
+        match self.services.get_mut(name) {
+            Some(managed) => {
+                managed.config.max_output_bytes = max_bytes;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set the tool output limit for services without their own; `None` restores the
+    /// default
+    pub fn set_default_output_limit(&mut self, max_bytes: Option<usize>) {
+        self.max_output_bytes = max_bytes;
+    }
+
+    /// Launch configuration of every running service
+    pub fn configs(&self) -> impl Iterator<Item = (&String, &ServiceConfig)> {
+        self.services
+            .iter()
+            .map(|(name, managed)| (name, &managed.config))
+    }
+
+    /// Register a start in progress, returning the token that cancels it.
+    ///
+    /// Returns `None` if the service is already starting.
+    pub fn begin_start(&mut self, name: &str) -> Option<CancellationToken> {
+        if self.pending_starts.contains_key(name) {
+            return None;
+        }
+        let token = CancellationToken::new();
+        self.pending_starts.insert(name.to_string(), token.clone());
+        Some(token)
+    }
+
+    pub fn finish_start(&mut self, name: &str) {
+        self.pending_starts.remove(name);
+    }
+
+    /// Cancel a start in progress, returning false if the service isn't starting
+    pub fn cancel_start(&mut self, name: &str) -> bool {
+        match self.pending_starts.remove(name) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
     }
 
     pub fn list_services(&self) -> Vec<String> {
         self.services.keys().cloned().collect()
     }
 
-    pub fn remove_service(&mut self, name: &str) -> Option<RunningService<RoleClient, ()>> {
-        self.services.remove(name)
+    /// OS process id of the service's child process
+    pub fn pid(&self, name: &str) -> Option<u32> {
+        self.services
+            .get(name)
+            .and_then(|managed| managed.process.as_ref())
+            .and_then(ServiceProcess::id)
+    }
+
+    /// Record a tool call on the service
+    pub fn mark_used(&mut self, name: &str) {
+        if let Some(managed) = self.services.get_mut(name) {
+            managed.last_used_ms = Some(now_ms());
+        }
+    }
+
+    /// Live state of every running service, sorted by name
+    pub fn details(&self) -> Vec<ServiceDetail> {
+        let mut details: Vec<ServiceDetail> = self
+            .services
+            .iter()
+            .map(|(name, managed)| ServiceDetail {
+                name: name.clone(),
+                pid: managed.process.as_ref().and_then(ServiceProcess::id),
+                // Services we didn't spawn are alive as long as they are connected
+                alive: managed
+                    .process
+                    .as_ref()
+                    .is_none_or(ServiceProcess::is_running),
+                started_at_ms: managed.started_at_ms,
+                tool_count: managed.tools.as_ref().map(Vec::len),
+                last_used_ms: managed.last_used_ms,
+                config: managed.config.clone(),
+            })
+            .collect();
+        details.sort_by(|a, b| a.name.cmp(&b.name));
+        details
+    }
+
+    /// Remove every service, handing back each with its child process
+    pub fn remove_all(&mut self) -> Vec<(String, McpService, Option<ServiceProcess>)> {
+        let names = self.list_services();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let (service, process) = self.remove_service(&name)?;
+                Some((name, service, process))
+            })
+            .collect()
+    }
+
+    /// Remove a service, handing back its child process so the caller decides when it
+    /// is killed (it is killed when dropped). The process's exit is recorded as expected.
+    pub fn remove_service(&mut self, name: &str) -> Option<(McpService, Option<ServiceProcess>)> {
+        self.services.remove(name).map(|managed| {
+            if let Some(process) = &managed.process {
+                process.expect_exit();
+            }
+            (managed.service, managed.process)
+        })
+    }
+
+    pub fn record_exit(&mut self, exit: ServiceExit) {
+        self.exits.insert(exit.service.clone(), exit);
+    }
+
+    /// Last exit of every service that has exited, including ones since restarted
+    pub fn exits(&self) -> impl Iterator<Item = &ServiceExit> {
+        self.exits.values()
+    }
+
+    /// How the service's process last exited, if it has exited since the app started
+    pub fn last_exit(&self, name: &str) -> Option<&ServiceExit> {
+        self.exits.get(name)
     }
 }
 
@@ -32,6 +373,16 @@ impl ServiceManager {
 pub struct ServiceResponse {
     pub success: bool,
     pub message: String,
+    /// Caveat the caller should surface, such as a protocol version mismatch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceRestartResult {
+    pub service_name: String,
+    pub success: bool,
+    pub message: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -41,9 +392,112 @@ pub struct ToolsResponse {
     pub message: String,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolsPageResponse {
+    pub success: bool,
+    pub tools: Vec<Tool>,
+    /// Cursor for the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+    pub message: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ToolCallResponse {
     pub success: bool,
     pub result: Option<CallToolResult>,
     pub message: String,
+    /// Whether the result's text was cut to the output limit
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResourceTemplatesResponse {
+    pub success: bool,
+    pub resource_templates: Vec<ResourceTemplate>,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompletionResponse {
+    pub success: bool,
+    pub completion: CompletionInfo,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompatibilityResponse {
+    pub success: bool,
+    pub compatibility: Compatibility,
+    pub message: String,
+}
+
+/// Outcome of stopping every service
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ShutdownReport {
+    /// Services that shut down gracefully
+    pub stopped: Vec<String>,
+    /// Services that didn't shut down within the drain timeout and were killed
+    pub forced: Vec<String>,
+    /// Services whose shutdown failed, with the error
+    pub failed: Vec<(String, String)>,
+}
+
+/// Live state of a running service, for a service management table
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceDetail {
+    pub name: String,
+    pub pid: Option<u32>,
+    pub alive: bool,
+    /// Unix time in milliseconds when the service was started
+    pub started_at_ms: u64,
+    /// Number of tools, once they have been listed
+    pub tool_count: Option<usize>,
+    /// Unix time in milliseconds of the last tool call
+    pub last_used_ms: Option<u64>,
+    pub config: ServiceConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub running: bool,
+    pub pid: Option<u32>,
+    /// Launch configuration, while the service is running
+    pub config: Option<ServiceConfig>,
+    pub last_exit: Option<ServiceExit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CapabilitiesResponse {
+    pub success: bool,
+    pub capabilities: ServiceCapabilities,
+    /// The capabilities object exactly as the server sent it
+    pub raw: Value,
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_from_a_poisoned_manager_lock() {
+        let services = Arc::new(Mutex::new(ServiceManager::default()));
+        lock_services(&services).set_default_output_limit(Some(10));
+
+        let poisoner = services.clone();
+        let panicked = std::thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("command panicked while holding the lock");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(services.is_poisoned());
+
+        // The state survives and the poison is cleared for later callers
+        assert_eq!(lock_services(&services).output_limit("any"), 10);
+        assert!(!services.is_poisoned());
+        assert!(services.lock().is_ok());
+    }
 }
@@ -0,0 +1,165 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Messages kept per service; older ones are dropped
+pub const MAX_TRACED_MESSAGES: usize = 500;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Sent by us to the server
+    Outgoing,
+    /// Received from the server
+    Incoming,
+}
+
+/// One JSON-RPC message exchanged with a service
+#[derive(Serialize, Debug, Clone)]
+pub struct TracedMessage {
+    pub direction: Direction,
+    pub timestamp_ms: u64,
+    /// The parsed message, or the raw line if it isn't JSON
+    pub message: Value,
+}
+
+/// Recent JSON-RPC traffic per service, recorded only while tracing is enabled
+#[derive(Default)]
+pub struct JsonRpcTrace {
+    enabled: AtomicBool,
+    logs: Mutex<HashMap<String, VecDeque<TracedMessage>>>,
+}
+
+impl JsonRpcTrace {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Turn tracing on or off; turning it off discards what was recorded
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            if let Ok(mut logs) = self.logs.lock() {
+                logs.clear();
+            }
+        }
+    }
+
+    /// Recorded messages of a service, oldest first
+    pub fn log(&self, service: &str) -> Vec<TracedMessage> {
+        let Ok(logs) = self.logs.lock() else {
+            return Vec::new();
+        };
+        logs.get(service)
+            .map(|log| log.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn record(&self, service: &str, direction: Direction, line: &[u8]) {
+        let message = serde_json::from_slice(line)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(line).into_owned()));
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+
+        let Ok(mut logs) = self.logs.lock() else {
+            return;
+        };
+        let log = logs.entry(service.to_string()).or_default();
+        if log.len() == MAX_TRACED_MESSAGES {
+            log.pop_front();
+        }
+        log.push_back(TracedMessage {
+            direction,
+            timestamp_ms,
+            message,
+        });
+    }
+}
+
+/// Wraps one side of a service's stdio, recording each newline-delimited message that
+/// passes through while tracing is enabled
+pub struct Tap<T> {
+    inner: T,
+    trace: Arc<JsonRpcTrace>,
+    service: String,
+    direction: Direction,
+    /// Bytes of a message whose newline hasn't passed through yet
+    line: Vec<u8>,
+}
+
+impl<T> Tap<T> {
+    pub fn new(inner: T, trace: Arc<JsonRpcTrace>, service: &str, direction: Direction) -> Self {
+        Self {
+            inner,
+            trace,
+            service: service.to_string(),
+            direction,
+            line: Vec::new(),
+        }
+    }
+
+    fn observe(&mut self, bytes: &[u8]) {
+        if !self.trace.is_enabled() {
+            self.line.clear();
+            return;
+        }
+        let mut rest = bytes;
+        while let Some(pos) = rest.iter().position(|&b| b == b'\n') {
+            self.line.extend_from_slice(&rest[..pos]);
+            let line = std::mem::take(&mut self.line);
+            if !line.iter().all(u8::is_ascii_whitespace) {
+                self.trace.record(&self.service, self.direction, &line);
+            }
+            rest = &rest[pos + 1..];
+        }
+        self.line.extend_from_slice(rest);
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Tap<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            this.observe(&buf.filled()[filled..]);
+        }
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Tap<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            this.observe(&buf[..written]);
+        }
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
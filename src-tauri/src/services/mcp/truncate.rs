@@ -0,0 +1,113 @@
+use log::warn;
+use rmcp::model::CallToolResult;
+use serde_json::Value;
+
+/// Text a tool result may carry when neither the service nor the app sets a limit
+pub const DEFAULT_MAX_TOOL_OUTPUT_BYTES: usize = 1024 * 1024;
+
+/// Cut the text content of a tool result down to `max_bytes` in total, marking where
+/// text was cut. Text items past the limit are dropped; other content is kept.
+///
+/// Returns whether anything was truncated.
+pub fn truncate_tool_output(result: &mut CallToolResult, max_bytes: usize) -> bool {
+    let Ok(Value::Array(mut items)) = serde_json::to_value(&result.content) else {
+        return false;
+    };
+    let total: usize = items.iter().filter_map(text_len).sum();
+    if total <= max_bytes {
+        return false;
+    }
+
+    let mut remaining = max_bytes;
+    let mut cut = false;
+    items.retain_mut(|item| {
+        let Some(Value::String(text)) = item.get_mut("text") else {
+            return true;
+        };
+        if cut {
+            return false;
+        }
+        if text.len() <= remaining {
+            remaining -= text.len();
+            return true;
+        }
+        let mut end = remaining;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str(&format!(
+            "\n[truncated: {} of {} bytes shown]",
+            max_bytes - remaining + end,
+            total
+        ));
+        cut = true;
+        true
+    });
+
+    match serde_json::from_value(Value::Array(items)) {
+        Ok(content) => {
+            result.content = content;
+            true
+        }
+        Err(e) => {
+            warn!("Failed to rebuild truncated tool output: {}", e);
+            false
+        }
+    }
+}
+
+fn text_len(item: &Value) -> Option<usize> {
+    item.get("text").and_then(Value::as_str).map(str::len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn result(content: Value) -> CallToolResult {
+        serde_json::from_value(json!({"content": content, "isError": false})).unwrap()
+    }
+
+    fn content(result: &CallToolResult) -> Value {
+        serde_json::to_value(&result.content).unwrap()
+    }
+
+    #[test]
+    fn leaves_output_within_the_limit_alone() {
+        let mut output = result(json!([{"type": "text", "text": "hello"}]));
+        assert!(!truncate_tool_output(&mut output, 5));
+        assert_eq!(content(&output), json!([{"type": "text", "text": "hello"}]));
+    }
+
+    #[test]
+    fn cuts_text_at_the_limit_and_keeps_other_content() {
+        let image = json!({"type": "image", "data": "aGk=", "mimeType": "image/png"});
+        let mut output = result(json!([
+            {"type": "text", "text": "hello "},
+            image,
+            {"type": "text", "text": "world"},
+            {"type": "text", "text": "!"},
+        ]));
+        assert!(truncate_tool_output(&mut output, 8));
+        assert_eq!(
+            content(&output),
+            json!([
+                {"type": "text", "text": "hello "},
+                image,
+                {"type": "text", "text": "wo\n[truncated: 8 of 12 bytes shown]"},
+            ])
+        );
+    }
+
+    #[test]
+    fn cuts_at_a_character_boundary() {
+        let mut output = result(json!([{"type": "text", "text": "héllo"}]));
+        assert!(truncate_tool_output(&mut output, 2));
+        assert_eq!(
+            content(&output),
+            json!([{"type": "text", "text": "h\n[truncated: 1 of 6 bytes shown]"}])
+        );
+    }
+}
@@ -1,2 +1,4 @@
+pub mod agent;
+pub mod config;
 pub mod mcp;
 pub mod proxy;
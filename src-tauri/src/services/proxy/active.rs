@@ -0,0 +1,331 @@
+use crate::services::proxy::partial::PartialText;
+use crate::services::proxy::pause::PauseControl;
+use crate::services::proxy::redirect::RedirectTarget;
+use crate::services::proxy::replay::ReplayBuffer;
+use crate::services::proxy::{EventSink, ProxyError, ProxyResult, StreamEvent};
+use log::info;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+
+/// Snapshot of a stream that is queued or in flight
+#[derive(Serialize, Debug, Clone)]
+pub struct ActiveStreamInfo {
+    pub id: String,
+    pub provider: String,
+    pub model: Option<String>,
+    /// Unix time in milliseconds when the request was received
+    pub started_at_ms: u64,
+    pub bytes_streamed: u64,
+    pub paused: bool,
+}
+
+/// Outcome of `cancel_stream`
+#[derive(Serialize, Debug, Clone)]
+pub struct CancelledStream {
+    /// False if no such stream was active
+    pub cancelled: bool,
+    /// Text generated before the cancellation, for streams started with `keep_partial`
+    pub partial_text: Option<String>,
+}
+
+struct ActiveStream {
+    provider: String,
+    model: Option<String>,
+    started_at_ms: u64,
+    bytes_streamed: Arc<AtomicU64>,
+    cancel: CancellationToken,
+    pause: Arc<PauseControl>,
+    replay: Arc<ReplayBuffer>,
+    redirect: Arc<RedirectTarget>,
+    partial: Arc<PartialText>,
+}
+
+/// Registry of streams that are queued or in flight
+#[derive(Default)]
+pub struct ActiveStreams {
+    streams: Arc<Mutex<HashMap<String, ActiveStream>>>,
+    next_id: AtomicU64,
+}
+
+impl ActiveStreams {
+    /// Register a stream, using the caller's id if given. The entry is removed when the
+    /// returned registration is dropped.
+    ///
+    /// Fails if a stream with the same id is still active; both would share one entry,
+    /// and the first to finish would remove the other's.
+    pub fn register(
+        &self,
+        id: Option<String>,
+        provider: &str,
+        model: Option<&str>,
+    ) -> ProxyResult<ActiveStreamRegistration> {
+        let id = id.unwrap_or_else(|| {
+            format!(
+                "stream-{}",
+                self.next_id.fetch_add(1, Ordering::Relaxed) + 1
+            )
+        });
+        let started_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        let bytes_streamed = Arc::new(AtomicU64::new(0));
+        let cancel = CancellationToken::new();
+        let pause = Arc::new(PauseControl::default());
+        let replay = Arc::new(ReplayBuffer::default());
+        let redirect = Arc::new(RedirectTarget::default());
+        let partial = Arc::new(PartialText::default());
+
+        let mut streams = self
+            .streams
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if streams.contains_key(&id) {
+            return Err(ProxyError::DuplicateStream(id));
+        }
+        streams.insert(
+            id.clone(),
+            ActiveStream {
+                provider: provider.to_string(),
+                model: model.map(str::to_string),
+                started_at_ms,
+                bytes_streamed: bytes_streamed.clone(),
+                cancel: cancel.clone(),
+                pause: pause.clone(),
+                replay: replay.clone(),
+                redirect: redirect.clone(),
+                partial: partial.clone(),
+            },
+        );
+        drop(streams);
+
+        Ok(ActiveStreamRegistration {
+            id,
+            bytes_streamed,
+            cancel,
+            pause,
+            replay,
+            redirect,
+            partial,
+            streams: self.streams.clone(),
+        })
+    }
+
+    pub fn list(&self) -> Vec<ActiveStreamInfo> {
+        let Ok(streams) = self.streams.lock() else {
+            return Vec::new();
+        };
+        let mut active: Vec<ActiveStreamInfo> = streams
+            .iter()
+            .map(|(id, stream)| ActiveStreamInfo {
+                id: id.clone(),
+                provider: stream.provider.clone(),
+                model: stream.model.clone(),
+                started_at_ms: stream.started_at_ms,
+                bytes_streamed: stream.bytes_streamed.load(Ordering::Relaxed),
+                paused: stream.pause.is_paused(),
+            })
+            .collect();
+        active.sort_by_key(|stream| stream.started_at_ms);
+        active
+    }
+
+    /// Number of active streams without waiting on the lock; `None` if it is held
+    /// elsewhere
+    pub fn try_count(&self) -> Option<usize> {
+        self.streams.try_lock().ok().map(|streams| streams.len())
+    }
+
+    /// Cancel a stream, returning the text it generated if it was keeping it
+    pub fn cancel(&self, id: &str) -> CancelledStream {
+        let partial = self.streams.lock().ok().and_then(|streams| {
+            let stream = streams.get(id)?;
+            info!("Cancelling stream {}", id);
+            stream.cancel.cancel();
+            Some(stream.partial.clone())
+        });
+        CancelledStream {
+            cancelled: partial.is_some(),
+            partial_text: partial.and_then(|partial| partial.text()),
+        }
+    }
+
+    /// Recent events of an active stream, oldest first, or `None` if it isn't active
+    pub fn replay(&self, id: &str) -> Option<Vec<StreamEvent>> {
+        let streams = self.streams.lock().ok()?;
+        streams.get(id).map(|stream| stream.replay.events())
+    }
+
+    /// Send the rest of a stream's events to `sink` instead of its original destination,
+    /// first replaying the buffered events to it. Returns the number replayed, or `None`
+    /// if no such stream is active.
+    pub fn reattach(&self, id: &str, sink: Box<dyn EventSink>) -> ProxyResult<Option<usize>> {
+        let stream = self.streams.lock().ok().and_then(|streams| {
+            streams
+                .get(id)
+                .map(|stream| (stream.redirect.clone(), stream.replay.clone()))
+        });
+        let Some((redirect, replay)) = stream else {
+            return Ok(None);
+        };
+        info!("Reattaching stream {}", id);
+        redirect.redirect(sink, &replay).map(Some)
+    }
+
+    /// Hold back a stream's events until it is resumed, returning false if no such
+    /// stream is active
+    pub fn pause(&self, id: &str) -> bool {
+        self.with_pause(id, |pause| {
+            info!("Pausing stream {}", id);
+            pause.pause();
+        })
+    }
+
+    /// Flush and resume a paused stream, returning false if no such stream is active
+    pub fn resume(&self, id: &str) -> bool {
+        self.with_pause(id, |pause| {
+            info!("Resuming stream {}", id);
+            pause.resume();
+        })
+    }
+
+    fn with_pause(&self, id: &str, f: impl FnOnce(&PauseControl)) -> bool {
+        let Ok(streams) = self.streams.lock() else {
+            return false;
+        };
+        match streams.get(id) {
+            Some(stream) => {
+                f(&stream.pause);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A stream's entry in [`ActiveStreams`], removed when dropped
+pub struct ActiveStreamRegistration {
+    id: String,
+    bytes_streamed: Arc<AtomicU64>,
+    cancel: CancellationToken,
+    pause: Arc<PauseControl>,
+    replay: Arc<ReplayBuffer>,
+    redirect: Arc<RedirectTarget>,
+    partial: Arc<PartialText>,
+    streams: Arc<Mutex<HashMap<String, ActiveStream>>>,
+}
+
+impl ActiveStreamRegistration {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Counter the provider loop adds received bytes to
+    pub fn bytes_streamed(&self) -> Arc<AtomicU64> {
+        self.bytes_streamed.clone()
+    }
+
+    pub fn cancel_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    pub fn pause_control(&self) -> Arc<PauseControl> {
+        self.pause.clone()
+    }
+
+    /// Buffer of the stream's recent events, dropped with the registration
+    pub fn replay_buffer(&self) -> &ReplayBuffer {
+        &self.replay
+    }
+
+    /// Where the stream's events go once it is reattached
+    pub fn redirect_target(&self) -> &RedirectTarget {
+        &self.redirect
+    }
+
+    /// Text the stream has delivered, collected only while a sink is keeping it
+    pub fn partial_text(&self) -> &PartialText {
+        &self.partial
+    }
+}
+
+impl Drop for ActiveStreamRegistration {
+    fn drop(&mut self) {
+        if let Ok(mut streams) = self.streams.lock() {
+            streams.remove(&self.id);
+        }
+    }
+}
+
+/// Handle to a stream started over a channel, held in the window's resource table.
+///
+/// Closing the resource from the frontend drops the handle and cancels the stream. This
+/// is independent of the registry: `cancel_stream` still works on the same stream, and
+/// whichever comes first wins.
+#[derive(Default)]
+pub struct StreamHandle {
+    cancel: CancellationToken,
+}
+
+impl StreamHandle {
+    /// Token cancelled when the handle is dropped
+    pub fn cancel_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+}
+
+impl tauri::Resource for StreamHandle {}
+
+impl Drop for StreamHandle {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_an_id_that_is_still_active() {
+        let active = ActiveStreams::default();
+        let first = active
+            .register(Some("chat".to_string()), "openai", Some("gpt-4o"))
+            .unwrap();
+        let duplicate = active.register(Some("chat".to_string()), "anthropic", None);
+        assert!(matches!(duplicate, Err(ProxyError::DuplicateStream(id)) if id == "chat"));
+
+        // The rejected registration leaves the first stream's entry alone
+        let listed = active.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].provider, "openai");
+
+        drop(first);
+        assert!(active.list().is_empty());
+        let reused = active.register(Some("chat".to_string()), "anthropic", None);
+        assert!(reused.is_ok());
+    }
+
+    #[test]
+    fn generates_distinct_ids_and_removes_entries_on_drop() {
+        let active = ActiveStreams::default();
+        let first = active.register(None, "openai", None).unwrap();
+        let second = active.register(None, "openai", None).unwrap();
+        assert_ne!(first.id(), second.id());
+        assert_eq!(active.list().len(), 2);
+
+        let cancelled = active.cancel(first.id());
+        assert!(cancelled.cancelled);
+        assert!(first.cancel_token().is_cancelled());
+        assert!(!second.cancel_token().is_cancelled());
+
+        drop(first);
+        drop(second);
+        assert!(active.list().is_empty());
+        assert!(!active.cancel("stream-1").cancelled);
+    }
+}
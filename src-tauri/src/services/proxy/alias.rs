@@ -0,0 +1,87 @@
+use crate::services::proxy::{EventSink, ProxyResult, StreamEvent};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// The provider and model an alias stands for
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModelAlias {
+    pub provider: String,
+    pub model: String,
+}
+
+/// Friendly model names, such as `fast`, mapped to a provider's model id so the
+/// frontend can offer stable names while the mapping is managed here
+#[derive(Default)]
+pub struct ModelAliases {
+    aliases: Mutex<HashMap<String, ModelAlias>>,
+}
+
+impl ModelAliases {
+    /// Point `alias` at a provider's model, replacing any previous target
+    pub fn set(&self, alias: &str, target: ModelAlias) {
+        if let Ok(mut aliases) = self.aliases.lock() {
+            aliases.insert(alias.to_string(), target);
+        }
+    }
+
+    /// Remove an alias, returning whether it existed
+    pub fn remove(&self, alias: &str) -> bool {
+        self.aliases
+            .lock()
+            .map(|mut aliases| aliases.remove(alias).is_some())
+            .unwrap_or(false)
+    }
+
+    /// What `model` stands for, if it is an alias
+    pub fn resolve(&self, model: &str) -> Option<ModelAlias> {
+        self.aliases.lock().ok()?.get(model).cloned()
+    }
+
+    /// Every alias with its target, sorted by alias
+    pub fn list(&self) -> Vec<(String, ModelAlias)> {
+        let mut aliases: Vec<(String, ModelAlias)> = self
+            .aliases
+            .lock()
+            .map(|aliases| {
+                aliases
+                    .iter()
+                    .map(|(alias, target)| (alias.clone(), target.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        aliases.sort_by(|a, b| a.0.cmp(&b.0));
+        aliases
+    }
+}
+
+/// Stamps the alias a request named, and the model it resolved to, on the start event
+pub(crate) struct ModelAliasSink<'a> {
+    inner: &'a dyn EventSink,
+    alias: String,
+    model: String,
+}
+
+impl<'a> ModelAliasSink<'a> {
+    pub(crate) fn new(inner: &'a dyn EventSink, alias: &str, model: &str) -> Self {
+        Self {
+            inner,
+            alias: alias.to_string(),
+            model: model.to_string(),
+        }
+    }
+}
+
+impl EventSink for ModelAliasSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        match event {
+            StreamEvent::Start(mut start) => {
+                start.model_alias = Some(self.alias.clone());
+                // A model the provider reports is more precise, such as a dated snapshot
+                start.model.get_or_insert_with(|| self.model.clone());
+                self.inner.emit(StreamEvent::Start(start))
+            }
+            event => self.inner.emit(event),
+        }
+    }
+}
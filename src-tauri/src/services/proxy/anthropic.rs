@@ -1,15 +1,18 @@
-use crate::services::proxy::{emit_chunk, emit_end, emit_error};
-use crate::services::proxy::{ProxyError, ProxyProvider, ProxyResult};
+use crate::services::proxy::sse::{is_event_stream, read_sse, SseEvent};
+use crate::services::proxy::{
+    apply_extra_headers, check_status, emit_citation, emit_end, emit_filtered, emit_incomplete,
+    emit_raw, emit_role, emit_start, emit_structured_error, emit_text, emit_tool_call,
+    emit_tool_delta, emit_usage, http_client, read_json_body, redact_secrets, send_request,
+};
+use crate::services::proxy::{
+    ErrorKind, EventSink, FinishReason, ProxyError, ProxyProvider, ProxyResult, StreamCitation,
+    StreamOptions, StreamStart, StreamUsage, ToolCallAccumulator, DEFAULT_ANTHROPIC_VERSION,
+};
 use async_trait::async_trait;
-use futures_util::StreamExt;
 use log::{debug, error, info, warn};
 use serde::Deserialize;
 use serde_json::Value;
-use tauri::Window;
-use tauri_plugin_http::reqwest::{
-    self,
-    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
-};
+use tauri_plugin_http::reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
 
 pub struct AnthropicProvider {
     api_key: String,
@@ -26,12 +29,33 @@ struct AnthropicEvent {
     #[serde(rename = "type")]
     event_type: String,
     delta: Option<AnthropicDelta>,
-    #[allow(dead_code)]
-    message: Option<Value>,
-    usage: Option<Value>,
+    message: Option<AnthropicMessage>,
+    usage: Option<AnthropicUsage>,
     error: Option<AnthropicError>,
-    #[allow(dead_code)]
     index: Option<u32>,
+    content_block: Option<AnthropicContentBlock>,
+}
+
+/// The message envelope sent with `message_start`
+#[derive(Deserialize, Debug)]
+struct AnthropicMessage {
+    model: Option<String>,
+    role: Option<String>,
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct AnthropicUsage {
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicContentBlock {
+    #[serde(rename = "type")]
+    block_type: String,
+    id: Option<String>,
+    name: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -39,6 +63,10 @@ struct AnthropicDelta {
     #[serde(rename = "type")]
     delta_type: Option<String>,
     text: Option<String>,
+    partial_json: Option<String>,
+    stop_reason: Option<String>,
+    /// Sent with `citations_delta`
+    citation: Option<Value>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -48,149 +76,812 @@ struct AnthropicError {
     message: String,
 }
 
+/// Whether the request enables a feature that makes Claude cite sources: a server-side
+/// search tool, or documents and search results with citations turned on
+fn citations_requested(body: &Value) -> bool {
+    let tools = body.get("tools").and_then(Value::as_array);
+    let search_tool = tools.into_iter().flatten().any(|tool| {
+        tool.get("type")
+            .and_then(Value::as_str)
+            .is_some_and(|tool_type| {
+                tool_type.starts_with("web_search") || tool_type.starts_with("web_fetch")
+            })
+    });
+
+    let messages = body.get("messages").and_then(Value::as_array);
+    let cited_content = messages
+        .into_iter()
+        .flatten()
+        .filter_map(|message| message.get("content").and_then(Value::as_array))
+        .flatten()
+        .any(|block| block.pointer("/citations/enabled") == Some(&Value::Bool(true)));
+
+    search_tool || cited_content
+}
+
+/// Error event types after which Anthropic sends nothing more for the message
+const FATAL_ERROR_TYPES: &[&str] = &["overloaded_error", "api_error"];
+
+/// Parsing state for a single Anthropic message stream
+#[derive(Default)]
+struct AnthropicStream {
+    /// Whether citations are parsed and emitted
+    citations: bool,
+    /// Characters of text emitted so far
+    text_chars: usize,
+    /// `text_chars` when the current content block started
+    block_text_offset: usize,
+    finish_reason: Option<FinishReason>,
+    /// Raw reason reported when the provider blocked the output
+    filtered_reason: Option<String>,
+    tool_calls: ToolCallAccumulator,
+    /// Whether the terminal `message_stop` marker was seen, or the stream closed cleanly
+    /// in a way treated as an implicit stop
+    completed: bool,
+    /// Whether `message_start` was seen
+    started: bool,
+    /// Content blocks started but not yet stopped
+    open_blocks: usize,
+    /// Usage reported so far, emitted once when the message stops
+    usage: Option<StreamUsage>,
+}
+
+impl AnthropicStream {
+    fn handle_event(&mut self, event: SseEvent, sink: &dyn EventSink) -> ProxyResult<()> {
+        if event.data.is_empty() {
+            debug!("Skipping event block - no data line found");
+            return Ok(());
+        }
+
+        let event = match serde_json::from_str::<AnthropicEvent>(&event.data) {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Failed to parse data as JSON event: {}", e);
+                return Ok(());
+            }
+        };
+
+        debug!("Parsed event type: {}", event.event_type);
+        match event.event_type.as_str() {
+            "message_start" => {
+                debug!("Processing message_start event");
+                self.started = true;
+                let Some(message) = event.message else {
+                    return emit_start(sink, StreamStart::default());
+                };
+                if let Some(usage) = &message.usage {
+                    self.record_usage(usage);
+                }
+                emit_start(
+                    sink,
+                    StreamStart {
+                        model: message.model,
+                        input_tokens: message.usage.and_then(|usage| usage.input_tokens),
+                        ..Default::default()
+                    },
+                )?;
+                if let Some(role) = message.role {
+                    emit_role(sink, role)?;
+                }
+            }
+            "content_block_start" => {
+                self.open_blocks += 1;
+                self.block_text_offset = self.text_chars;
+                if let Some(block) = event.content_block {
+                    if block.block_type == "tool_use" {
+                        self.tool_calls.start(
+                            event.index.unwrap_or_default(),
+                            block.id,
+                            block.name,
+                        );
+                    }
+                }
+            }
+            "content_block_delta" => {
+                if let Some(delta) = event.delta {
+                    match delta.delta_type.as_deref() {
+                        Some("text_delta") => {
+                            if let Some(text) = delta.text {
+                                self.text_chars += text.chars().count();
+                                emit_text(sink, text)?;
+                            }
+                        }
+                        Some("citations_delta") if self.citations => {
+                            if let Some(citation) = delta.citation {
+                                emit_citation(
+                                    sink,
+                                    StreamCitation {
+                                        index: event.index.unwrap_or_default(),
+                                        text_offset: self.block_text_offset,
+                                        citation,
+                                    },
+                                )?;
+                            }
+                        }
+                        Some("input_json_delta") => {
+                            if let Some(fragment) = delta.partial_json {
+                                let tool_delta = self.tool_calls.push(
+                                    event.index.unwrap_or_default(),
+                                    None,
+                                    None,
+                                    &fragment,
+                                );
+                                emit_tool_delta(sink, tool_delta)?;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            "content_block_stop" => {
+                self.open_blocks = self.open_blocks.saturating_sub(1);
+                if let Some(call) = self.tool_calls.finish(event.index.unwrap_or_default()) {
+                    emit_tool_call(sink, call)?;
+                }
+            }
+            "message_delta" => {
+                if let Some(reason) = event.delta.and_then(|delta| delta.stop_reason) {
+                    debug!("Message stopped with reason: {}", reason);
+                    self.finish_reason = Some(FinishReason::from_anthropic(&reason));
+                    if self.finish_reason == Some(FinishReason::ContentFilter) {
+                        self.filtered_reason = Some(reason);
+                    }
+                }
+                if let Some(usage) = event.usage {
+                    debug!("Message_delta with usage metrics received");
+                    self.record_usage(&usage);
+                }
+            }
+            "message_stop" => {
+                debug!("Message_stop event received");
+                self.completed = true;
+                if let Some(usage) = event.usage {
+                    debug!("Final usage data received");
+                    self.record_usage(&usage);
+                }
+                self.flush_usage(sink)?;
+            }
+            "error" => {
+                if let Some(error_details) = event.error {
+                    let err_msg = format!(
+                        "API Error Event: [{}] {}",
+                        error_details.error_type, error_details.message
+                    );
+                    error!("{}", err_msg);
+                    let (kind, retryable) = match error_details.error_type.as_str() {
+                        "authentication_error" | "permission_error" => (ErrorKind::Auth, false),
+                        "rate_limit_error" => (ErrorKind::RateLimit, true),
+                        "overloaded_error" | "api_error" => (ErrorKind::Upstream, true),
+                        _ => (ErrorKind::Upstream, false),
+                    };
+                    emit_structured_error(sink, kind, err_msg, retryable)?;
+
+                    // The server gives up on the message, so the error ends the stream in
+                    // place of an end event
+                    if FATAL_ERROR_TYPES.contains(&error_details.error_type.as_str()) {
+                        return Err(ProxyError::StreamFailed {
+                            error_type: error_details.error_type,
+                            message: error_details.message,
+                        });
+                    }
+                }
+            }
+            "ping" => {
+                debug!("Ping event ignored");
+            }
+            _ => warn!("Unknown event type: {}", event.event_type),
+        }
+        Ok(())
+    }
+
+    /// Merge token counts from a usage object into the buffered usage
+    fn record_usage(&mut self, usage: &AnthropicUsage) {
+        let buffered = self.usage.get_or_insert_with(|| StreamUsage {
+            provider: "anthropic".to_string(),
+            ..Default::default()
+        });
+        if usage.input_tokens.is_some() {
+            buffered.input_tokens = usage.input_tokens;
+        }
+        if usage.output_tokens.is_some() {
+            buffered.output_tokens = usage.output_tokens;
+        }
+        buffered.total_tokens = buffered
+            .input_tokens
+            .zip(buffered.output_tokens)
+            .map(|(i, o)| i + o);
+    }
+
+    /// Emit the buffered usage, if any
+    fn flush_usage(&mut self, sink: &dyn EventSink) -> ProxyResult<()> {
+        match self.usage.take() {
+            Some(usage) => emit_usage(sink, usage),
+            None => Ok(()),
+        }
+    }
+
+    /// Treat a connection that closed cleanly between content blocks as an implicit
+    /// `message_stop`, as some Anthropic-compatible proxies never send one
+    fn close(&mut self) {
+        if !self.completed && self.started && self.open_blocks == 0 {
+            warn!("Anthropic stream closed without message_stop; treating as stopped");
+            self.completed = true;
+        }
+    }
+
+    /// Handle a non-streaming `message` body as a complete response
+    fn handle_complete(&mut self, body: Value, sink: &dyn EventSink) -> ProxyResult<()> {
+        self.started = true;
+        let usage = body
+            .get("usage")
+            .cloned()
+            .and_then(|usage| serde_json::from_value::<AnthropicUsage>(usage).ok());
+        emit_start(
+            sink,
+            StreamStart {
+                model: body
+                    .get("model")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                input_tokens: usage.as_ref().and_then(|usage| usage.input_tokens),
+                ..Default::default()
+            },
+        )?;
+        if let Some(role) = body.get("role").and_then(Value::as_str) {
+            emit_role(sink, role.to_string())?;
+        }
+
+        let blocks = body.get("content").and_then(Value::as_array);
+        for (index, block) in blocks.into_iter().flatten().enumerate() {
+            let index = index as u32;
+            match block.get("type").and_then(Value::as_str) {
+                Some("text") => {
+                    let text_offset = self.text_chars;
+                    if let Some(text) = block.get("text").and_then(Value::as_str) {
+                        self.text_chars += text.chars().count();
+                        emit_text(sink, text)?;
+                    }
+                    let citations = block
+                        .get("citations")
+                        .and_then(Value::as_array)
+                        .filter(|_| self.citations);
+                    for citation in citations.into_iter().flatten() {
+                        emit_citation(
+                            sink,
+                            StreamCitation {
+                                index,
+                                text_offset,
+                                citation: citation.clone(),
+                            },
+                        )?;
+                    }
+                }
+                Some("tool_use") => {
+                    let id = block.get("id").and_then(Value::as_str).map(str::to_string);
+                    let name = block
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .map(str::to_string);
+                    let input = block.get("input").cloned().unwrap_or(Value::Null);
+                    self.tool_calls.push(index, id, name, &input.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(reason) = body.get("stop_reason").and_then(Value::as_str) {
+            self.finish_reason = Some(FinishReason::from_anthropic(reason));
+            if self.finish_reason == Some(FinishReason::ContentFilter) {
+                self.filtered_reason = Some(reason.to_string());
+            }
+        }
+        if let Some(usage) = &usage {
+            self.record_usage(usage);
+        }
+        self.completed = true;
+        Ok(())
+    }
+
+    /// Emit pending tool calls, flagged incomplete if the stream was cut off
+    fn flush_tool_calls(&mut self, sink: &dyn EventSink) -> ProxyResult<()> {
+        let calls = if self.completed {
+            self.tool_calls.finish_all()
+        } else {
+            self.tool_calls.finish_all_incomplete()
+        };
+        for call in calls {
+            emit_tool_call(sink, call)?;
+        }
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl ProxyProvider for AnthropicProvider {
-    async fn stream(&self, window: Window, body: Value) -> ProxyResult<()> {
+    async fn stream(
+        &self,
+        sink: &dyn EventSink,
+        body: Value,
+        options: StreamOptions,
+    ) -> ProxyResult<()> {
         info!("Starting Anthropic stream request");
-        let client = reqwest::Client::new();
+        let client = http_client()?;
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+        let version = options
+            .anthropic_version
+            .as_deref()
+            .unwrap_or(DEFAULT_ANTHROPIC_VERSION);
+        headers.insert(
+            "anthropic-version",
+            HeaderValue::from_str(version).map_err(|e| {
+                ProxyError::Header(format!("Invalid anthropic-version '{}': {}", version, e))
+            })?,
+        );
+        if !options.anthropic_beta.is_empty() {
+            let beta = options.anthropic_beta.join(",");
+            headers.insert(
+                "anthropic-beta",
+                HeaderValue::from_str(&beta).map_err(|e| {
+                    ProxyError::Header(format!("Invalid anthropic-beta '{}': {}", beta, e))
+                })?,
+            );
+        }
         headers.insert(
             "x-api-key",
             HeaderValue::from_str(&self.api_key).map_err(|e| {
                 ProxyError::ApiKey(format!("Invalid Anthropic API key format: {}", e))
             })?,
         );
+        apply_extra_headers(&mut headers, &options);
 
-        let response = client
+        let request = client
             .post("https://api.anthropic.com/v1/messages")
             .headers(headers)
-            .json(&body)
-            .send()
-            .await?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_body = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Failed to read error body".to_string());
-            let error_msg = format!(
-                "Anthropic API request failed with status {}: {}",
-                status, error_body
-            );
-            emit_error(&window, &error_msg)?;
-            return Err(ProxyError::Status(status.as_u16()));
-        }
-        info!("Anthropic API request successful (status: {})", status);
-
-        let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
-
-        debug!("Starting to process Anthropic stream");
-        while let Some(item) = stream.next().await {
-            match item {
-                Ok(chunk) => {
-                    debug!("Received raw bytes chunk: {} bytes", chunk.len());
-                    match String::from_utf8(chunk.to_vec()) {
-                        Ok(chunk_string) => {
-                            buffer.push_str(&chunk_string);
-
-                            while let Some(pos) = buffer.find("\n\n") {
-                                let event_data = buffer[..pos].trim().to_string();
-                                buffer = buffer[pos + 2..].to_string(); // Skip "\n\n"
-
-                                let mut data_line = "";
-                                for line in event_data.lines() {
-                                    if let Some(stripped) = line.strip_prefix("data: ") {
-                                        data_line = stripped;
-                                    }
-                                }
-
-                                if data_line.is_empty() {
-                                    debug!("Skipping event block - no data line found");
-                                    continue;
-                                }
-
-                                match serde_json::from_str::<AnthropicEvent>(data_line) {
-                                    Ok(event) => {
-                                        debug!("Parsed event type: {}", event.event_type);
-                                        match event.event_type.as_str() {
-                                            "message_start" => {
-                                                debug!("Processing message_start event");
-                                            }
-                                            "content_block_delta" => {
-                                                if let Some(delta) = event.delta {
-                                                    if delta.delta_type.as_deref()
-                                                        == Some("text_delta")
-                                                    {
-                                                        if let Some(text) = delta.text {
-                                                            let text_json =
-                                                                serde_json::to_string(&text)
-                                                                    .map_err(ProxyError::Parse)?;
-                                                            emit_chunk(
-                                                                &window,
-                                                                format!("0:{}\n", text_json),
-                                                            )?;
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            "message_delta" => {
-                                                if let Some(_usage) = event.usage {
-                                                    debug!(
-                                                        "Message_delta with usage metrics received"
-                                                    );
-                                                }
-                                            }
-                                            "message_stop" => {
-                                                debug!("Message_stop event received");
-                                                if let Some(_usage) = event.usage {
-                                                    debug!("Final usage data received");
-                                                }
-                                            }
-                                            "error" => {
-                                                if let Some(error_details) = event.error {
-                                                    let err_msg = format!(
-                                                        "API Error Event: [{}] {}",
-                                                        error_details.error_type,
-                                                        error_details.message
-                                                    );
-                                                    error!("{}", err_msg);
-                                                    emit_error(&window, &err_msg)?;
-                                                }
-                                            }
-                                            "ping" => {
-                                                debug!("Ping event ignored");
-                                            }
-                                            _ => warn!("Unknown event type: {}", event.event_type),
-                                        }
-                                    }
-                                    Err(e) => {
-                                        warn!("Failed to parse data as JSON event: {}", e);
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let error_msg = format!("Failed to decode chunk as UTF-8: {}", e);
-                            error!("{}", error_msg);
-                            emit_error(&window, &error_msg)?;
-                        }
-                    }
-                }
-                Err(e) => {
-                    let error_msg = format!("Error reading stream chunk: {}", e);
-                    error!("{}", error_msg);
-                    emit_error(&window, &error_msg)?;
-                    return Err(ProxyError::Http(e));
-                }
+            .json(&body);
+        // Kept to resume the stream if the connection drops
+        let resume_request = request.try_clone();
+        let response = send_request(request, &options, "Anthropic").await?;
+        let response = check_status(response, sink, "anthropic", "Anthropic").await?;
+
+        let mut state = AnthropicStream {
+            citations: citations_requested(&body),
+            ..Default::default()
+        };
+        let raw = if is_event_stream(&response) {
+            debug!("Starting to process Anthropic stream");
+            let read = read_sse(response, resume_request, sink, &options, |event| {
+                state.handle_event(event, sink)
+            })
+            .await;
+            if read.is_ok() {
+                state.close();
             }
-        }
 
+            // Partial tool calls are surfaced even when the stream failed
+            state.flush_tool_calls(sink)?;
+            read?
+        } else {
+            let body = read_json_body(response, sink, "Anthropic").await?;
+            let raw = if options.capture_raw {
+                body.to_string()
+            } else {
+                String::new()
+            };
+            state.handle_complete(body, sink)?;
+            state.flush_tool_calls(sink)?;
+            raw
+        };
+        state.flush_usage(sink)?;
         info!("Anthropic stream completed");
-        emit_end(&window)?;
+        if options.capture_raw {
+            emit_raw(sink, redact_secrets(&raw, &self.api_key))?;
+        }
+        if let Some(reason) = state.filtered_reason.take() {
+            emit_filtered(sink, "anthropic", reason)?;
+        }
+        if !state.completed {
+            emit_incomplete(sink, "anthropic")?;
+        }
+        emit_end(sink, state.finish_reason)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::proxy::test_support::{
+        data_event, MockResponse, MockServer, RecordingSink,
+    };
+    use crate::services::proxy::StreamEvent;
+    use serde_json::json;
+    use tauri_plugin_http::reqwest;
+
+    /// Feed each event to the stream in order
+    fn feed(
+        state: &mut AnthropicStream,
+        sink: &RecordingSink,
+        events: &[Value],
+    ) -> ProxyResult<()> {
+        for event in events {
+            state.handle_event(data_event(&event.to_string()), sink)?;
+        }
         Ok(())
     }
+
+    #[test]
+    fn records_the_reason_for_a_refusal() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        let refused = json!({"type": "message_delta", "delta": {"stop_reason": "refusal"}});
+        feed(&mut state, &sink, &[refused]).unwrap();
+        assert_eq!(state.finish_reason, Some(FinishReason::ContentFilter));
+        assert_eq!(state.filtered_reason.as_deref(), Some("refusal"));
+
+        let mut state = AnthropicStream::default();
+        let stopped = json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}});
+        feed(&mut state, &sink, &[stopped]).unwrap();
+        assert_eq!(state.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(state.filtered_reason, None);
+    }
+
+    #[test]
+    fn completes_only_on_message_stop() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        feed(
+            &mut state,
+            &sink,
+            &[
+                json!({"type": "message_start", "message": {"role": "assistant"}}),
+                json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text"}}),
+            ],
+        )
+        .unwrap();
+        assert!(!state.completed);
+
+        feed(
+            &mut state,
+            &sink,
+            &[
+                json!({"type": "content_block_stop", "index": 0}),
+                json!({"type": "message_stop"}),
+            ],
+        )
+        .unwrap();
+        assert!(state.completed);
+    }
+
+    fn usage(sink: &RecordingSink) -> Vec<StreamUsage> {
+        sink.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::Usage(usage) => Some(usage),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn emits_merged_usage_once_the_message_stops() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        feed(
+            &mut state,
+            &sink,
+            &[
+                json!({"type": "message_start", "message": {"usage": {"input_tokens": 25, "output_tokens": 1}}}),
+                json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 15}}),
+            ],
+        )
+        .unwrap();
+        assert!(usage(&sink).is_empty());
+
+        feed(&mut state, &sink, &[json!({"type": "message_stop"})]).unwrap();
+        let usage = usage(&sink);
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].provider, "anthropic");
+        assert_eq!(usage[0].input_tokens, Some(25));
+        assert_eq!(usage[0].output_tokens, Some(15));
+        assert_eq!(usage[0].total_tokens, Some(40));
+    }
+
+    #[test]
+    fn reports_model_and_prompt_tokens_at_message_start() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        let event = data_event(
+            r#"{"type":"message_start","message":{"id":"msg_01XFDUDYJgAACzvnptvVoYEL","type":"message","role":"assistant","content":[],"model":"claude-sonnet-4-5-20250929","stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":472,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":2}}}"#,
+        );
+        state.handle_event(event, &sink).unwrap();
+
+        let events = sink.events();
+        assert!(matches!(
+            &events[..],
+            [StreamEvent::Start(start), StreamEvent::Role { role }]
+                if start.model.as_deref() == Some("claude-sonnet-4-5-20250929")
+                    && start.input_tokens == Some(472)
+                    && role == "assistant"
+        ));
+
+        feed(&mut state, &sink, &[json!({"type": "message_stop"})]).unwrap();
+        let usage = usage(&sink);
+        assert_eq!(usage[0].input_tokens, Some(472));
+        assert_eq!(usage[0].output_tokens, Some(2));
+    }
+
+    #[test]
+    fn emits_the_role_after_the_start_event() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        let start = json!({"type": "message_start", "message": {"role": "assistant"}});
+        feed(&mut state, &sink, &[start]).unwrap();
+
+        let events = sink.events();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], StreamEvent::Start(_)));
+        assert!(matches!(&events[1], StreamEvent::Role { role } if role == "assistant"));
+    }
+
+    #[test]
+    fn treats_a_clean_close_between_blocks_as_a_stop() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        feed(
+            &mut state,
+            &sink,
+            &[
+                json!({"type": "message_start", "message": {"usage": {"input_tokens": 5}}}),
+                json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text"}}),
+                json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hi"}}),
+                json!({"type": "content_block_stop", "index": 0}),
+            ],
+        )
+        .unwrap();
+        state.close();
+        assert!(state.completed);
+        // Usage held for message_stop is still delivered
+        state.flush_usage(&sink).unwrap();
+        assert_eq!(usage(&sink)[0].input_tokens, Some(5));
+    }
+
+    #[test]
+    fn does_not_treat_a_close_inside_a_block_as_a_stop() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        feed(
+            &mut state,
+            &sink,
+            &[
+                json!({"type": "message_start", "message": {}}),
+                json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text"}}),
+            ],
+        )
+        .unwrap();
+        assert_eq!(state.open_blocks, 1);
+        state.close();
+        assert!(!state.completed);
+
+        // Nothing was started, so nothing can have finished
+        let mut state = AnthropicStream::default();
+        state.close();
+        assert!(!state.completed);
+    }
+
+    #[test]
+    fn handles_a_complete_json_reply() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        let body = json!({
+            "type": "message",
+            "model": "claude-sonnet-4-5",
+            "role": "assistant",
+            "content": [
+                {"type": "text", "text": "Checking."},
+                {"type": "tool_use", "id": "toolu_1", "name": "search", "input": {"q": "rust"}},
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 3, "output_tokens": 4},
+        });
+        state.handle_complete(body, &sink).unwrap();
+        state.flush_tool_calls(&sink).unwrap();
+        state.flush_usage(&sink).unwrap();
+
+        assert!(state.completed);
+        assert_eq!(state.finish_reason, Some(FinishReason::ToolCalls));
+        let events = sink.events();
+        assert!(matches!(
+            &events[0],
+            StreamEvent::Start(start)
+                if start.model.as_deref() == Some("claude-sonnet-4-5")
+                    && start.input_tokens == Some(3)
+        ));
+        assert_eq!(sink.text(), "Checking.");
+        let call = events
+            .iter()
+            .find_map(|event| match event {
+                StreamEvent::ToolCall(call) => Some(call),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!((call.index, call.id.as_str()), (1, "toolu_1"));
+        assert_eq!(call.arguments, json!({"q": "rust"}));
+        assert_eq!(usage(&sink)[0].total_tokens, Some(7));
+    }
+
+    #[test]
+    fn requests_citations_for_search_tools_and_cited_documents() {
+        let search = json!({"tools": [{"type": "web_search_20250305", "name": "web_search"}]});
+        assert!(citations_requested(&search));
+
+        let document = json!({"messages": [{"role": "user", "content": [{
+            "type": "document",
+            "source": {"type": "text", "media_type": "text/plain", "data": "The grass is green."},
+            "citations": {"enabled": true},
+        }]}]});
+        assert!(citations_requested(&document));
+
+        let plain = json!({
+            "tools": [{"name": "lookup", "input_schema": {"type": "object"}}],
+            "messages": [{"role": "user", "content": "What color is the grass?"}],
+        });
+        assert!(!citations_requested(&plain));
+    }
+
+    /// Two text blocks, the second citing a document
+    fn cited_answer() -> Vec<Value> {
+        vec![
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Per the doc, "}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "citations_delta", "citation": {
+                "type": "char_location",
+                "cited_text": "The grass is green.",
+                "document_index": 0,
+                "start_char_index": 0,
+                "end_char_index": 19,
+            }}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "the grass is green."}}),
+            json!({"type": "content_block_stop", "index": 1}),
+        ]
+    }
+
+    #[test]
+    fn emits_citations_at_the_offset_of_their_block() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream {
+            citations: true,
+            ..Default::default()
+        };
+        feed(&mut state, &sink, &cited_answer()).unwrap();
+
+        let citations: Vec<StreamCitation> = sink
+            .events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::Citation(citation) => Some(citation),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0].index, 1);
+        assert_eq!(citations[0].text_offset, "Per the doc, ".len());
+        assert_eq!(citations[0].citation["cited_text"], "The grass is green.");
+        assert_eq!(sink.text(), "Per the doc, the grass is green.");
+    }
+
+    #[test]
+    fn ignores_citations_that_were_not_requested() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        feed(&mut state, &sink, &cited_answer()).unwrap();
+
+        assert!(!sink
+            .events()
+            .iter()
+            .any(|event| matches!(event, StreamEvent::Citation(_))));
+        assert_eq!(sink.text(), "Per the doc, the grass is green.");
+    }
+
+    #[tokio::test]
+    async fn ends_the_stream_at_a_fatal_error() {
+        let events = [
+            json!({"type": "message_start", "message": {"role": "assistant"}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hel"}}),
+            json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "lo"}}),
+            json!({"type": "message_stop"}),
+        ];
+        let body: String = events
+            .iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .collect();
+        let server = MockServer::start(vec![MockResponse::new("text/event-stream", body)]).await;
+        let response = reqwest::get(&server.url).await.unwrap();
+
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        let result = read_sse(response, None, &sink, &StreamOptions::default(), |event| {
+            state.handle_event(event, &sink)
+        })
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(ProxyError::StreamFailed { error_type, .. }) if error_type == "overloaded_error"
+        ));
+        assert!(!state.completed);
+        assert_eq!(sink.text(), "Hel");
+        let events = sink.events();
+        assert!(matches!(
+            events.last(),
+            Some(StreamEvent::Error(error)) if error.kind == ErrorKind::Upstream && error.retryable
+        ));
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, StreamEvent::End { .. })));
+    }
+
+    #[test]
+    fn keeps_reading_after_a_non_fatal_error() {
+        let sink = RecordingSink::default();
+        let mut state = AnthropicStream::default();
+        feed(
+            &mut state,
+            &sink,
+            &[
+                json!({"type": "message_start", "message": {"role": "assistant"}}),
+                json!({"type": "error", "error": {"type": "invalid_request_error", "message": "Bad block"}}),
+                json!({"type": "message_stop"}),
+            ],
+        )
+        .unwrap();
+
+        assert!(state.completed);
+        assert!(matches!(
+            &sink.events()[..],
+            [StreamEvent::Start(_), StreamEvent::Role { .. }, StreamEvent::Error(error)]
+                if !error.retryable
+        ));
+    }
+
+    #[test]
+    fn classifies_error_events_by_type() {
+        let cases = [
+            ("authentication_error", ErrorKind::Auth, false),
+            ("permission_error", ErrorKind::Auth, false),
+            ("rate_limit_error", ErrorKind::RateLimit, true),
+            ("overloaded_error", ErrorKind::Upstream, true),
+            ("api_error", ErrorKind::Upstream, true),
+            ("unknown_error", ErrorKind::Upstream, false),
+        ];
+        for (error_type, kind, retryable) in cases {
+            let sink = RecordingSink::default();
+            let mut state = AnthropicStream::default();
+            let result = feed(
+                &mut state,
+                &sink,
+                &[json!({"type": "error", "error": {"type": error_type, "message": "Failed"}})],
+            );
+
+            assert_eq!(
+                result.is_err(),
+                FATAL_ERROR_TYPES.contains(&error_type),
+                "{}",
+                error_type
+            );
+            assert!(
+                matches!(
+                    &sink.events()[..],
+                    [StreamEvent::Error(error)]
+                        if error.kind == kind && error.retryable == retryable
+                ),
+                "{}",
+                error_type
+            );
+        }
+    }
 }
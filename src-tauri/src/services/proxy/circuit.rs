@@ -0,0 +1,184 @@
+use crate::services::proxy::{ProxyError, ProxyResult};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default number of consecutive failures before the circuit opens
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+/// Default time the circuit stays open before allowing a trial request
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Requests fail fast until the cooldown elapses
+    Open,
+    /// A single trial request is testing whether the provider recovered
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CircuitConfig {
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+impl Default for CircuitConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+}
+
+struct ProviderCircuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    since: Instant,
+}
+
+impl Default for ProviderCircuit {
+    fn default() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            since: Instant::now(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Circuits {
+    config: CircuitConfig,
+    providers: HashMap<String, ProviderCircuit>,
+}
+
+/// Per-provider circuit breakers that fail fast while a provider is down
+#[derive(Default)]
+pub struct CircuitBreakers {
+    inner: Mutex<Circuits>,
+}
+
+impl CircuitBreakers {
+    pub fn configure(&self, failure_threshold: u32, cooldown: Duration) -> ProxyResult<()> {
+        if failure_threshold == 0 {
+            return Err(ProxyError::Config(
+                "failure_threshold must be greater than zero".to_string(),
+            ));
+        }
+
+        let mut circuits = self.lock()?;
+        info!(
+            "Circuit breaker configured: {} failures, {:?} cooldown",
+            failure_threshold, cooldown
+        );
+        circuits.config = CircuitConfig {
+            failure_threshold,
+            cooldown,
+        };
+        Ok(())
+    }
+
+    /// Current configuration as `(failure_threshold, cooldown)`
+    pub fn config(&self) -> (u32, Duration) {
+        self.lock()
+            .map(|circuits| (circuits.config.failure_threshold, circuits.config.cooldown))
+            .unwrap_or((DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN))
+    }
+
+    /// Check whether a request to the provider may proceed.
+    ///
+    /// Once the cooldown has elapsed, an open circuit lets one trial request through.
+    pub fn check(&self, provider: &str) -> ProxyResult<()> {
+        let mut circuits = self.lock()?;
+        let cooldown = circuits.config.cooldown;
+        let circuit = circuits.providers.entry(provider.to_string()).or_default();
+
+        match circuit.state {
+            CircuitState::Closed => Ok(()),
+            // A trial that never reported back must not keep the circuit stuck half-open
+            CircuitState::Open | CircuitState::HalfOpen if circuit.since.elapsed() >= cooldown => {
+                info!("Circuit for {} half-open, allowing trial request", provider);
+                circuit.state = CircuitState::HalfOpen;
+                circuit.since = Instant::now();
+                Ok(())
+            }
+            CircuitState::Open | CircuitState::HalfOpen => {
+                Err(ProxyError::CircuitOpen(provider.to_string()))
+            }
+        }
+    }
+
+    /// Record a successful request, returning true if this closed the circuit
+    pub fn record_success(&self, provider: &str) -> bool {
+        let Ok(mut circuits) = self.lock() else {
+            return false;
+        };
+        let circuit = circuits.providers.entry(provider.to_string()).or_default();
+        let was_closed = circuit.state == CircuitState::Closed;
+
+        circuit.state = CircuitState::Closed;
+        circuit.consecutive_failures = 0;
+        circuit.since = Instant::now();
+
+        if !was_closed {
+            info!("Circuit for {} closed", provider);
+        }
+        !was_closed
+    }
+
+    /// Record a failed request, returning true if this opened the circuit
+    pub fn record_failure(&self, provider: &str) -> bool {
+        let Ok(mut circuits) = self.lock() else {
+            return false;
+        };
+        let threshold = circuits.config.failure_threshold;
+        let circuit = circuits.providers.entry(provider.to_string()).or_default();
+        circuit.consecutive_failures += 1;
+
+        let should_open = match circuit.state {
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => circuit.consecutive_failures >= threshold,
+            CircuitState::Open => false,
+        };
+
+        if should_open {
+            warn!(
+                "Circuit for {} opened after {} consecutive failures",
+                provider, circuit.consecutive_failures
+            );
+            circuit.state = CircuitState::Open;
+            circuit.since = Instant::now();
+        }
+        should_open
+    }
+
+    pub fn state(&self, provider: &str) -> CircuitState {
+        self.lock()
+            .ok()
+            .and_then(|circuits| circuits.providers.get(provider).map(|c| c.state))
+            .unwrap_or(CircuitState::Closed)
+    }
+
+    /// Circuit state without waiting on the lock; `None` if it is held elsewhere
+    pub fn try_state(&self, provider: &str) -> Option<CircuitState> {
+        let circuits = self.inner.try_lock().ok()?;
+        Some(
+            circuits
+                .providers
+                .get(provider)
+                .map_or(CircuitState::Closed, |c| c.state),
+        )
+    }
+
+    fn lock(&self) -> ProxyResult<std::sync::MutexGuard<'_, Circuits>> {
+        self.inner
+            .lock()
+            .map_err(|e| ProxyError::Config(format!("Circuit breaker lock poisoned: {}", e)))
+    }
+}
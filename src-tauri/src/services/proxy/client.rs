@@ -0,0 +1,76 @@
+use crate::services::proxy::{ProxyError, ProxyResult};
+use log::{info, warn};
+use std::env;
+use std::sync::Mutex;
+use tauri_plugin_http::reqwest::{self, Certificate};
+
+/// PEM file of root certificates to trust in addition to the default ones, for internal
+/// CAs and TLS-inspecting proxies. May hold several certificates.
+pub const CA_CERT_VAR: &str = "ROBIN_CA_CERT";
+
+/// Set to `true` or `1` to accept any certificate, including expired, self-signed and
+/// mismatched ones. This disables the protection TLS gives API keys in transit, so it
+/// is for local development against a test server only; use [`CA_CERT_VAR`] to trust
+/// a private CA instead.
+pub const ACCEPT_INVALID_CERTS_VAR: &str = "ROBIN_DANGER_ACCEPT_INVALID_CERTS";
+
+/// Client shared by every provider request, so connections are pooled across streams
+static CLIENT: Mutex<Option<reqwest::Client>> = Mutex::new(None);
+
+/// The shared HTTP client, built on first use with the configured trust store
+pub fn http_client() -> ProxyResult<reqwest::Client> {
+    let mut client = CLIENT
+        .lock()
+        .map_err(|e| ProxyError::Config(format!("HTTP client lock poisoned: {}", e)))?;
+    if let Some(client) = client.as_ref() {
+        return Ok(client.clone());
+    }
+    let built = build_client()?;
+    *client = Some(built.clone());
+    Ok(built)
+}
+
+/// Drop the shared client so the next request rebuilds it from the current environment
+pub fn reset_http_client() {
+    if let Ok(mut client) = CLIENT.lock() {
+        *client = None;
+    }
+}
+
+fn build_client() -> ProxyResult<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+
+    match env::var(CA_CERT_VAR).ok().filter(|path| !path.is_empty()) {
+        Some(path) => {
+            let pem = std::fs::read(&path).map_err(|e| {
+                ProxyError::Config(format!("Failed to read {} {}: {}", CA_CERT_VAR, path, e))
+            })?;
+            let certificates = Certificate::from_pem_bundle(&pem).map_err(|e| {
+                ProxyError::Config(format!("Invalid certificate in {}: {}", path, e))
+            })?;
+            info!(
+                "Trusting the default root certificates plus {} from {}",
+                certificates.len(),
+                path
+            );
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        None => info!("Trusting the default root certificates"),
+    }
+
+    let accept_invalid =
+        env::var(ACCEPT_INVALID_CERTS_VAR).is_ok_and(|value| matches!(value.trim(), "1" | "true"));
+    if accept_invalid {
+        warn!(
+            "{} is set: certificate verification is disabled for all provider requests",
+            ACCEPT_INVALID_CERTS_VAR
+        );
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    builder
+        .build()
+        .map_err(|e| ProxyError::Config(format!("Failed to build HTTP client: {}", e)))
+}
@@ -0,0 +1,480 @@
+use crate::services::proxy::{
+    emit_structured_error, format_text_chunk, AudioChunk, CircuitState, ErrorKind, FinishReason,
+    ModelSubstitution, OpenAITokenLogprob, ProxyError, ProxyResult, RateLimitInfo, StreamCitation,
+    StreamEndPayload, StreamError, StreamFallback, StreamFiltered, StreamStart, StreamStats,
+    StreamTrimmed, StreamUsage, ToolCall, ToolCallDelta,
+};
+use crate::services::proxy::{
+    EVT_AUDIO_CHUNK, EVT_CHUNK, EVT_CIRCUIT_CLOSED, EVT_CIRCUIT_OPEN, EVT_CITATION, EVT_END,
+    EVT_ERROR, EVT_ERROR_DETAIL, EVT_FALLBACK, EVT_FILTERED, EVT_INCOMPLETE, EVT_LOGPROBS,
+    EVT_MODEL_SUBSTITUTED, EVT_QUEUED, EVT_RATELIMIT, EVT_RAW, EVT_REFUSAL, EVT_ROLE, EVT_START,
+    EVT_STATS, EVT_TOOL_CALL, EVT_TOOL_DELTA, EVT_TRIMMED, EVT_USAGE, EVT_WARNING,
+};
+use log::warn;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tauri::ipc::Channel;
+use tauri::{Emitter, EventTarget, Window};
+
+/// A typed event produced while streaming a completion
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// The request is waiting for a free stream slot
+    Queued { provider: String },
+    /// The provider started responding
+    Start(StreamStart),
+    /// The provider served a different model than the request named
+    ModelSubstituted(ModelSubstitution),
+    /// The role of the streamed message, sent once when first seen
+    Role { role: String },
+    /// A fragment of generated text
+    Text { text: String },
+    /// The model declined the request; sent once with the whole refusal text
+    Refusal { text: String },
+    /// A fragment of audio from an audio provider
+    Audio(AudioChunk),
+    /// Log probabilities for the tokens of the preceding text
+    Logprobs { tokens: Vec<OpenAITokenLogprob> },
+    /// A source cited by a text content block
+    Citation(StreamCitation),
+    /// A fragment of a tool call's arguments
+    ToolDelta(ToolCallDelta),
+    /// A fully assembled tool call
+    ToolCall(ToolCall),
+    /// The raw response text, captured for debugging
+    Raw { raw: String },
+    /// The provider blocked the output; text already emitted is kept
+    Filtered(StreamFiltered),
+    /// The provider's remaining quota, sent with every response that reports it
+    RateLimit(RateLimitInfo),
+    /// Token counts for the request
+    Usage(StreamUsage),
+    /// Timing for the stream, sent just before the end event
+    Stats(StreamStats),
+    /// The connection closed before the provider's terminal event; output may be cut off
+    Incomplete { provider: String },
+    /// A non-fatal condition the client may want to surface
+    Warning { message: String },
+    /// An error reported by the provider or while parsing its response
+    Error(StreamError),
+    /// The stream finished
+    End { finish_reason: Option<FinishReason> },
+    /// Old messages were dropped so the request fits the model's context window
+    Trimmed(StreamTrimmed),
+    /// The provider that served a fallback chain
+    Fallback(StreamFallback),
+    /// The provider's circuit breaker changed state
+    Circuit {
+        provider: String,
+        state: CircuitState,
+    },
+}
+
+impl StreamEvent {
+    /// Name of the window event this is delivered as
+    pub fn event_name(&self) -> &'static str {
+        match self {
+            StreamEvent::Queued { .. } => EVT_QUEUED,
+            StreamEvent::Start(_) => EVT_START,
+            StreamEvent::ModelSubstituted(_) => EVT_MODEL_SUBSTITUTED,
+            StreamEvent::Role { .. } => EVT_ROLE,
+            StreamEvent::Text { .. } => EVT_CHUNK,
+            StreamEvent::Refusal { .. } => EVT_REFUSAL,
+            StreamEvent::Audio(_) => EVT_AUDIO_CHUNK,
+            StreamEvent::Logprobs { .. } => EVT_LOGPROBS,
+            StreamEvent::Citation(_) => EVT_CITATION,
+            StreamEvent::ToolDelta(_) => EVT_TOOL_DELTA,
+            StreamEvent::ToolCall(_) => EVT_TOOL_CALL,
+            StreamEvent::Raw { .. } => EVT_RAW,
+            StreamEvent::Filtered(_) => EVT_FILTERED,
+            StreamEvent::RateLimit(_) => EVT_RATELIMIT,
+            StreamEvent::Usage(_) => EVT_USAGE,
+            StreamEvent::Stats(_) => EVT_STATS,
+            StreamEvent::Incomplete { .. } => EVT_INCOMPLETE,
+            StreamEvent::Warning { .. } => EVT_WARNING,
+            StreamEvent::Error(_) => EVT_ERROR,
+            StreamEvent::End { .. } => EVT_END,
+            StreamEvent::Trimmed(_) => EVT_TRIMMED,
+            StreamEvent::Fallback(_) => EVT_FALLBACK,
+            StreamEvent::Circuit { state, .. } => match state {
+                CircuitState::Closed => EVT_CIRCUIT_CLOSED,
+                CircuitState::Open | CircuitState::HalfOpen => EVT_CIRCUIT_OPEN,
+            },
+        }
+    }
+}
+
+/// Destination for the events of a stream
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()>;
+}
+
+/// Delivers events to a Tauri window using the legacy event names and payloads
+pub struct WindowSink {
+    window: Window,
+    /// Caller-supplied metadata echoed back with every event of the stream
+    metadata: Option<Value>,
+    /// Label of the window to deliver to, instead of emitting from `window`
+    target: Option<String>,
+}
+
+/// Event payload wrapped together with the stream's metadata
+#[derive(Serialize, Clone)]
+struct TaggedPayload<'a, T: Serialize> {
+    metadata: &'a Value,
+    payload: T,
+}
+
+impl WindowSink {
+    pub fn new(window: Window) -> Self {
+        Self {
+            window,
+            metadata: None,
+            target: None,
+        }
+    }
+
+    /// Deliver to the window with this label only
+    pub fn with_target(mut self, label: String) -> Self {
+        self.target = Some(label);
+        self
+    }
+
+    /// Echo `metadata` with every event, wrapping each payload as
+    /// `{ "metadata": ..., "payload": ... }`
+    pub fn with_metadata(mut self, metadata: Option<Value>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    fn send<T: Serialize + Clone>(&self, name: &str, payload: T) -> tauri::Result<()> {
+        match &self.metadata {
+            Some(metadata) => self.deliver(name, TaggedPayload { metadata, payload }),
+            None => self.deliver(name, payload),
+        }
+    }
+
+    fn deliver<T: Serialize + Clone>(&self, name: &str, payload: T) -> tauri::Result<()> {
+        match &self.target {
+            Some(label) => {
+                let target = EventTarget::AnyLabel {
+                    label: label.clone(),
+                };
+                self.window.emit_to(target, name, payload)
+            }
+            None => self.window.emit(name, payload),
+        }
+    }
+}
+
+impl EventSink for WindowSink {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        let name = event.event_name();
+        let result = match event {
+            StreamEvent::Queued { provider } => self.send(name, provider),
+            StreamEvent::Start(start) => self.send(name, start),
+            StreamEvent::ModelSubstituted(substitution) => self.send(name, substitution),
+            StreamEvent::Role { role } => self.send(name, role),
+            StreamEvent::Text { text } => self.send(name, format_text_chunk(&text)?),
+            StreamEvent::Refusal { text } => self.send(name, text),
+            StreamEvent::Audio(chunk) => self.send(name, chunk),
+            StreamEvent::Logprobs { tokens } => self.send(name, tokens),
+            StreamEvent::Citation(citation) => self.send(name, citation),
+            StreamEvent::ToolDelta(delta) => self.send(name, delta),
+            StreamEvent::ToolCall(call) => self.send(name, call),
+            StreamEvent::Raw { raw } => self.send(name, raw),
+            StreamEvent::Filtered(filtered) => self.send(name, filtered),
+            StreamEvent::RateLimit(rate_limit) => self.send(name, rate_limit),
+            StreamEvent::Usage(usage) => self.send(name, usage),
+            StreamEvent::Stats(stats) => self.send(name, stats),
+            StreamEvent::Incomplete { provider } => self.send(name, provider),
+            StreamEvent::Warning { message } => self.send(name, message),
+            StreamEvent::Error(error) => {
+                // The plain message stays on the legacy event for older frontends
+                self.send(EVT_ERROR_DETAIL, error.clone())
+                    .and_then(|_| self.send(name, error.message))
+            }
+            StreamEvent::End { finish_reason } => {
+                self.send(name, StreamEndPayload { finish_reason })
+            }
+            StreamEvent::Trimmed(trimmed) => self.send(name, trimmed),
+            StreamEvent::Fallback(fallback) => self.send(name, fallback),
+            StreamEvent::Circuit { provider, .. } => self.send(name, provider),
+        };
+        result.map_err(|e| ProxyError::Emit(format!("Failed to emit {} event: {}", name, e)))
+    }
+}
+
+/// An event sent over a [`ChannelSink`]: the [`StreamEvent`] itself, plus the stream's
+/// metadata if the request set any
+#[derive(Serialize, Debug, Clone)]
+pub struct ChannelEvent {
+    #[serde(flatten)]
+    pub event: StreamEvent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+}
+
+/// Delivers typed events over an IPC channel owned by the caller.
+///
+/// Once the frontend drops its end, sending fails and the stream is aborted.
+pub struct ChannelSink {
+    channel: Channel<ChannelEvent>,
+    /// Caller-supplied metadata echoed back with every event of the stream
+    metadata: Option<Value>,
+}
+
+impl ChannelSink {
+    pub fn new(channel: Channel<ChannelEvent>) -> Self {
+        Self {
+            channel,
+            metadata: None,
+        }
+    }
+
+    /// Echo `metadata` with every event, as a `metadata` field beside the event's own
+    pub fn with_metadata(mut self, metadata: Option<Value>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+impl EventSink for ChannelSink {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        let name = event.event_name();
+        let event = ChannelEvent {
+            event,
+            metadata: self.metadata.clone(),
+        };
+        self.channel
+            .send(event)
+            .map_err(|e| ProxyError::Emit(format!("Failed to send {} event: {}", name, e)))
+    }
+}
+
+/// Delivers every event to several sinks, such as one per window.
+///
+/// A sink that fails is logged and skipped; emitting fails only if every sink failed.
+pub struct FanoutSink {
+    sinks: Vec<Box<dyn EventSink>>,
+}
+
+impl FanoutSink {
+    pub fn new(sinks: Vec<Box<dyn EventSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl EventSink for FanoutSink {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        let mut last_error = None;
+        let mut delivered = false;
+        for sink in &self.sinks {
+            match sink.emit(event.clone()) {
+                Ok(()) => delivered = true,
+                Err(e) => {
+                    warn!("{}", e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) if !delivered => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Delivers events to a caller-supplied closure
+pub struct CallbackSink<F> {
+    callback: Mutex<F>,
+}
+
+impl<F> CallbackSink<F>
+where
+    F: FnMut(StreamEvent) + Send,
+{
+    pub fn new(callback: F) -> Self {
+        Self {
+            callback: Mutex::new(callback),
+        }
+    }
+}
+
+impl<F> EventSink for CallbackSink<F>
+where
+    F: FnMut(StreamEvent) + Send,
+{
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        let mut callback = self
+            .callback
+            .lock()
+            .map_err(|e| ProxyError::Emit(format!("Event callback lock poisoned: {}", e)))?;
+        (*callback)(event);
+        Ok(())
+    }
+}
+
+/// Wraps a sink for a single attempt that may be retried.
+///
+/// An error emitted before any other event is held back instead of forwarded, so a
+/// request that fails up front can be retried without the client seeing the failure.
+pub(crate) struct AttemptSink<'a> {
+    inner: &'a dyn EventSink,
+    held_error: Mutex<Option<StreamEvent>>,
+    forwarded: AtomicBool,
+}
+
+impl<'a> AttemptSink<'a> {
+    pub(crate) fn new(inner: &'a dyn EventSink) -> Self {
+        Self {
+            inner,
+            held_error: Mutex::new(None),
+            forwarded: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether any event reached the client, after which retrying is unsafe
+    pub(crate) fn forwarded_any(&self) -> bool {
+        self.forwarded.load(Ordering::SeqCst)
+    }
+
+    /// Forward the held error, once it is clear the attempt won't be retried
+    pub(crate) fn flush(&self) -> ProxyResult<()> {
+        let held = self
+            .held_error
+            .lock()
+            .map_err(|e| ProxyError::Emit(format!("Attempt sink lock poisoned: {}", e)))?
+            .take();
+        match held {
+            Some(event) => self.inner.emit(event),
+            None => Ok(()),
+        }
+    }
+}
+
+impl EventSink for AttemptSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        if matches!(event, StreamEvent::Error(_)) && !self.forwarded_any() {
+            let mut held = self
+                .held_error
+                .lock()
+                .map_err(|e| ProxyError::Emit(format!("Attempt sink lock poisoned: {}", e)))?;
+            if held.is_none() {
+                *held = Some(event);
+                return Ok(());
+            }
+        }
+        // Status updates are informational and don't commit the attempt
+        if !matches!(
+            event,
+            StreamEvent::RateLimit(_)
+                | StreamEvent::Queued { .. }
+                | StreamEvent::Warning { .. }
+                | StreamEvent::ModelSubstituted(_)
+                | StreamEvent::Circuit { .. }
+                | StreamEvent::Trimmed(_)
+        ) {
+            self.forwarded.store(true, Ordering::SeqCst);
+        }
+        self.inner.emit(event)
+    }
+}
+
+/// Wraps a sink to abort the stream when the first role event isn't the expected role
+pub(crate) struct RoleGuardSink<'a> {
+    inner: &'a dyn EventSink,
+    expected_role: Option<String>,
+    checked: AtomicBool,
+}
+
+impl<'a> RoleGuardSink<'a> {
+    pub(crate) fn new(inner: &'a dyn EventSink, expected_role: Option<String>) -> Self {
+        Self {
+            inner,
+            expected_role,
+            checked: AtomicBool::new(false),
+        }
+    }
+}
+
+impl EventSink for RoleGuardSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        if let (StreamEvent::Role { role }, Some(expected)) = (&event, &self.expected_role) {
+            if !self.checked.swap(true, Ordering::SeqCst) && role != expected {
+                let error = ProxyError::UnexpectedRole {
+                    expected: expected.clone(),
+                    actual: role.clone(),
+                };
+                emit_structured_error(
+                    self.inner,
+                    ErrorKind::Upstream,
+                    format!("{}, aborting stream", error),
+                    false,
+                )?;
+                return Err(error);
+            }
+        }
+        self.inner.emit(event)
+    }
+}
+
+/// Wraps a sink to stamp the stream's registry id on its start event
+pub(crate) struct StreamIdSink<'a> {
+    inner: &'a dyn EventSink,
+    stream_id: String,
+}
+
+impl<'a> StreamIdSink<'a> {
+    pub(crate) fn new(inner: &'a dyn EventSink, stream_id: &str) -> Self {
+        Self {
+            inner,
+            stream_id: stream_id.to_string(),
+        }
+    }
+}
+
+impl EventSink for StreamIdSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        match event {
+            StreamEvent::Start(mut start) => {
+                start.stream_id = Some(self.stream_id.clone());
+                self.inner.emit(StreamEvent::Start(start))
+            }
+            event => self.inner.emit(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sends_metadata_beside_the_channel_event() {
+        let text = StreamEvent::Text {
+            text: "Hi".to_string(),
+        };
+        let plain = ChannelEvent {
+            event: text.clone(),
+            metadata: None,
+        };
+        assert_eq!(
+            serde_json::to_value(plain).unwrap(),
+            json!({"type": "text", "text": "Hi"})
+        );
+
+        let tagged = ChannelEvent {
+            event: text,
+            metadata: Some(json!({"conversation": 7})),
+        };
+        assert_eq!(
+            serde_json::to_value(tagged).unwrap(),
+            json!({"type": "text", "text": "Hi", "metadata": {"conversation": 7}})
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri_plugin_http::reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+/// Header the provider uses to recognise a retried request
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+static NEXT_KEY: AtomicU64 = AtomicU64::new(0);
+
+/// A fresh random key, as 32 hex digits
+pub fn new_idempotency_key() -> String {
+    let counter = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default();
+    // Each RandomState is seeded randomly, so the halves are unpredictable
+    let half = |salt: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(counter);
+        hasher.write_u64(nanos);
+        hasher.write_u64(salt);
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", half(0), half(1))
+}
+
+/// Attach a fresh idempotency key unless the caller already set one.
+///
+/// Called once per logical request; the headers are reused by every retry, so the
+/// provider sees the same key and can drop duplicates.
+pub fn attach_idempotency_key(headers: &mut HeaderMap) {
+    let name = HeaderName::from_static(IDEMPOTENCY_KEY_HEADER);
+    if headers.contains_key(&name) {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&new_idempotency_key()) {
+        headers.insert(name, value);
+    }
+}
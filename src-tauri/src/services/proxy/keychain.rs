@@ -0,0 +1,59 @@
+use crate::services::proxy::{key_var, ProxyError, ProxyResult};
+use keyring::Entry;
+use log::{debug, info};
+
+/// Keychain service name, namespaced to the app so entries don't collide with other tools
+const KEYCHAIN_SERVICE: &str = "com.pqp.app";
+
+fn entry(provider: &str) -> ProxyResult<Entry> {
+    let key_name = key_var(provider)
+        .ok_or_else(|| ProxyError::ApiKey(format!("Unsupported provider: {}", provider)))?;
+    Entry::new(KEYCHAIN_SERVICE, key_name)
+        .map_err(|e| ProxyError::ApiKey(format!("Keychain unavailable: {}", e)))
+}
+
+/// Read the provider's key from the OS keychain.
+///
+/// Returns `None` when there is no entry or no usable keychain, so callers can fall
+/// back to the environment.
+pub fn read_key(provider: &str) -> Option<String> {
+    match entry(provider).and_then(|entry| {
+        entry
+            .get_password()
+            .map_err(|e| ProxyError::ApiKey(e.to_string()))
+    }) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            debug!("No keychain key for {}: {}", provider, e);
+            None
+        }
+    }
+}
+
+/// Store the provider's key in the OS keychain, replacing any existing entry
+pub fn store_key(provider: &str, key: &str) -> ProxyResult<()> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(ProxyError::ApiKey("API key must not be empty".to_string()));
+    }
+    entry(provider)?
+        .set_password(key)
+        .map_err(|e| ProxyError::ApiKey(format!("Failed to store key in keychain: {}", e)))?;
+    info!("Stored {} key in the keychain", provider);
+    Ok(())
+}
+
+/// Delete the provider's key from the OS keychain, returning whether one existed
+pub fn delete_key(provider: &str) -> ProxyResult<bool> {
+    match entry(provider)?.delete_credential() {
+        Ok(()) => {
+            info!("Deleted {} key from the keychain", provider);
+            Ok(true)
+        }
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(ProxyError::ApiKey(format!(
+            "Failed to delete key from keychain: {}",
+            e
+        ))),
+    }
+}
@@ -0,0 +1,128 @@
+use crate::services::proxy::{key_var, ProxyError, ProxyResult};
+use log::{debug, info};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+struct PooledKey {
+    key: String,
+    failures: u32,
+}
+
+#[derive(Default)]
+struct ProviderKeys {
+    keys: Vec<PooledKey>,
+    next: usize,
+}
+
+/// Pool of API keys per provider, used round-robin with failover
+#[derive(Default)]
+pub struct KeyPool {
+    providers: Mutex<HashMap<String, ProviderKeys>>,
+}
+
+impl KeyPool {
+    /// Add a key to the provider's pool, returning the pool size
+    pub fn add(&self, provider: &str, key: String) -> ProxyResult<usize> {
+        if key_var(provider).is_none() {
+            return Err(ProxyError::ApiKey(format!(
+                "Unsupported provider: {}",
+                provider
+            )));
+        }
+        let key = key.trim().to_string();
+        if key.is_empty() {
+            return Err(ProxyError::ApiKey("API key must not be empty".to_string()));
+        }
+
+        let mut providers = self.lock()?;
+        let pool = providers.entry(provider.to_string()).or_default();
+        if !pool.keys.iter().any(|pooled| pooled.key == key) {
+            pool.keys.push(PooledKey { key, failures: 0 });
+        }
+        info!("{} key pool now has {} keys", provider, pool.keys.len());
+        Ok(pool.keys.len())
+    }
+
+    /// Remove a key from the provider's pool, returning whether it was present
+    pub fn remove(&self, provider: &str, key: &str) -> ProxyResult<bool> {
+        let mut providers = self.lock()?;
+        let Some(pool) = providers.get_mut(provider) else {
+            return Ok(false);
+        };
+        let before = pool.keys.len();
+        pool.keys.retain(|pooled| pooled.key != key.trim());
+        info!("{} key pool now has {} keys", provider, pool.keys.len());
+        Ok(pool.keys.len() < before)
+    }
+
+    /// Keys to try for the next request, in order.
+    ///
+    /// The starting key rotates on every call; keys with fewer recorded failures are
+    /// tried first so a bad key is only used once the others have failed too.
+    pub fn candidates(&self, provider: &str) -> Vec<String> {
+        let Ok(mut providers) = self.lock() else {
+            return Vec::new();
+        };
+        let Some(pool) = providers.get_mut(provider) else {
+            return Vec::new();
+        };
+        if pool.keys.is_empty() {
+            return Vec::new();
+        }
+
+        let start = pool.next % pool.keys.len();
+        pool.next = (start + 1) % pool.keys.len();
+
+        let mut rotated: Vec<&PooledKey> = pool.keys[start..]
+            .iter()
+            .chain(pool.keys[..start].iter())
+            .collect();
+        rotated.sort_by_key(|pooled| pooled.failures);
+        rotated
+            .into_iter()
+            .map(|pooled| pooled.key.clone())
+            .collect()
+    }
+
+    pub fn record_success(&self, provider: &str, key: &str) {
+        self.update(provider, key, |pooled| pooled.failures = 0);
+    }
+
+    pub fn record_failure(&self, provider: &str, key: &str) {
+        self.update(provider, key, |pooled| {
+            pooled.failures = pooled.failures.saturating_add(1);
+            debug!("Pooled key now has {} failures", pooled.failures);
+        });
+    }
+
+    /// Number of pooled keys for a provider
+    pub fn key_count(&self, provider: &str) -> usize {
+        self.lock()
+            .ok()
+            .and_then(|providers| providers.get(provider).map(|pool| pool.keys.len()))
+            .unwrap_or(0)
+    }
+
+    /// Number of pooled keys without waiting on the lock; `None` if it is held elsewhere
+    pub fn try_key_count(&self, provider: &str) -> Option<usize> {
+        let providers = self.providers.try_lock().ok()?;
+        Some(providers.get(provider).map_or(0, |pool| pool.keys.len()))
+    }
+
+    fn update<F: FnOnce(&mut PooledKey)>(&self, provider: &str, key: &str, update: F) {
+        if let Ok(mut providers) = self.lock() {
+            if let Some(pooled) = providers
+                .get_mut(provider)
+                .and_then(|pool| pool.keys.iter_mut().find(|pooled| pooled.key == key))
+            {
+                update(pooled);
+            }
+        }
+    }
+
+    fn lock(&self) -> ProxyResult<MutexGuard<'_, HashMap<String, ProviderKeys>>> {
+        self.providers
+            .lock()
+            .map_err(|e| ProxyError::Config(format!("Key pool lock poisoned: {}", e)))
+    }
+}
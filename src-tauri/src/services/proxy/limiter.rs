@@ -0,0 +1,135 @@
+use crate::services::proxy::{ProxyError, ProxyResult};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Default number of streams a single provider may run at once
+pub const DEFAULT_MAX_CONCURRENT_STREAMS: usize = 16;
+/// Default number of requests allowed to wait for a free slot
+pub const DEFAULT_MAX_QUEUE_DEPTH: usize = 32;
+
+struct ProviderLimit {
+    semaphore: Arc<Semaphore>,
+    waiting: Arc<AtomicUsize>,
+    max_concurrent: usize,
+    max_queue_depth: usize,
+}
+
+impl ProviderLimit {
+    fn new(max_concurrent: usize, max_queue_depth: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+            max_concurrent,
+            max_queue_depth,
+        }
+    }
+}
+
+/// Decrements the waiting counter even if the queued request is dropped
+struct QueueGuard(Arc<AtomicUsize>);
+
+impl Drop for QueueGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Limits the number of concurrent streams per provider
+#[derive(Default)]
+pub struct StreamLimiter {
+    providers: Mutex<HashMap<String, ProviderLimit>>,
+}
+
+impl StreamLimiter {
+    /// Configure the limits for a provider.
+    ///
+    /// Streams already holding a slot keep it; the new limit applies to new requests.
+    pub fn set_limit(
+        &self,
+        provider: &str,
+        max_concurrent: usize,
+        max_queue_depth: usize,
+    ) -> ProxyResult<()> {
+        if max_concurrent == 0 {
+            return Err(ProxyError::Config(
+                "max_concurrent must be greater than zero".to_string(),
+            ));
+        }
+
+        let mut providers = self
+            .providers
+            .lock()
+            .map_err(|e| ProxyError::Config(format!("Stream limiter lock poisoned: {}", e)))?;
+        info!(
+            "Setting stream limit for {}: {} concurrent, {} queued",
+            provider, max_concurrent, max_queue_depth
+        );
+        providers.insert(
+            provider.to_string(),
+            ProviderLimit::new(max_concurrent, max_queue_depth),
+        );
+        Ok(())
+    }
+
+    /// Acquire a stream slot for the provider, waiting in the queue if needed.
+    ///
+    /// `on_queued` is called once if the request has to wait for a free slot.
+    pub async fn acquire<F>(
+        &self,
+        provider: &str,
+        on_queued: F,
+    ) -> ProxyResult<OwnedSemaphorePermit>
+    where
+        F: FnOnce(),
+    {
+        let (semaphore, waiting, max_queue_depth) = {
+            let mut providers = self
+                .providers
+                .lock()
+                .map_err(|e| ProxyError::Config(format!("Stream limiter lock poisoned: {}", e)))?;
+            let limit = providers.entry(provider.to_string()).or_insert_with(|| {
+                ProviderLimit::new(DEFAULT_MAX_CONCURRENT_STREAMS, DEFAULT_MAX_QUEUE_DEPTH)
+            });
+            (
+                limit.semaphore.clone(),
+                limit.waiting.clone(),
+                limit.max_queue_depth,
+            )
+        };
+
+        if let Ok(permit) = semaphore.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        if waiting.fetch_add(1, Ordering::SeqCst) >= max_queue_depth {
+            waiting.fetch_sub(1, Ordering::SeqCst);
+            warn!("Rejecting stream for {}: queue is full", provider);
+            return Err(ProxyError::TooManyRequests(provider.to_string()));
+        }
+        let _guard = QueueGuard(waiting);
+
+        debug!("Stream for {} queued waiting for a free slot", provider);
+        on_queued();
+
+        semaphore
+            .acquire_owned()
+            .await
+            .map_err(|e| ProxyError::Config(format!("Stream limiter closed: {}", e)))
+    }
+
+    /// Current limits for a provider as `(max_concurrent, max_queue_depth)`
+    pub fn limit(&self, provider: &str) -> (usize, usize) {
+        self.providers
+            .lock()
+            .ok()
+            .and_then(|providers| {
+                providers
+                    .get(provider)
+                    .map(|limit| (limit.max_concurrent, limit.max_queue_depth))
+            })
+            .unwrap_or((DEFAULT_MAX_CONCURRENT_STREAMS, DEFAULT_MAX_QUEUE_DEPTH))
+    }
+}
@@ -0,0 +1,101 @@
+use crate::services::proxy::{EventSink, ProxyError, ProxyResult, StreamEvent};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// File in the app data directory the metrics are persisted to across restarts
+pub const METRICS_FILE: &str = "metrics.json";
+
+/// Usage accumulated across every stream
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MetricsSnapshot {
+    pub streams: u64,
+    pub failed_streams: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub tool_calls: u64,
+}
+
+/// Running totals of all streams, persisted on shutdown
+#[derive(Default)]
+pub struct Metrics {
+    totals: Mutex<MetricsSnapshot>,
+}
+
+impl Metrics {
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.totals
+            .lock()
+            .map(|totals| totals.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn reset(&self) {
+        self.restore(MetricsSnapshot::default());
+    }
+
+    /// Replace the totals, e.g. with those saved by a previous run
+    pub fn restore(&self, snapshot: MetricsSnapshot) {
+        if let Ok(mut totals) = self.totals.lock() {
+            *totals = snapshot;
+        }
+    }
+
+    pub(crate) fn record(&self, update: impl FnOnce(&mut MetricsSnapshot)) {
+        if let Ok(mut totals) = self.totals.lock() {
+            update(&mut totals);
+        }
+    }
+
+    /// Read saved totals; a missing file starts from zero
+    pub fn load(path: &Path) -> ProxyResult<MetricsSnapshot> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(MetricsSnapshot::default()),
+            Err(e) => Err(ProxyError::Config(format!(
+                "Failed to read {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    /// Write the current totals to `path`, creating its directory if needed
+    pub fn save(&self, path: &Path) -> ProxyResult<()> {
+        let contents = serde_json::to_string_pretty(&self.snapshot())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| {
+                ProxyError::Config(format!("Failed to create {}: {}", dir.display(), e))
+            })?;
+        }
+        fs::write(path, contents)
+            .map_err(|e| ProxyError::Config(format!("Failed to write {}: {}", path.display(), e)))
+    }
+}
+
+/// Wraps a sink to add the stream's tokens and tool calls to the running totals
+pub(crate) struct MetricsSink<'a> {
+    inner: &'a dyn EventSink,
+    metrics: &'a Metrics,
+}
+
+impl<'a> MetricsSink<'a> {
+    pub(crate) fn new(inner: &'a dyn EventSink, metrics: &'a Metrics) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+impl EventSink for MetricsSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        match &event {
+            StreamEvent::Usage(usage) => self.metrics.record(|totals| {
+                totals.input_tokens += usage.input_tokens.unwrap_or(0);
+                totals.output_tokens += usage.output_tokens.unwrap_or(0);
+            }),
+            StreamEvent::ToolCall(_) => self.metrics.record(|totals| totals.tool_calls += 1),
+            _ => {}
+        }
+        self.inner.emit(event)
+    }
+}
@@ -0,0 +1,145 @@
+use crate::services::proxy::{redact_secrets, EventSink, ProxyResult, StreamEvent};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+/// Name the secret redaction middleware is registered under
+pub const REDACTION_MIDDLEWARE: &str = "redact-secrets";
+/// Name the system prompt override is registered under
+pub const SYSTEM_PROMPT_MIDDLEWARE: &str = "system-prompt-override";
+
+/// Hook into every proxied request, for logging, transformation or policy enforcement
+pub trait ProxyMiddleware: Send + Sync {
+    /// Rewrite the request body before the proxy validates and sends it
+    fn before(&self, _provider: &str, _body: &mut Value) {}
+
+    /// Observe an event on its way to the client
+    fn on_event(&self, _event: &StreamEvent) {}
+}
+
+/// Named middlewares, applied in registration order
+#[derive(Default)]
+pub struct MiddlewareRegistry {
+    middlewares: Mutex<Vec<(String, Arc<dyn ProxyMiddleware>)>>,
+}
+
+impl MiddlewareRegistry {
+    /// Add a middleware, replacing one already registered under `name` in place
+    pub fn register(&self, name: &str, middleware: Arc<dyn ProxyMiddleware>) {
+        if let Ok(mut middlewares) = self.middlewares.lock() {
+            match middlewares
+                .iter_mut()
+                .find(|(existing, _)| existing == name)
+            {
+                Some((_, current)) => *current = middleware,
+                None => middlewares.push((name.to_string(), middleware)),
+            }
+        }
+    }
+
+    /// Remove a middleware, returning whether it was registered
+    pub fn remove(&self, name: &str) -> bool {
+        let Ok(mut middlewares) = self.middlewares.lock() else {
+            return false;
+        };
+        let before = middlewares.len();
+        middlewares.retain(|(existing, _)| existing != name);
+        middlewares.len() < before
+    }
+
+    /// Names of the registered middlewares, in the order they run
+    pub fn names(&self) -> Vec<String> {
+        self.middlewares
+            .lock()
+            .map(|middlewares| middlewares.iter().map(|(name, _)| name.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// The middlewares to run for one request; later changes don't affect it
+    pub(crate) fn pipeline(&self) -> Vec<Arc<dyn ProxyMiddleware>> {
+        self.middlewares
+            .lock()
+            .map(|middlewares| {
+                middlewares
+                    .iter()
+                    .map(|(_, middleware)| middleware.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Shows each event to the middlewares before passing it on
+pub(crate) struct MiddlewareSink<'a> {
+    inner: &'a dyn EventSink,
+    middlewares: &'a [Arc<dyn ProxyMiddleware>],
+}
+
+impl<'a> MiddlewareSink<'a> {
+    pub(crate) fn new(
+        inner: &'a dyn EventSink,
+        middlewares: &'a [Arc<dyn ProxyMiddleware>],
+    ) -> Self {
+        Self { inner, middlewares }
+    }
+}
+
+impl EventSink for MiddlewareSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        for middleware in self.middlewares {
+            middleware.on_event(&event);
+        }
+        self.inner.emit(event)
+    }
+}
+
+/// Scrubs anything shaped like an API key from the request before it leaves the app
+pub struct RedactSecrets;
+
+impl ProxyMiddleware for RedactSecrets {
+    fn before(&self, _provider: &str, body: &mut Value) {
+        redact_strings(body);
+    }
+}
+
+fn redact_strings(value: &mut Value) {
+    match value {
+        Value::String(text) => *text = redact_secrets(text, ""),
+        Value::Array(items) => items.iter_mut().for_each(redact_strings),
+        Value::Object(fields) => fields.values_mut().for_each(redact_strings),
+        _ => {}
+    }
+}
+
+/// Replaces the request's system prompt with a fixed one
+pub struct SystemPromptOverride {
+    prompt: String,
+}
+
+impl SystemPromptOverride {
+    pub fn new(prompt: String) -> Self {
+        Self { prompt }
+    }
+}
+
+impl ProxyMiddleware for SystemPromptOverride {
+    fn before(&self, provider: &str, body: &mut Value) {
+        if provider == "anthropic" {
+            if let Some(fields) = body.as_object_mut() {
+                fields.insert("system".to_string(), Value::String(self.prompt.clone()));
+            }
+            return;
+        }
+
+        // Requests without messages, such as speech, have no system prompt
+        let Some(messages) = body.get_mut("messages").and_then(Value::as_array_mut) else {
+            return;
+        };
+        messages.retain(|message| {
+            !matches!(
+                message.get("role").and_then(Value::as_str),
+                Some("system" | "developer")
+            )
+        });
+        messages.insert(0, json!({ "role": "system", "content": self.prompt }));
+    }
+}
@@ -0,0 +1,223 @@
+use crate::services::proxy::{emit_end, emit_start, emit_text, emit_usage};
+use crate::services::proxy::{
+    EventSink, FinishReason, ProxyError, ProxyProvider, ProxyResult, StreamOptions, StreamStart,
+    StreamUsage,
+};
+use async_trait::async_trait;
+use log::info;
+use serde_json::Value;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+/// Delay between chunks when the payload doesn't set `delay_ms`
+const DEFAULT_CHUNK_DELAY: Duration = Duration::from_millis(30);
+
+/// Directory under the app data dir that `transcript_path` is resolved against
+pub const TRANSCRIPTS_DIR: &str = "transcripts";
+
+/// Replays a canned transcript through the normal event path, for demos and frontend
+/// development without an API key.
+///
+/// The transcript comes from the payload, first match wins:
+/// - `chunks`: text deltas, each a string or `{"text", "delay_ms"}` to override the delay
+/// - `text`: a string streamed word by word
+/// - `transcript_path`: a file in the [`TRANSCRIPTS_DIR`] directory holding a JSON
+///   `chunks` array, or plain text streamed word by word
+///
+/// `delay_ms` sets the delay between chunks, `model` the reported model, and
+/// `finish_reason` the reason sent with the end event (`stop` by default).
+pub struct MockProvider;
+
+/// A text delta and the delay before it is sent
+struct MockChunk {
+    text: String,
+    delay: Duration,
+}
+
+fn parse_chunks(chunks: &[Value], delay: Duration) -> ProxyResult<Vec<MockChunk>> {
+    chunks
+        .iter()
+        .map(|chunk| {
+            let (text, chunk_delay) = match chunk {
+                Value::String(text) => (Some(text.as_str()), None),
+                chunk => (
+                    chunk.get("text").and_then(Value::as_str),
+                    chunk.get("delay_ms").and_then(Value::as_u64),
+                ),
+            };
+            let text = text.ok_or_else(|| {
+                ProxyError::InvalidPayload(
+                    "mock `chunks` must be strings or objects with a `text` string".to_string(),
+                )
+            })?;
+            Ok(MockChunk {
+                text: text.to_string(),
+                delay: chunk_delay.map_or(delay, Duration::from_millis),
+            })
+        })
+        .collect()
+}
+
+/// Split text into word-sized deltas, keeping the whitespace so they rejoin exactly
+fn split_words(text: &str, delay: Duration) -> Vec<MockChunk> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices().skip(1) {
+        let previous_space = text[..i].ends_with(char::is_whitespace);
+        if previous_space && !c.is_whitespace() {
+            chunks.push(&text[start..i]);
+            start = i;
+        }
+    }
+    if start < text.len() {
+        chunks.push(&text[start..]);
+    }
+    chunks
+        .into_iter()
+        .map(|text| MockChunk {
+            text: text.to_string(),
+            delay,
+        })
+        .collect()
+}
+
+/// Resolve a transcript path inside `dir`, refusing anything that could leave it
+fn transcript_file(dir: Option<&Path>, path: &str) -> ProxyResult<PathBuf> {
+    let dir = dir.ok_or_else(|| {
+        ProxyError::InvalidPayload("mock transcript files are not available".to_string())
+    })?;
+    let relative = Path::new(path);
+    let contained = relative.components().next().is_some()
+        && relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !contained {
+        return Err(ProxyError::InvalidPayload(format!(
+            "transcript_path must be a relative path inside the {} directory: {}",
+            TRANSCRIPTS_DIR, path
+        )));
+    }
+    Ok(dir.join(relative))
+}
+
+fn transcript(body: &Value, dir: Option<&Path>) -> ProxyResult<Vec<MockChunk>> {
+    let delay = body
+        .get("delay_ms")
+        .and_then(Value::as_u64)
+        .map_or(DEFAULT_CHUNK_DELAY, Duration::from_millis);
+
+    if let Some(chunks) = body.get("chunks").and_then(Value::as_array) {
+        return parse_chunks(chunks, delay);
+    }
+    if let Some(text) = body.get("text").and_then(Value::as_str) {
+        return Ok(split_words(text, delay));
+    }
+    if let Some(path) = body.get("transcript_path").and_then(Value::as_str) {
+        let file = transcript_file(dir, path)?;
+        let contents = std::fs::read_to_string(file).map_err(|e| {
+            ProxyError::InvalidPayload(format!("Failed to read transcript {}: {}", path, e))
+        })?;
+        return match serde_json::from_str::<Value>(&contents) {
+            Ok(Value::Array(chunks)) => parse_chunks(&chunks, delay),
+            _ => Ok(split_words(&contents, delay)),
+        };
+    }
+    Err(ProxyError::InvalidPayload(
+        "mock payload needs `chunks`, `text` or `transcript_path`".to_string(),
+    ))
+}
+
+#[async_trait]
+impl ProxyProvider for MockProvider {
+    async fn stream(
+        &self,
+        sink: &dyn EventSink,
+        body: Value,
+        options: StreamOptions,
+    ) -> ProxyResult<()> {
+        let chunks = transcript(&body, options.transcript_dir.as_deref())?;
+        info!("Replaying mock transcript of {} chunks", chunks.len());
+
+        emit_start(
+            sink,
+            StreamStart {
+                model: Some(
+                    body.get("model")
+                        .and_then(Value::as_str)
+                        .unwrap_or("mock")
+                        .to_string(),
+                ),
+                ..Default::default()
+            },
+        )?;
+        let count = chunks.len() as u64;
+        for chunk in chunks {
+            if !chunk.delay.is_zero() {
+                tokio::time::sleep(chunk.delay).await;
+            }
+            emit_text(sink, chunk.text)?;
+        }
+
+        emit_usage(
+            sink,
+            StreamUsage {
+                provider: "mock".to_string(),
+                input_tokens: Some(0),
+                output_tokens: Some(count),
+                total_tokens: Some(count),
+                ..Default::default()
+            },
+        )?;
+        let finish_reason = body
+            .get("finish_reason")
+            .and_then(Value::as_str)
+            .unwrap_or("stop");
+        emit_end(sink, Some(FinishReason::from_openai(finish_reason)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn resolves_transcripts_inside_the_directory() {
+        let dir = Path::new("data").join(TRANSCRIPTS_DIR);
+        assert_eq!(
+            transcript_file(Some(&dir), "demo/answer.json").unwrap(),
+            dir.join("demo").join("answer.json")
+        );
+    }
+
+    #[test]
+    fn rejects_transcripts_outside_the_directory() {
+        let dir = Path::new("data").join(TRANSCRIPTS_DIR);
+        let outside = std::env::temp_dir().join("secret.txt");
+        for path in [
+            "../secret.txt",
+            "demo/../../secret.txt",
+            "./answer.json",
+            "",
+            outside.to_str().unwrap(),
+        ] {
+            assert!(
+                matches!(
+                    transcript_file(Some(&dir), path),
+                    Err(ProxyError::InvalidPayload(_))
+                ),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_transcript_files_without_a_directory() {
+        let body = json!({"transcript_path": "answer.json"});
+        assert!(matches!(
+            transcript(&body, None),
+            Err(ProxyError::InvalidPayload(_))
+        ));
+    }
+}
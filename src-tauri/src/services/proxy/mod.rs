@@ -1,24 +1,136 @@
 use async_trait::async_trait;
 use dotenv::dotenv;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use serde::{Serialize, Serializer};
 use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
-use tauri::{Emitter, Window};
-use tauri_plugin_http::reqwest;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri_plugin_http::reqwest::{
+    self,
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    RequestBuilder, Response, StatusCode,
+};
 use thiserror::Error;
 
 // Expose provider modules
 mod anthropic;
+mod mock;
 mod openai;
+mod tts;
+
+pub mod active;
+pub mod alias;
+pub mod circuit;
+pub mod client;
+pub mod events;
+pub mod idempotency;
+pub mod keychain;
+pub mod keys;
+pub mod limiter;
+pub mod metrics;
+pub mod middleware;
+pub mod models;
+pub mod normalize;
+pub mod partial;
+pub mod pause;
+pub mod ratelimit;
+pub mod redirect;
+pub mod replay;
+pub mod retry;
+pub mod sse;
+pub mod state;
+pub mod stats;
+pub mod tools;
+pub mod trim;
+pub mod usage;
+pub mod validation;
+#[cfg(feature = "ws-server")]
+pub mod ws;
+
+#[cfg(test)]
+pub(crate) mod test_support;
 
 // Re-export provider structs
 pub use anthropic::AnthropicProvider;
-pub use openai::OpenAIProvider;
+pub use mock::{MockProvider, TRANSCRIPTS_DIR};
+pub use openai::{OpenAIProvider, OpenAITokenLogprob, OpenAITopLogprob};
+pub use tts::OpenAITtsProvider;
+
+pub use active::{ActiveStreamInfo, ActiveStreams, CancelledStream, StreamHandle};
+pub use alias::{ModelAlias, ModelAliases};
+pub use circuit::{CircuitBreakers, CircuitState};
+pub use client::{http_client, reset_http_client};
+pub use events::{
+    CallbackSink, ChannelEvent, ChannelSink, EventSink, FanoutSink, StreamEvent, WindowSink,
+};
+pub use keys::KeyPool;
+pub use limiter::StreamLimiter;
+pub use metrics::{Metrics, MetricsSnapshot};
+pub use middleware::{MiddlewareRegistry, ProxyMiddleware};
+pub use models::ModelInfo;
+pub use ratelimit::RateLimitInfo;
+pub use retry::RetryPolicy;
+pub use state::ProxyState;
+pub use stats::StreamStats;
+pub use tools::{ToolCall, ToolCallAccumulator, ToolCallDelta};
+pub use trim::StreamTrimmed;
+pub use usage::{UsageTracker, UserUsage};
+pub use validation::validate_payload;
+
+/// Version of the stream event schema, sent on the start event.
+///
+/// Bump whenever an event's shape changes or a new event is added.
+/// - 0: legacy `0:{json}\n` chunks with error and end events only (no start event)
+/// - 1: typed events, starting with `ai-stream-start`
+/// - 2: `ai-stream-incomplete` before the end event of a truncated stream
+/// - 3: `ai-stream-usage` with token counts
+/// - 4: `ai-stream-role` when the message role is first seen
+/// - 5: `incomplete` flag on tool calls cut off by a truncated stream
+/// - 6: `ai-stream-stats` with timing before the end event
+/// - 7: `stream_id` on the start event
+/// - 8: `ai-stream-audio-chunk` for audio providers
+/// - 9: optional request metadata wrapping every payload
+/// - 10: `model` and `input_tokens` on the start event
+/// - 11: `ai-stream-error-detail` with the error kind and a retry hint
+/// - 12: `ai-stream-fallback` naming the provider that served a fallback chain
+/// - 13: `ai-stream-trimmed` when old messages were dropped to fit the context window
+/// - 14: `ai-stream-citation` for sources cited by Anthropic text blocks
+/// - 15: `ai-stream-model-substituted` when the provider served a different model
+/// - 16: `clock_skew_ms` on the start and stats events
+/// - 17: `ai-stream-refusal` with the text of an OpenAI refusal
+/// - 18: `model_alias` on the start event of a request that named a model alias
+pub const STREAM_PROTOCOL_VERSION: u32 = 18;
 
 // Event type constants
 pub(crate) const EVT_CHUNK: &str = "ai-stream-chunk";
 pub(crate) const EVT_ERROR: &str = "ai-stream-error";
 pub(crate) const EVT_END: &str = "ai-stream-end";
+pub(crate) const EVT_QUEUED: &str = "ai-stream-queued";
+pub(crate) const EVT_LOGPROBS: &str = "ai-stream-logprobs";
+pub(crate) const EVT_RAW: &str = "ai-stream-raw";
+pub(crate) const EVT_TOOL_DELTA: &str = "ai-stream-tool-delta";
+pub(crate) const EVT_TOOL_CALL: &str = "ai-stream-tool-call";
+pub(crate) const EVT_CIRCUIT_OPEN: &str = "provider-circuit-open";
+pub(crate) const EVT_CIRCUIT_CLOSED: &str = "provider-circuit-closed";
+pub(crate) const EVT_START: &str = "ai-stream-start";
+pub(crate) const EVT_WARNING: &str = "ai-stream-warning";
+pub(crate) const EVT_FILTERED: &str = "ai-stream-filtered";
+pub(crate) const EVT_RATELIMIT: &str = "ai-stream-ratelimit";
+pub(crate) const EVT_INCOMPLETE: &str = "ai-stream-incomplete";
+pub(crate) const EVT_USAGE: &str = "ai-stream-usage";
+pub(crate) const EVT_ROLE: &str = "ai-stream-role";
+pub(crate) const EVT_STATS: &str = "ai-stream-stats";
+pub(crate) const EVT_AUDIO_CHUNK: &str = "ai-stream-audio-chunk";
+pub(crate) const EVT_ERROR_DETAIL: &str = "ai-stream-error-detail";
+pub(crate) const EVT_FALLBACK: &str = "ai-stream-fallback";
+pub(crate) const EVT_TRIMMED: &str = "ai-stream-trimmed";
+pub(crate) const EVT_CITATION: &str = "ai-stream-citation";
+pub(crate) const EVT_MODEL_SUBSTITUTED: &str = "ai-stream-model-substituted";
+pub(crate) const EVT_REFUSAL: &str = "ai-stream-refusal";
 
 /// Errors that can occur when working with API proxies
 #[derive(Error, Debug)]
@@ -37,25 +149,458 @@ pub enum ProxyError {
 
     #[error("Failed to emit event: {0}")]
     Emit(String),
+
+    #[error("Too many concurrent streams for provider {0}")]
+    TooManyRequests(String),
+
+    #[error("Invalid proxy configuration: {0}")]
+    Config(String),
+
+    #[error("Provider {0} is unavailable (circuit open), failing fast")]
+    CircuitOpen(String),
+
+    #[error("Invalid header: {0}")]
+    Header(String),
+
+    #[error("Invalid request payload: {0}")]
+    InvalidPayload(String),
+
+    #[error("Rate limited by provider (status 429)")]
+    RateLimited(Option<Duration>),
+
+    #[error("Provider returned a non-streaming response: {0}")]
+    UnexpectedResponse(String),
+
+    #[error("Timed out waiting for {0}")]
+    Timeout(String),
+
+    #[error("Server-sent event exceeded the {1} byte limit ({0} bytes without a boundary)")]
+    EventTooLarge(usize, usize),
+
+    #[error("Response exceeded the {1} byte output limit ({0} bytes received)")]
+    OutputTooLarge(usize, usize),
+
+    #[error("Unsupported response content-encoding: {0}")]
+    UnsupportedEncoding(String),
+
+    #[error("Expected the {expected} role, got {actual}")]
+    UnexpectedRole { expected: String, actual: String },
+
+    #[error("Provider ended the stream with {error_type}: {message}")]
+    StreamFailed { error_type: String, message: String },
+
+    #[error("Stream {0} is already active")]
+    DuplicateStream(String),
+}
+
+impl ProxyError {
+    /// Whether the error suggests the provider itself is failing, as opposed to a bad request
+    pub fn is_provider_failure(&self) -> bool {
+        match self {
+            ProxyError::Http(_) | ProxyError::Timeout(_) => true,
+            ProxyError::Status(status) => *status >= 500,
+            ProxyError::StreamFailed { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the error is specific to the API key used, so another key may succeed
+    pub fn is_key_failure(&self) -> bool {
+        matches!(self, ProxyError::Status(401) | ProxyError::RateLimited(_))
+    }
+}
+
+/// Provider-independent reason a stream finished
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinishReason {
+    /// The model reached a natural stopping point
+    Stop,
+    /// The output token limit was reached
+    Length,
+    /// The model requested one or more tool calls
+    ToolCalls,
+    /// The output was blocked by a content filter
+    ContentFilter,
+    /// A caller-supplied stop sequence was generated
+    StopSequence,
+    /// A reason this proxy does not know about, kept verbatim
+    Other(String),
+}
+
+impl FinishReason {
+    /// Map an OpenAI `finish_reason` value
+    pub fn from_openai(reason: &str) -> Self {
+        match reason {
+            "stop" => FinishReason::Stop,
+            "length" => FinishReason::Length,
+            "tool_calls" | "function_call" => FinishReason::ToolCalls,
+            "content_filter" => FinishReason::ContentFilter,
+            other => FinishReason::Other(other.to_string()),
+        }
+    }
+
+    /// Map an Anthropic `stop_reason` value
+    pub fn from_anthropic(reason: &str) -> Self {
+        match reason {
+            "end_turn" => FinishReason::Stop,
+            "max_tokens" => FinishReason::Length,
+            "tool_use" => FinishReason::ToolCalls,
+            "stop_sequence" => FinishReason::StopSequence,
+            "refusal" => FinishReason::ContentFilter,
+            other => FinishReason::Other(other.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::ToolCalls => "tool_calls",
+            FinishReason::ContentFilter => "content_filter",
+            FinishReason::StopSequence => "stop_sequence",
+            FinishReason::Other(reason) => reason,
+        }
+    }
+}
+
+impl Serialize for FinishReason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Payload of the stream start event
+#[derive(Serialize, Debug, Clone)]
+pub struct StreamStart {
+    /// Event schema version, see [`STREAM_PROTOCOL_VERSION`]
+    pub protocol_version: u32,
+    /// Id of the stream in the active stream registry, for `cancel_stream`
+    pub stream_id: Option<String>,
+    /// Backend configuration fingerprint reported by the provider, if any
+    pub system_fingerprint: Option<String>,
+    /// Model serving the request, when the provider reports it up front
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Prompt tokens, when the provider reports them up front
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u64>,
+    /// Local receipt time of the first chunk minus the provider's creation timestamp,
+    /// in milliseconds, when the provider reports one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_skew_ms: Option<i64>,
+    /// Model alias the request named; `model` is then the model it resolved to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_alias: Option<String>,
+}
+
+impl StreamStart {
+    pub fn new(system_fingerprint: Option<String>) -> Self {
+        Self {
+            protocol_version: STREAM_PROTOCOL_VERSION,
+            stream_id: None,
+            system_fingerprint,
+            model: None,
+            input_tokens: None,
+            clock_skew_ms: None,
+            model_alias: None,
+        }
+    }
+}
+
+impl Default for StreamStart {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// Token usage reported by the provider
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct StreamUsage {
+    pub provider: String,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    pub total_tokens: Option<u64>,
+    /// Predicted output tokens that appeared in the completion (OpenAI predicted outputs)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accepted_prediction_tokens: Option<u64>,
+    /// Predicted output tokens that were discarded, but still billed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejected_prediction_tokens: Option<u64>,
+}
+
+/// Category of a stream error, so the frontend can react without parsing messages
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The API key was rejected
+    Auth,
+    /// The provider is throttling requests; retry after a pause
+    RateLimit,
+    /// The connection failed or broke off
+    Network,
+    /// The provider's response couldn't be decoded
+    Parse,
+    /// The provider reported an error
+    Upstream,
+}
+
+impl ErrorKind {
+    /// Classify a failed HTTP status, returning whether the request is worth retrying
+    pub fn from_status(status: StatusCode) -> (Self, bool) {
+        match status.as_u16() {
+            401 | 403 => (ErrorKind::Auth, false),
+            429 => (ErrorKind::RateLimit, true),
+            500..=599 => (ErrorKind::Upstream, true),
+            _ => (ErrorKind::Upstream, false),
+        }
+    }
+}
+
+/// Payload of the structured error event
+#[derive(Serialize, Debug, Clone)]
+pub struct StreamError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// Whether retrying the same request may succeed
+    pub retryable: bool,
+}
+
+/// A fragment of streamed audio
+#[derive(Serialize, Debug, Clone)]
+pub struct AudioChunk {
+    /// Audio format as requested from the provider, e.g. `mp3`
+    pub format: String,
+    pub mime: String,
+    /// Base64-encoded audio bytes
+    pub data: String,
+}
+
+/// Payload of the fallback event, sent once a provider in a fallback chain served the
+/// request
+#[derive(Serialize, Debug, Clone)]
+pub struct StreamFallback {
+    pub provider: String,
+    /// Providers tried first that failed before streaming anything, in order
+    pub failed: Vec<String>,
+}
+
+/// Payload of the citation event, a source cited by a text content block
+#[derive(Serialize, Debug, Clone)]
+pub struct StreamCitation {
+    /// Index of the content block whose text the citation supports
+    pub index: u32,
+    /// Characters of text streamed before that block started, so the client can
+    /// locate the annotated text
+    pub text_offset: usize,
+    /// The citation as sent by the provider: its type, `cited_text`, and the source
+    /// location (URL and title, document index and character or page range, ...)
+    pub citation: Value,
+}
+
+/// Payload of the model substituted event, sent when the provider reports serving a
+/// different model than the request named
+#[derive(Serialize, Debug, Clone)]
+pub struct ModelSubstitution {
+    pub requested: String,
+    pub served: String,
+}
+
+/// Payload of the filtered event, sent when the provider blocked the output
+#[derive(Serialize, Debug, Clone)]
+pub struct StreamFiltered {
+    pub provider: String,
+    /// The provider's own reason or category for the block
+    pub reason: String,
+}
+
+/// Payload of the stream end event
+#[derive(Serialize, Debug, Clone)]
+pub struct StreamEndPayload {
+    pub finish_reason: Option<FinishReason>,
 }
 
 /// Result type for proxy operations
 pub type ProxyResult<T> = Result<T, ProxyError>;
 
+/// Default bound on connecting and receiving response headers from a provider
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default cap on a single server-sent event, so a malformed upstream can't exhaust memory
+pub const DEFAULT_MAX_EVENT_BYTES: usize = 16 * 1024 * 1024;
+
+/// `anthropic-version` sent when none is configured
+pub const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Per-request options for a stream
+#[derive(Debug, Clone, Default)]
+pub struct StreamOptions {
+    /// Accumulate the raw response text and emit it at the end of the stream
+    pub capture_raw: bool,
+    /// Additional headers sent with the upstream request
+    pub extra_headers: HeaderMap,
+    /// Id to register the stream under, generated when absent
+    pub stream_id: Option<String>,
+    /// End user the request is made for, sent to the provider and tracked locally
+    pub user_id: Option<String>,
+    /// Time allowed to connect and receive the response headers, before any streaming
+    /// starts; [`DEFAULT_REQUEST_TIMEOUT`] when unset
+    pub request_timeout: Option<Duration>,
+    /// Largest single server-sent event accepted; [`DEFAULT_MAX_EVENT_BYTES`] when unset
+    pub max_event_bytes: Option<usize>,
+    /// Most response bytes read before the stream is cut off, closing the connection so
+    /// the provider stops generating; unlimited when unset
+    pub max_output_bytes: Option<usize>,
+    /// Drop the oldest messages when the request is estimated not to fit the model's
+    /// context window
+    pub auto_trim: bool,
+    /// Most messages the request may carry; longer histories are rejected, or trimmed
+    /// when `auto_trim` is set
+    pub max_messages: Option<usize>,
+    /// Role the streamed message must have; the stream is aborted if the first role
+    /// event differs
+    pub expected_role: Option<String>,
+    /// `anthropic-version` for Anthropic requests; [`DEFAULT_ANTHROPIC_VERSION`] when unset
+    pub anthropic_version: Option<String>,
+    /// Anthropic beta features to enable, sent joined in the `anthropic-beta` header
+    pub anthropic_beta: Vec<String>,
+    /// Keep the generated text so `cancel_stream` can return it
+    pub keep_partial: bool,
+    /// Times a dropped event stream may be resumed with `Last-Event-ID`, for servers
+    /// that send event ids
+    pub max_resumes: u32,
+    /// Directory the mock provider reads `transcript_path` files from; transcript files
+    /// are refused when unset
+    pub transcript_dir: Option<PathBuf>,
+    /// Running count of bytes received, shared with the active stream registry
+    pub(crate) bytes_streamed: Option<Arc<AtomicU64>>,
+}
+
+/// Headers callers may not set, so the provider credentials and framing can't be overridden
+const RESERVED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "content-type",
+    "content-length",
+    "transfer-encoding",
+    "host",
+];
+
+/// Validate caller-supplied headers and convert them into a `HeaderMap`
+pub fn build_extra_headers(headers: &HashMap<String, String>) -> ProxyResult<HeaderMap> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let header_name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|e| ProxyError::Header(format!("Invalid header name '{}': {}", name, e)))?;
+        if RESERVED_HEADERS.contains(&header_name.as_str()) {
+            return Err(ProxyError::Header(format!(
+                "Header '{}' is reserved and cannot be overridden",
+                header_name
+            )));
+        }
+        let header_value = HeaderValue::from_str(value).map_err(|e| {
+            ProxyError::Header(format!("Invalid value for header '{}': {}", name, e))
+        })?;
+        header_map.insert(header_name, header_value);
+    }
+    Ok(header_map)
+}
+
+/// Add the request's extra headers on top of the provider's own headers
+pub(crate) fn apply_extra_headers(headers: &mut HeaderMap, options: &StreamOptions) {
+    for (name, value) in options.extra_headers.iter() {
+        debug!("Adding extra header: {}", name);
+        headers.insert(name.clone(), value.clone());
+    }
+}
+
 /// Trait for API providers that can stream responses
 #[async_trait]
 pub trait ProxyProvider {
     /// Stream a response from the API provider
-    async fn stream(&self, window: Window, body: Value) -> ProxyResult<()>;
+    async fn stream(
+        &self,
+        sink: &dyn EventSink,
+        body: Value,
+        options: StreamOptions,
+    ) -> ProxyResult<()>;
+}
+
+/// Environment variable holding each supported provider's API key
+pub const PROVIDER_KEY_VARS: &[(&str, &str)] = &[
+    ("anthropic", "ANTHROPIC_API_KEY"),
+    ("openai", "OPENAI_API_KEY"),
+    ("openai-tts", "OPENAI_API_KEY"),
+];
+
+/// Providers that run locally and need no API key
+pub const KEYLESS_PROVIDERS: &[&str] = &["mock"];
+
+pub(crate) fn key_var(provider: &str) -> Option<&'static str> {
+    PROVIDER_KEY_VARS
+        .iter()
+        .find(|(name, _)| *name == provider)
+        .map(|(_, var)| *var)
+}
+
+/// Environment variable names set in place of the defaults, by provider. Kept
+/// process-wide like the environment itself.
+static KEY_VAR_OVERRIDES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Environment variable the provider's API key is read from, honouring overrides
+pub fn key_env_var(provider: &str) -> Option<String> {
+    let default = key_var(provider)?;
+    let overridden = KEY_VAR_OVERRIDES
+        .lock()
+        .ok()
+        .and_then(|overrides| overrides.get(provider).cloned());
+    Some(overridden.unwrap_or_else(|| default.to_string()))
 }
 
-/// Load an API key from environment variables for the given provider
+/// Whether `name` is a portable environment variable name: ASCII letters, digits and
+/// underscores, not starting with a digit
+fn is_env_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Read the provider's API key from `var_name` instead of its default variable.
+/// Setting the default name again removes the override.
+pub fn set_key_env_var(provider: &str, var_name: &str) -> ProxyResult<()> {
+    let default = key_var(provider)
+        .ok_or_else(|| ProxyError::ApiKey(format!("Unsupported provider: {}", provider)))?;
+    if !is_env_var_name(var_name) {
+        return Err(ProxyError::Config(format!(
+            "Invalid environment variable name: {:?}",
+            var_name
+        )));
+    }
+
+    let mut overrides = KEY_VAR_OVERRIDES
+        .lock()
+        .map_err(|e| ProxyError::Config(format!("Key variable lock poisoned: {}", e)))?;
+    if var_name == default {
+        overrides.remove(provider);
+    } else {
+        overrides.insert(provider.to_string(), var_name.to_string());
+    }
+    info!("{} API key will be read from {}", provider, var_name);
+    Ok(())
+}
+
+/// Load an API key for the given provider from the OS keychain, falling back to
+/// environment variables
 pub fn load_api_key(provider: &str) -> ProxyResult<String> {
+    if KEYLESS_PROVIDERS.contains(&provider) {
+        return Ok(String::new());
+    }
     dotenv().ok();
-    let key_name = match provider {
-        "anthropic" => "ANTHROPIC_API_KEY",
-        "openai" => "OPENAI_API_KEY",
-        _ => {
+    let key_name = match key_env_var(provider) {
+        Some(key_name) => key_name,
+        None => {
             return Err(ProxyError::ApiKey(format!(
                 "Unsupported provider: {}",
                 provider
@@ -63,9 +608,14 @@ pub fn load_api_key(provider: &str) -> ProxyResult<String> {
         }
     };
 
+    if let Some(key) = keychain::read_key(provider) {
+        debug!("{} loaded from the keychain", key_name);
+        return Ok(key);
+    }
+
     debug!("Loading {} from environment/dotenv", key_name);
 
-    match env::var(key_name) {
+    match env::var(&key_name) {
         Ok(key) => {
             let redacted = if key.len() > 10 {
                 format!("{}...{}", &key[..5], &key[key.len() - 5..])
@@ -83,13 +633,98 @@ pub fn load_api_key(provider: &str) -> ProxyResult<String> {
     }
 }
 
+/// Re-read the `.env` file, overriding variables already set in the process.
+///
+/// `dotenv()` never overrides existing variables, so without this an edited key is
+/// only picked up after a restart. Returns the API key variables now present.
+pub fn reload_env() -> ProxyResult<Vec<String>> {
+    match dotenv::dotenv_iter() {
+        Ok(entries) => {
+            let mut reloaded = 0;
+            for entry in entries {
+                let (key, value) = entry
+                    .map_err(|e| ProxyError::Config(format!("Failed to parse .env: {}", e)))?;
+                env::set_var(key, value);
+                reloaded += 1;
+            }
+            info!("Reloaded {} variables from .env", reloaded);
+            // Pick up a changed trust store on the next request
+            reset_http_client();
+        }
+        Err(e) if e.not_found() => warn!("No .env file found to reload"),
+        Err(e) => return Err(ProxyError::Config(format!("Failed to read .env: {}", e))),
+    }
+
+    let mut present: Vec<String> = PROVIDER_KEY_VARS
+        .iter()
+        .filter_map(|(provider, _)| key_env_var(provider))
+        .filter(|var| env::var(var).is_ok_and(|key| !key.is_empty()))
+        .collect();
+    // Providers may share a key variable
+    present.sort();
+    present.dedup();
+    Ok(present)
+}
+
+/// Shortest `sk-` token treated as an API key when scrubbing debug output
+const MIN_REDACTED_KEY_LEN: usize = 20;
+
+/// Scrub API keys from text captured for debugging.
+///
+/// Keys are never sent in the response body, but the raw dump may be shared in bug
+/// reports, so the known key and anything shaped like an `sk-` key is redacted anyway.
+pub fn redact_secrets(text: &str, api_key: &str) -> String {
+    let mut redacted = if api_key.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(api_key, "[REDACTED]")
+    };
+
+    let mut search_from = 0;
+    while let Some(offset) = redacted[search_from..].find("sk-") {
+        let start = search_from + offset;
+        let end = redacted[start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .map(|len| start + len)
+            .unwrap_or(redacted.len());
+        let at_word_start = redacted[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_ascii_alphanumeric());
+
+        if at_word_start && end - start >= MIN_REDACTED_KEY_LEN {
+            redacted.replace_range(start..end, "[REDACTED]");
+            search_from = start + "[REDACTED]".len();
+        } else {
+            search_from = end.max(start + 3);
+        }
+    }
+
+    redacted
+}
+
+/// Frame a text fragment in the legacy chunk wire format, `0:{json string}\n`
+pub fn format_text_chunk(text: &str) -> ProxyResult<String> {
+    let text_json = serde_json::to_string(text)?;
+    Ok(format!("0:{}\n", text_json))
+}
+
 /// Get a provider implementation based on the provider name
 pub fn get_provider(provider: &str) -> ProxyResult<Box<dyn ProxyProvider + Send + Sync>> {
     let api_key = load_api_key(provider)?;
+    provider_with_key(provider, api_key)
+}
 
+/// Get a provider implementation using a specific API key
+pub fn provider_with_key(
+    provider: &str,
+    api_key: String,
+) -> ProxyResult<Box<dyn ProxyProvider + Send + Sync>> {
     match provider {
         "anthropic" => Ok(Box::new(AnthropicProvider::new(api_key))),
         "openai" => Ok(Box::new(OpenAIProvider::new(api_key))),
+        "openai-tts" => Ok(Box::new(OpenAITtsProvider::new(api_key))),
+        "mock" => Ok(Box::new(MockProvider)),
         _ => Err(ProxyError::ApiKey(format!(
             "Unsupported provider: {}",
             provider
@@ -97,30 +732,440 @@ pub fn get_provider(provider: &str) -> ProxyResult<Box<dyn ProxyProvider + Send
     }
 }
 
+/// Surface a non-success response as an error event and `ProxyError::Status`.
+///
+/// Rate-limit headers are emitted for every response so the client can throttle.
+pub(crate) async fn check_status(
+    response: Response,
+    sink: &dyn EventSink,
+    provider: &str,
+    provider_label: &str,
+) -> ProxyResult<Response> {
+    let status = response.status();
+    let rate_limit = RateLimitInfo::from_headers(provider, response.headers());
+    let retry_delay = rate_limit.as_ref().and_then(RateLimitInfo::retry_delay);
+    if let Some(rate_limit) = rate_limit {
+        emit_rate_limit(sink, rate_limit)?;
+    }
+
+    if !status.is_success() {
+        let error_body = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Failed to read error body".to_string());
+        let error_msg = format!(
+            "{} API request failed with status {}: {}",
+            provider_label, status, error_body
+        );
+        let (kind, retryable) = ErrorKind::from_status(status);
+        emit_structured_error(sink, kind, error_msg, retryable)?;
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(ProxyError::RateLimited(retry_delay));
+        }
+        return Err(ProxyError::Status(status.as_u16()));
+    }
+    info!(
+        "{} API request successful (status: {})",
+        provider_label, status
+    );
+    Ok(response)
+}
+
+/// Send an upstream request, failing if the response headers don't arrive within the
+/// request timeout.
+///
+/// The timeout covers only the connection and headers; the streamed body that follows
+/// can take as long as the model needs.
+pub(crate) async fn send_request(
+    request: RequestBuilder,
+    options: &StreamOptions,
+    provider_label: &str,
+) -> ProxyResult<Response> {
+    let timeout = options.request_timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT);
+    match tokio::time::timeout(timeout, request.send()).await {
+        Ok(response) => Ok(response?),
+        Err(_) => {
+            warn!("{} did not respond within {:?}", provider_label, timeout);
+            Err(ProxyError::Timeout(format!(
+                "connection to {} ({:?})",
+                provider_label, timeout
+            )))
+        }
+    }
+}
+
+/// Read a successful response that isn't an event stream as a single JSON document.
+///
+/// A body carrying an `error` object, or one that isn't JSON, is surfaced as an error
+/// event; otherwise the parsed body is returned for the provider to handle as a
+/// complete response.
+pub(crate) async fn read_json_body(
+    response: Response,
+    sink: &dyn EventSink,
+    provider_label: &str,
+) -> ProxyResult<Value> {
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    warn!(
+        "{} returned {} instead of an event stream",
+        provider_label, content_type
+    );
+    let text = response.text().await?;
+
+    let body: Value = match serde_json::from_str(&text) {
+        Ok(body) => body,
+        Err(_) => {
+            let error_msg = format!(
+                "{} returned a non-streaming {} response: {}",
+                provider_label, content_type, text
+            );
+            emit_structured_error(sink, ErrorKind::Parse, error_msg, false)?;
+            return Err(ProxyError::UnexpectedResponse(content_type));
+        }
+    };
+
+    if let Some(error) = body.get("error") {
+        let message = error
+            .get("message")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string());
+        let error_msg = format!("{} API error: {}", provider_label, message);
+        emit_error(sink, &error_msg)?;
+        return Err(ProxyError::UnexpectedResponse(message));
+    }
+    Ok(body)
+}
+
 // --- Event Emission Helpers ---
 
-/// Emit an error event to the client
-pub(crate) fn emit_error<S: Into<String>>(window: &Window, message: S) -> ProxyResult<()> {
-    let msg = message.into();
-    error!("Emitting Error: {}", msg);
-    window
-        .emit(EVT_ERROR, &msg)
-        .map_err(|e| ProxyError::Emit(format!("Failed to emit error event: {}", e)))
+/// Emit an error event to the client, classified as a non-retryable upstream error
+pub(crate) fn emit_error<S: Into<String>>(sink: &dyn EventSink, message: S) -> ProxyResult<()> {
+    emit_structured_error(sink, ErrorKind::Upstream, message, false)
+}
+
+/// Emit a classified error event to the client
+pub(crate) fn emit_structured_error<S: Into<String>>(
+    sink: &dyn EventSink,
+    kind: ErrorKind,
+    message: S,
+    retryable: bool,
+) -> ProxyResult<()> {
+    let message = message.into();
+    error!("Emitting {:?} Error: {}", kind, message);
+    sink.emit(StreamEvent::Error(StreamError {
+        kind,
+        message,
+        retryable,
+    }))
+}
+
+/// Emit a fragment of generated text to the client
+pub(crate) fn emit_text<S: Into<String>>(sink: &dyn EventSink, text: S) -> ProxyResult<()> {
+    let text = text.into();
+    debug!("Emitting chunk ({} bytes)", text.len());
+    sink.emit(StreamEvent::Text { text })
+}
+
+/// Emit the start event once the provider begins responding
+pub(crate) fn emit_start(sink: &dyn EventSink, start: StreamStart) -> ProxyResult<()> {
+    info!(
+        "Emitting stream start event (fingerprint: {})",
+        start.system_fingerprint.as_deref().unwrap_or("none")
+    );
+    sink.emit(StreamEvent::Start(start))
 }
 
-/// Emit a chunk of data to the client
-pub(crate) fn emit_chunk<S: Into<String>>(window: &Window, data: S) -> ProxyResult<()> {
-    let chunk_data = data.into();
-    debug!("Emitting chunk ({} bytes)", chunk_data.len());
-    window
-        .emit(EVT_CHUNK, &chunk_data)
-        .map_err(|e| ProxyError::Emit(format!("Failed to emit chunk event: {}", e)))
+/// Emit a chunk of audio to the client
+pub(crate) fn emit_audio(sink: &dyn EventSink, chunk: AudioChunk) -> ProxyResult<()> {
+    debug!("Emitting audio chunk ({} base64 chars)", chunk.data.len());
+    sink.emit(StreamEvent::Audio(chunk))
+}
+
+/// Emit the role of the streamed message
+pub(crate) fn emit_role(sink: &dyn EventSink, role: String) -> ProxyResult<()> {
+    debug!("Emitting role: {}", role);
+    sink.emit(StreamEvent::Role { role })
+}
+
+/// Emit a non-fatal warning about the stream
+pub(crate) fn emit_warning<S: Into<String>>(sink: &dyn EventSink, message: S) -> ProxyResult<()> {
+    let message = message.into();
+    warn!("Emitting warning: {}", message);
+    sink.emit(StreamEvent::Warning { message })
+}
+
+/// Emit a filtered event when the provider blocked content
+pub(crate) fn emit_filtered(
+    sink: &dyn EventSink,
+    provider: &str,
+    reason: String,
+) -> ProxyResult<()> {
+    warn!("Emitting filtered event for {}: {}", provider, reason);
+    sink.emit(StreamEvent::Filtered(StreamFiltered {
+        provider: provider.to_string(),
+        reason,
+    }))
+}
+
+/// Emit the provider's rate-limit state to the client
+pub(crate) fn emit_rate_limit(sink: &dyn EventSink, rate_limit: RateLimitInfo) -> ProxyResult<()> {
+    debug!("Emitting rate limit: {:?}", rate_limit);
+    sink.emit(StreamEvent::RateLimit(rate_limit))
+}
+
+/// Emit an incomplete event when the stream ended without the provider's terminal marker
+pub(crate) fn emit_incomplete(sink: &dyn EventSink, provider: &str) -> ProxyResult<()> {
+    warn!(
+        "{} stream ended without a terminal event, output may be truncated",
+        provider
+    );
+    sink.emit(StreamEvent::Incomplete {
+        provider: provider.to_string(),
+    })
+}
+
+/// Emit the token usage for the stream
+pub(crate) fn emit_usage(sink: &dyn EventSink, usage: StreamUsage) -> ProxyResult<()> {
+    info!(
+        "Emitting usage for {}: {:?} in, {:?} out",
+        usage.provider, usage.input_tokens, usage.output_tokens
+    );
+    sink.emit(StreamEvent::Usage(usage))
 }
 
 /// Emit an end event to the client
-pub(crate) fn emit_end(window: &Window) -> ProxyResult<()> {
-    info!("Emitting stream end event");
-    window
-        .emit(EVT_END, ())
-        .map_err(|e| ProxyError::Emit(format!("Failed to emit end event: {}", e)))
+pub(crate) fn emit_end(
+    sink: &dyn EventSink,
+    finish_reason: Option<FinishReason>,
+) -> ProxyResult<()> {
+    info!(
+        "Emitting stream end event (finish reason: {})",
+        finish_reason
+            .as_ref()
+            .map(FinishReason::as_str)
+            .unwrap_or("none")
+    );
+    sink.emit(StreamEvent::End { finish_reason })
+}
+
+/// Emit a queued event when a stream is waiting for a free slot
+pub(crate) fn emit_queued(sink: &dyn EventSink, provider: &str) -> ProxyResult<()> {
+    info!("Emitting queued event for provider: {}", provider);
+    sink.emit(StreamEvent::Queued {
+        provider: provider.to_string(),
+    })
+}
+
+/// Emit the log probabilities for the tokens of the preceding chunk
+pub(crate) fn emit_logprobs(
+    sink: &dyn EventSink,
+    tokens: Vec<OpenAITokenLogprob>,
+) -> ProxyResult<()> {
+    debug!("Emitting logprobs for {} tokens", tokens.len());
+    sink.emit(StreamEvent::Logprobs { tokens })
+}
+
+/// Emit the text of a refusal, kept apart from the generated content
+pub(crate) fn emit_refusal(sink: &dyn EventSink, text: String) -> ProxyResult<()> {
+    info!("Emitting refusal ({} bytes)", text.len());
+    sink.emit(StreamEvent::Refusal { text })
+}
+
+/// Emit the raw response text received from the provider
+pub(crate) fn emit_raw(sink: &dyn EventSink, raw: String) -> ProxyResult<()> {
+    debug!("Emitting raw response ({} bytes)", raw.len());
+    sink.emit(StreamEvent::Raw { raw })
+}
+
+/// Emit a partial tool call argument fragment as it arrives
+pub(crate) fn emit_tool_delta(sink: &dyn EventSink, delta: ToolCallDelta) -> ProxyResult<()> {
+    debug!(
+        "Emitting tool call delta for index {} ({} bytes)",
+        delta.index,
+        delta.arguments_delta.len()
+    );
+    sink.emit(StreamEvent::ToolDelta(delta))
+}
+
+/// Emit a fully assembled tool call
+pub(crate) fn emit_tool_call(sink: &dyn EventSink, call: ToolCall) -> ProxyResult<()> {
+    info!("Emitting tool call {} ({})", call.index, call.name);
+    sink.emit(StreamEvent::ToolCall(call))
+}
+
+/// Emit how many messages were dropped to fit the context window
+pub(crate) fn emit_trimmed(sink: &dyn EventSink, trimmed: StreamTrimmed) -> ProxyResult<()> {
+    info!(
+        "Emitting trimmed: dropped {} messages to fit {} tokens",
+        trimmed.dropped, trimmed.context_window
+    );
+    sink.emit(StreamEvent::Trimmed(trimmed))
+}
+
+/// Emit a source cited by a text content block
+pub(crate) fn emit_citation(sink: &dyn EventSink, citation: StreamCitation) -> ProxyResult<()> {
+    debug!(
+        "Emitting citation for block {} at offset {}",
+        citation.index, citation.text_offset
+    );
+    sink.emit(StreamEvent::Citation(citation))
+}
+
+/// Emit the model the provider served in place of the requested one
+pub(crate) fn emit_model_substituted(
+    sink: &dyn EventSink,
+    substitution: ModelSubstitution,
+) -> ProxyResult<()> {
+    info!(
+        "Emitting model substituted: requested {}, served {}",
+        substitution.requested, substitution.served
+    );
+    sink.emit(StreamEvent::ModelSubstituted(substitution))
+}
+
+/// Emit the provider that served a fallback chain
+pub(crate) fn emit_fallback(sink: &dyn EventSink, fallback: StreamFallback) -> ProxyResult<()> {
+    info!(
+        "Emitting fallback: served by {} after {:?}",
+        fallback.provider, fallback.failed
+    );
+    sink.emit(StreamEvent::Fallback(fallback))
+}
+
+/// Emit a provider circuit breaker transition
+pub(crate) fn emit_circuit_state(
+    sink: &dyn EventSink,
+    provider: &str,
+    state: CircuitState,
+) -> ProxyResult<()> {
+    info!("Emitting circuit {:?} for provider: {}", state, provider);
+    sink.emit(StreamEvent::Circuit {
+        provider: provider.to_string(),
+        state,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::proxy::test_support::{MockResponse, MockServer, RecordingSink};
+
+    #[test]
+    fn maps_provider_finish_reasons() {
+        assert_eq!(FinishReason::from_openai("stop"), FinishReason::Stop);
+        assert_eq!(FinishReason::from_openai("length"), FinishReason::Length);
+        assert_eq!(
+            FinishReason::from_openai("tool_calls"),
+            FinishReason::ToolCalls
+        );
+        assert_eq!(
+            FinishReason::from_openai("function_call"),
+            FinishReason::ToolCalls
+        );
+        assert_eq!(
+            FinishReason::from_openai("content_filter"),
+            FinishReason::ContentFilter
+        );
+
+        assert_eq!(FinishReason::from_anthropic("end_turn"), FinishReason::Stop);
+        assert_eq!(
+            FinishReason::from_anthropic("max_tokens"),
+            FinishReason::Length
+        );
+        assert_eq!(
+            FinishReason::from_anthropic("tool_use"),
+            FinishReason::ToolCalls
+        );
+        assert_eq!(
+            FinishReason::from_anthropic("stop_sequence"),
+            FinishReason::StopSequence
+        );
+        assert_eq!(
+            FinishReason::from_anthropic("refusal"),
+            FinishReason::ContentFilter
+        );
+    }
+
+    #[test]
+    fn keeps_unknown_finish_reasons_verbatim() {
+        let reason = FinishReason::from_anthropic("pause_turn");
+        assert_eq!(reason, FinishReason::Other("pause_turn".to_string()));
+        assert_eq!(serde_json::to_value(&reason).unwrap(), "pause_turn");
+        assert_eq!(
+            serde_json::to_value(FinishReason::StopSequence).unwrap(),
+            "stop_sequence"
+        );
+    }
+
+    #[test]
+    fn frames_text_chunks_as_json_strings() {
+        assert_eq!(format_text_chunk("Hello").unwrap(), "0:\"Hello\"\n");
+        assert_eq!(
+            format_text_chunk("line\n\"quoted\" \u{e9}").unwrap(),
+            "0:\"line\\n\\\"quoted\\\" \u{e9}\"\n"
+        );
+        assert_eq!(format_text_chunk("").unwrap(), "0:\"\"\n");
+    }
+
+    #[test]
+    fn classifies_failed_statuses() {
+        let cases = [
+            (401, ErrorKind::Auth, false),
+            (403, ErrorKind::Auth, false),
+            (429, ErrorKind::RateLimit, true),
+            (500, ErrorKind::Upstream, true),
+            (503, ErrorKind::Upstream, true),
+            (529, ErrorKind::Upstream, true),
+            (400, ErrorKind::Upstream, false),
+            (404, ErrorKind::Upstream, false),
+            (422, ErrorKind::Upstream, false),
+        ];
+        for (status, kind, retryable) in cases {
+            assert_eq!(
+                ErrorKind::from_status(StatusCode::from_u16(status).unwrap()),
+                (kind, retryable),
+                "status {}",
+                status
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn surfaces_an_error_sent_in_place_of_a_stream() {
+        let server = MockServer::start(vec![
+            MockResponse::new(
+                "application/json",
+                r#"{"error": {"message": "model overloaded"}}"#,
+            ),
+            MockResponse::new("text/html", "<h1>Bad gateway</h1>"),
+        ])
+        .await;
+
+        let sink = RecordingSink::default();
+        let response = reqwest::get(&server.url).await.unwrap();
+        let result = read_json_body(response, &sink, "OpenAI").await;
+        assert!(
+            matches!(result, Err(ProxyError::UnexpectedResponse(message)) if message == "model overloaded")
+        );
+        assert!(matches!(
+            &sink.events()[..],
+            [StreamEvent::Error(error)] if error.message == "OpenAI API error: model overloaded"
+        ));
+
+        let sink = RecordingSink::default();
+        let response = reqwest::get(&server.url).await.unwrap();
+        let result = read_json_body(response, &sink, "OpenAI").await;
+        assert!(
+            matches!(result, Err(ProxyError::UnexpectedResponse(content_type)) if content_type == "text/html")
+        );
+        assert!(matches!(
+            &sink.events()[..],
+            [StreamEvent::Error(error)] if error.kind == ErrorKind::Parse
+        ));
+    }
 }
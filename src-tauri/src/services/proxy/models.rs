@@ -0,0 +1,263 @@
+use crate::services::proxy::{http_client, ProxyError, ProxyResult};
+use log::{debug, info};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri_plugin_http::reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+/// How long a fetched model list is reused before asking the provider again
+pub const MODEL_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Context windows for model families whose API doesn't report one, matched by id prefix.
+/// More specific prefixes come first.
+const KNOWN_CONTEXT_WINDOWS: &[(&str, u64)] = &[
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("claude", 200_000),
+];
+
+/// Most output tokens a request to a model family may ask for, matched by id prefix.
+/// More specific prefixes come first. Limits a beta header can raise are left out.
+const KNOWN_MAX_OUTPUT_TOKENS: &[(&str, u64)] = &[
+    ("gpt-4.1", 32_768),
+    ("gpt-4o", 16_384),
+    ("gpt-4-turbo", 4_096),
+    ("gpt-3.5-turbo", 4_096),
+    ("o1-mini", 65_536),
+    ("o1", 100_000),
+    ("o3", 100_000),
+    ("o4", 100_000),
+    ("claude-opus-4-5", 64_000),
+    ("claude-opus-4", 32_000),
+    ("claude-sonnet-4", 64_000),
+    ("claude-3-5", 8_192),
+    ("claude-3-opus", 4_096),
+    ("claude-3-haiku", 4_096),
+];
+
+/// Models of providers without a models endpoint
+const STATIC_MODELS: &[(&str, &[&str])] = &[
+    ("openai-tts", &["gpt-4o-mini-tts", "tts-1", "tts-1-hd"]),
+    ("mock", &["mock"]),
+];
+
+/// A model a provider can serve
+#[derive(Serialize, Debug, Clone)]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: String,
+    pub context_window: Option<u64>,
+}
+
+/// Most output tokens a request to the model may ask for, if its family is known
+pub fn max_output_tokens(model: &str) -> Option<u64> {
+    KNOWN_MAX_OUTPUT_TOKENS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, limit)| *limit)
+}
+
+/// Context window of a model, in tokens, if its family is in the built-in table
+pub fn context_window(model: &str) -> Option<u64> {
+    KNOWN_CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+}
+
+impl ModelInfo {
+    fn new(id: String, display_name: Option<String>) -> Self {
+        let context_window = context_window(&id);
+        Self {
+            display_name: display_name.unwrap_or_else(|| id.clone()),
+            id,
+            context_window,
+        }
+    }
+}
+
+/// Model lists per provider, reused for [`MODEL_CACHE_TTL`]
+#[derive(Default)]
+pub struct ModelCache {
+    entries: Mutex<HashMap<String, (Instant, Vec<ModelInfo>)>>,
+}
+
+impl ModelCache {
+    pub fn get(&self, provider: &str) -> Option<Vec<ModelInfo>> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(provider)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < MODEL_CACHE_TTL)
+            .map(|(_, models)| models.clone())
+    }
+
+    pub fn insert(&self, provider: &str, models: Vec<ModelInfo>) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(provider.to_string(), (Instant::now(), models));
+        }
+    }
+}
+
+/// Context windows set by the user or learned from providers, on top of the built-in table
+#[derive(Default)]
+pub struct ContextWindows {
+    overrides: Mutex<HashMap<String, u64>>,
+    /// Windows the provider reported per model, `None` when it reported none
+    probed: Mutex<HashMap<String, Option<u64>>>,
+}
+
+impl ContextWindows {
+    /// Context window of a model without asking the provider: a user override first,
+    /// then the built-in table, then what the provider reported earlier
+    pub fn lookup(&self, model: &str) -> Option<u64> {
+        let overridden = self
+            .overrides
+            .lock()
+            .ok()
+            .and_then(|overrides| overrides.get(model).copied());
+        overridden
+            .or_else(|| context_window(model))
+            .or_else(|| self.probed(model).flatten())
+    }
+
+    /// Set the context window of a model, or clear its override with `None`
+    pub fn set_override(&self, model: &str, size: Option<u64>) {
+        if let Ok(mut overrides) = self.overrides.lock() {
+            match size {
+                Some(size) => overrides.insert(model.to_string(), size),
+                None => overrides.remove(model),
+            };
+        }
+    }
+
+    /// What the provider reported for a model, if it was asked
+    pub fn probed(&self, model: &str) -> Option<Option<u64>> {
+        self.probed.lock().ok()?.get(model).copied()
+    }
+
+    pub fn insert_probed(&self, model: &str, size: Option<u64>) {
+        if let Ok(mut probed) = self.probed.lock() {
+            probed.insert(model.to_string(), size);
+        }
+    }
+}
+
+/// Curated models for a provider without a models endpoint
+pub fn static_models(provider: &str) -> Option<Vec<ModelInfo>> {
+    STATIC_MODELS
+        .iter()
+        .find(|(name, _)| *name == provider)
+        .map(|(_, ids)| {
+            ids.iter()
+                .map(|id| ModelInfo::new(id.to_string(), None))
+                .collect()
+        })
+}
+
+/// URL and authentication headers of the provider's models endpoint
+fn models_endpoint(provider: &str, api_key: &str) -> ProxyResult<(&'static str, HeaderMap)> {
+    let mut headers = HeaderMap::new();
+    let url = match provider {
+        "openai" => {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", api_key)).map_err(|e| {
+                    ProxyError::ApiKey(format!("Invalid OpenAI API key format: {}", e))
+                })?,
+            );
+            "https://api.openai.com/v1/models"
+        }
+        "anthropic" => {
+            headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+            headers.insert(
+                "x-api-key",
+                HeaderValue::from_str(api_key).map_err(|e| {
+                    ProxyError::ApiKey(format!("Invalid Anthropic API key format: {}", e))
+                })?,
+            );
+            "https://api.anthropic.com/v1/models"
+        }
+        _ => {
+            return Err(ProxyError::Config(format!(
+                "Provider {} has no models endpoint",
+                provider
+            )))
+        }
+    };
+    Ok((url, headers))
+}
+
+/// Query the provider's models endpoint
+pub async fn fetch_models(provider: &str, api_key: &str) -> ProxyResult<Vec<ModelInfo>> {
+    let (url, headers) = models_endpoint(provider, api_key)?;
+    let url = match provider {
+        "anthropic" => format!("{}?limit=1000", url),
+        _ => url.to_string(),
+    };
+
+    debug!("Fetching {} models", provider);
+    let response = http_client()?.get(url).headers(headers).send().await?;
+    if !response.status().is_success() {
+        return Err(ProxyError::Status(response.status().as_u16()));
+    }
+    let body: Value = response.json().await?;
+
+    let mut models: Vec<ModelInfo> = body
+        .get("data")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|model| {
+            let id = model.get("id").and_then(Value::as_str)?;
+            let display_name = model
+                .get("display_name")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            Some(ModelInfo::new(id.to_string(), display_name))
+        })
+        .collect();
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    info!("Fetched {} models for {}", models.len(), provider);
+    Ok(models)
+}
+
+/// Fields a model object may report its context window in, depending on the provider
+const CONTEXT_WINDOW_FIELDS: &[&str] = &["context_window", "context_length", "max_input_tokens"];
+
+/// Ask the provider's models endpoint for a model's context window. Returns `None` when
+/// the provider doesn't report one.
+pub async fn fetch_context_window(
+    provider: &str,
+    api_key: &str,
+    model: &str,
+) -> ProxyResult<Option<u64>> {
+    let (url, headers) = models_endpoint(provider, api_key)?;
+
+    debug!("Fetching {} model {}", provider, model);
+    let response = http_client()?
+        .get(format!("{}/{}", url, model))
+        .headers(headers)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(ProxyError::Status(response.status().as_u16()));
+    }
+    let body: Value = response.json().await?;
+
+    let size = CONTEXT_WINDOW_FIELDS
+        .iter()
+        .find_map(|field| body.get(field).and_then(Value::as_u64));
+    info!(
+        "{} reports a context window of {:?} for {}",
+        provider, size, model
+    );
+    Ok(size)
+}
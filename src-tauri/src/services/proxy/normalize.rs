@@ -0,0 +1,105 @@
+use crate::services::proxy::models::max_output_tokens;
+use crate::services::proxy::{ProxyError, ProxyResult};
+use log::info;
+use serde_json::Value;
+
+const MAX_TOKENS: &str = "max_tokens";
+const MAX_COMPLETION_TOKENS: &str = "max_completion_tokens";
+
+/// Providers whose requests carry an output token limit
+const TOKEN_LIMIT_PROVIDERS: &[&str] = &["anthropic", "openai"];
+
+/// OpenAI models that accept only one of the output token limit fields, as
+/// `(model id prefix, accepted field)`. Models not listed accept both and are left alone.
+const TOKEN_LIMIT_FIELDS: &[(&str, &str)] = &[
+    ("o1", MAX_COMPLETION_TOKENS),
+    ("o3", MAX_COMPLETION_TOKENS),
+    ("o4", MAX_COMPLETION_TOKENS),
+    ("gpt-5", MAX_COMPLETION_TOKENS),
+];
+
+/// Rename an OpenAI output token limit the target model rejects to the field it accepts.
+///
+/// Returns a warning describing the translation when one was made, so callers can be
+/// updated.
+pub fn normalize_token_limit(provider: &str, body: &mut Value) -> Option<String> {
+    if provider != "openai" {
+        return None;
+    }
+    let model = body.get("model").and_then(Value::as_str)?.to_string();
+    let (_, accepted) = TOKEN_LIMIT_FIELDS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))?;
+    let rejected = if *accepted == MAX_TOKENS {
+        MAX_COMPLETION_TOKENS
+    } else {
+        MAX_TOKENS
+    };
+
+    let fields = body.as_object_mut()?;
+    let limit = fields.remove(rejected)?;
+    // An explicit value for the accepted field wins
+    if !fields.contains_key(*accepted) {
+        fields.insert(accepted.to_string(), limit);
+    }
+    info!("Translated `{}` to `{}` for {}", rejected, accepted, model);
+    Some(format!(
+        "{} does not accept `{}`; it was sent as `{}`. Update the request to use `{}`.",
+        model, rejected, accepted, accepted
+    ))
+}
+
+/// Fill in the provider's default output token limit when the request sets none, and
+/// check the limit against the model's maximum output where it is known.
+///
+/// Anthropic requires `max_tokens`, so a request without one or a default fails here
+/// rather than with an upstream 400.
+pub fn apply_max_tokens(provider: &str, body: &mut Value, default: Option<u64>) -> ProxyResult<()> {
+    if !TOKEN_LIMIT_PROVIDERS.contains(&provider) {
+        return Ok(());
+    }
+    let model = body
+        .get("model")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let Some(fields) = body.as_object_mut() else {
+        return Ok(());
+    };
+
+    let present = [MAX_TOKENS, MAX_COMPLETION_TOKENS]
+        .into_iter()
+        .find(|field| fields.get(*field).is_some_and(|limit| !limit.is_null()));
+    let (field, limit) = match (present, default) {
+        (Some(field), _) => (field, fields.get(field).and_then(Value::as_u64)),
+        (None, Some(default)) => {
+            let field = if provider == "openai" {
+                TOKEN_LIMIT_FIELDS
+                    .iter()
+                    .find(|(prefix, _)| model.starts_with(prefix))
+                    .map_or(MAX_TOKENS, |(_, accepted)| *accepted)
+            } else {
+                MAX_TOKENS
+            };
+            info!("Using default `{}` of {} for {}", field, default, provider);
+            fields.insert(field.to_string(), Value::from(default));
+            (field, Some(default))
+        }
+        (None, None) if provider == "anthropic" => {
+            return Err(ProxyError::InvalidPayload(
+                "anthropic requests require `max_tokens`; set it in the payload or configure a default with set_default_max_tokens".to_string(),
+            ));
+        }
+        (None, None) => return Ok(()),
+    };
+
+    if let Some((limit, max)) = limit.zip(max_output_tokens(&model)) {
+        if limit > max {
+            return Err(ProxyError::InvalidPayload(format!(
+                "`{}` of {} exceeds the {} output tokens {} allows",
+                field, limit, max, model
+            )));
+        }
+    }
+    Ok(())
+}
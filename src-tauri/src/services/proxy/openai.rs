@@ -1,15 +1,20 @@
-use crate::services::proxy::{emit_chunk, emit_end, emit_error};
-use crate::services::proxy::{ProxyError, ProxyProvider, ProxyResult};
+use crate::services::proxy::sse::{is_event_stream, read_sse, SseEvent};
+use crate::services::proxy::{
+    apply_extra_headers, check_status, emit_end, emit_filtered, emit_incomplete, emit_logprobs,
+    emit_model_substituted, emit_raw, emit_refusal, emit_role, emit_start, emit_structured_error,
+    emit_text, emit_tool_call, emit_tool_delta, emit_usage, emit_warning, http_client,
+    read_json_body, redact_secrets, send_request,
+};
+use crate::services::proxy::{
+    ErrorKind, EventSink, FinishReason, ModelSubstitution, ProxyError, ProxyProvider, ProxyResult,
+    StreamOptions, StreamStart, StreamUsage, ToolCallAccumulator,
+};
 use async_trait::async_trait;
-use futures_util::StreamExt;
-use log::{debug, error, info, warn};
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tauri::Window;
-use tauri_plugin_http::reqwest::{
-    self,
-    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
-};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri_plugin_http::reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 
 pub struct OpenAIProvider {
     api_key: String,
@@ -26,13 +31,47 @@ struct OpenAIChatCompletionChunk {
     id: String,
     #[allow(dead_code)]
     object: String,
-    #[allow(dead_code)]
+    /// Unix time in seconds when the provider created the completion
     created: u64,
-    #[allow(dead_code)]
     model: String,
-    #[allow(dead_code)]
     system_fingerprint: Option<String>,
     choices: Vec<OpenAIChoice>,
+    /// Sent on the final chunk when `stream_options.include_usage` is set
+    #[serde(default)]
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAIUsage {
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+    total_tokens: Option<u64>,
+    #[serde(default)]
+    completion_tokens_details: Option<OpenAICompletionTokensDetails>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAICompletionTokensDetails {
+    accepted_prediction_tokens: Option<u64>,
+    rejected_prediction_tokens: Option<u64>,
+}
+
+impl From<OpenAIUsage> for StreamUsage {
+    fn from(usage: OpenAIUsage) -> Self {
+        let details = usage.completion_tokens_details;
+        Self {
+            provider: "openai".to_string(),
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+            accepted_prediction_tokens: details
+                .as_ref()
+                .and_then(|details| details.accepted_prediction_tokens),
+            rejected_prediction_tokens: details
+                .as_ref()
+                .and_then(|details| details.rejected_prediction_tokens),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -40,22 +79,395 @@ struct OpenAIChoice {
     #[allow(dead_code)]
     index: u32,
     delta: OpenAIDelta,
-    #[allow(dead_code)]
-    logprobs: Option<Value>,
+    logprobs: Option<OpenAILogprobs>,
     finish_reason: Option<String>,
 }
 
+#[derive(Deserialize, Debug)]
+struct OpenAILogprobs {
+    content: Option<Vec<OpenAITokenLogprob>>,
+}
+
+/// Log probability of a single streamed token
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OpenAITokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    pub bytes: Option<Vec<u8>>,
+    #[serde(default)]
+    pub top_logprobs: Vec<OpenAITopLogprob>,
+}
+
+/// One of the most likely alternatives for a streamed token
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OpenAITopLogprob {
+    pub token: String,
+    pub logprob: f64,
+    pub bytes: Option<Vec<u8>>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 struct OpenAIDelta {
     role: Option<String>,
     content: Option<String>,
+    /// Text of a refusal, sent in place of `content` when the model declines
+    refusal: Option<String>,
+    tool_calls: Option<Vec<OpenAIToolCallDelta>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct OpenAIToolCallDelta {
+    index: u32,
+    id: Option<String>,
+    function: Option<OpenAIFunctionDelta>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct OpenAIFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+/// Whether `served` is a different model from `requested`, rather than the dated
+/// snapshot an alias like `gpt-4o` resolves to (`gpt-4o-2024-08-06`)
+fn is_substituted(requested: &str, served: &str) -> bool {
+    if served.is_empty() || served == requested {
+        return false;
+    }
+    let snapshot = served
+        .strip_prefix(requested)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()));
+    !snapshot
+}
+
+/// Clock skew beyond the time the request has been in flight that is reported as an
+/// anomaly. Covers the one-second resolution of `created`.
+const CLOCK_SKEW_TOLERANCE_MS: i64 = 5_000;
+
+/// Milliseconds since the Unix epoch, negative before it
+fn unix_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+/// Parsing state for a single OpenAI chat completion stream
+struct OpenAIStream {
+    started: bool,
+    /// When the request was sent, to tell clock skew from time in flight
+    request_sent: SystemTime,
+    /// Model named in the request
+    requested_model: Option<String>,
+    /// Whether the served model was checked against the requested one
+    model_checked: bool,
+    role_seen: bool,
+    system_fingerprint: Option<String>,
+    wants_logprobs: bool,
+    finish_reason: Option<FinishReason>,
+    /// Raw reason reported when the provider blocked the output
+    filtered_reason: Option<String>,
+    tool_calls: ToolCallAccumulator,
+    /// Refusal text received so far, emitted as one event once the stream ends
+    refusal: String,
+    /// Whether the terminal `[DONE]` marker was seen
+    completed: bool,
+}
+
+impl OpenAIStream {
+    fn new(body: &Value) -> Self {
+        Self {
+            started: false,
+            request_sent: SystemTime::now(),
+            requested_model: body
+                .get("model")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            model_checked: false,
+            role_seen: false,
+            system_fingerprint: None,
+            wants_logprobs: body.get("logprobs").and_then(Value::as_bool) == Some(true),
+            finish_reason: None,
+            filtered_reason: None,
+            tool_calls: ToolCallAccumulator::default(),
+            refusal: String::new(),
+            completed: false,
+        }
+    }
+
+    fn handle_event(&mut self, event: SseEvent, sink: &dyn EventSink) -> ProxyResult<()> {
+        let json_str = event.data.trim();
+        if json_str.is_empty() {
+            return Ok(());
+        }
+        if json_str == "[DONE]" {
+            debug!("OpenAI [DONE] signal received");
+            self.completed = true;
+            return Ok(());
+        }
+
+        match serde_json::from_str::<OpenAIChatCompletionChunk>(json_str) {
+            Ok(chunk_event) => {
+                debug!("Processing chunk event ID: {}", chunk_event.id);
+                self.track_fingerprint(chunk_event.system_fingerprint, chunk_event.created, sink)?;
+                self.check_model(&chunk_event.model, sink)?;
+                for choice in chunk_event.choices {
+                    self.handle_choice(choice, sink)?;
+                }
+                if let Some(usage) = chunk_event.usage {
+                    emit_usage(sink, usage.into())?;
+                }
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Failed to parse chunk event: {}", e);
+                emit_structured_error(
+                    sink,
+                    ErrorKind::Parse,
+                    format!("Failed to parse OpenAI JSON: {}", e),
+                    false,
+                )
+            }
+        }
+    }
+
+    /// Report the fingerprint and clock skew on the first chunk, and warn if the
+    /// fingerprint changes mid-stream.
+    ///
+    /// A changed fingerprint means the backend configuration changed, so a seeded
+    /// request may no longer be reproducible.
+    fn track_fingerprint(
+        &mut self,
+        fingerprint: Option<String>,
+        created: u64,
+        sink: &dyn EventSink,
+    ) -> ProxyResult<()> {
+        if !self.started {
+            self.started = true;
+            self.system_fingerprint = fingerprint.clone();
+            let clock_skew_ms = self.check_clock_skew(created, sink)?;
+            return emit_start(
+                sink,
+                StreamStart {
+                    clock_skew_ms,
+                    ..StreamStart::new(fingerprint)
+                },
+            );
+        }
+
+        if let Some(fingerprint) = fingerprint {
+            match &self.system_fingerprint {
+                Some(previous) if *previous != fingerprint => {
+                    emit_warning(
+                        sink,
+                        format!(
+                            "system_fingerprint changed mid-stream from {} to {}; output may not be reproducible",
+                            previous, fingerprint
+                        ),
+                    )?;
+                    self.system_fingerprint = Some(fingerprint);
+                }
+                Some(_) => {}
+                None => self.system_fingerprint = Some(fingerprint),
+            }
+        }
+        Ok(())
+    }
+
+    /// Compare the chunk's `created` timestamp with its local receipt time, warning when
+    /// the gap can't be explained by the time the request has been in flight. A
+    /// completion created before the request was sent, or after its first chunk
+    /// arrived, points to a clock problem or a slow proxy in between.
+    fn check_clock_skew(&self, created: u64, sink: &dyn EventSink) -> ProxyResult<Option<i64>> {
+        if created == 0 {
+            return Ok(None);
+        }
+        let received = SystemTime::now();
+        let skew_ms = unix_millis(received) - created as i64 * 1000;
+        let in_flight_ms = received
+            .duration_since(self.request_sent)
+            .map_or(0, |elapsed| elapsed.as_millis() as i64);
+        if skew_ms < -CLOCK_SKEW_TOLERANCE_MS || skew_ms > in_flight_ms + CLOCK_SKEW_TOLERANCE_MS {
+            emit_warning(
+                sink,
+                format!(
+                    "OpenAI chunk received {}ms after its created timestamp, with the request in flight for {}ms; check for clock skew or a slow proxy",
+                    skew_ms, in_flight_ms
+                ),
+            )?;
+        }
+        Ok(Some(skew_ms))
+    }
+
+    /// Report once if the served model isn't the one requested
+    fn check_model(&mut self, served: &str, sink: &dyn EventSink) -> ProxyResult<()> {
+        if self.model_checked {
+            return Ok(());
+        }
+        self.model_checked = true;
+        match &self.requested_model {
+            Some(requested) if is_substituted(requested, served) => emit_model_substituted(
+                sink,
+                ModelSubstitution {
+                    requested: requested.clone(),
+                    served: served.to_string(),
+                },
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    fn handle_choice(&mut self, choice: OpenAIChoice, sink: &dyn EventSink) -> ProxyResult<()> {
+        if let Some(role) = choice.delta.role {
+            if !self.role_seen {
+                self.role_seen = true;
+                emit_role(sink, role)?;
+            }
+        }
+
+        if let Some(content) = choice.delta.content {
+            if !content.is_empty() {
+                emit_text(sink, content)?;
+            }
+        }
+
+        if let Some(refusal) = choice.delta.refusal {
+            self.refusal.push_str(&refusal);
+        }
+
+        if let Some(deltas) = choice.delta.tool_calls {
+            for tool_delta in deltas {
+                let (name, arguments) = match tool_delta.function {
+                    Some(function) => (function.name, function.arguments.unwrap_or_default()),
+                    None => (None, String::new()),
+                };
+                let delta = self
+                    .tool_calls
+                    .push(tool_delta.index, tool_delta.id, name, &arguments);
+                emit_tool_delta(sink, delta)?;
+            }
+        }
+
+        if self.wants_logprobs {
+            if let Some(tokens) = choice.logprobs.and_then(|logprobs| logprobs.content) {
+                if !tokens.is_empty() {
+                    emit_logprobs(sink, tokens)?;
+                }
+            }
+        }
+
+        if let Some(reason) = choice.finish_reason {
+            debug!("Choice finished with reason: {}", reason);
+            self.finish_reason = Some(FinishReason::from_openai(&reason));
+            if self.finish_reason == Some(FinishReason::ContentFilter) {
+                self.filtered_reason = Some(reason);
+            }
+            // A finish reason means the calls so far are complete, even before [DONE]
+            for call in self.tool_calls.finish_all() {
+                emit_tool_call(sink, call)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a non-streaming `chat.completion` body as a complete response
+    fn handle_complete(&mut self, body: Value, sink: &dyn EventSink) -> ProxyResult<()> {
+        let fingerprint = body
+            .get("system_fingerprint")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let created = body.get("created").and_then(Value::as_u64).unwrap_or(0);
+        self.track_fingerprint(fingerprint, created, sink)?;
+        if let Some(served) = body.get("model").and_then(Value::as_str) {
+            self.check_model(served, sink)?;
+        }
+
+        let choices = body.get("choices").and_then(Value::as_array);
+        for choice in choices.into_iter().flatten() {
+            let message = choice.get("message").unwrap_or(&Value::Null);
+            if let Some(role) = message.get("role").and_then(Value::as_str) {
+                if !self.role_seen {
+                    self.role_seen = true;
+                    emit_role(sink, role.to_string())?;
+                }
+            }
+            if let Some(content) = message.get("content").and_then(Value::as_str) {
+                if !content.is_empty() {
+                    emit_text(sink, content)?;
+                }
+            }
+            if let Some(refusal) = message.get("refusal").and_then(Value::as_str) {
+                self.refusal.push_str(refusal);
+            }
+            let tool_calls = message.get("tool_calls").and_then(Value::as_array);
+            for (position, call) in tool_calls.into_iter().flatten().enumerate() {
+                let index = call
+                    .get("index")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(position as u64) as u32;
+                let id = call.get("id").and_then(Value::as_str).map(str::to_string);
+                let name = call
+                    .pointer("/function/name")
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+                let arguments = call
+                    .pointer("/function/arguments")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                self.tool_calls.push(index, id, name, arguments);
+            }
+            if let Some(reason) = choice.get("finish_reason").and_then(Value::as_str) {
+                self.finish_reason = Some(FinishReason::from_openai(reason));
+                if self.finish_reason == Some(FinishReason::ContentFilter) {
+                    self.filtered_reason = Some(reason.to_string());
+                }
+            }
+        }
+
+        if let Some(usage) = body.get("usage") {
+            match serde_json::from_value::<OpenAIUsage>(usage.clone()) {
+                Ok(usage) => emit_usage(sink, usage.into())?,
+                Err(e) => warn!("Failed to parse OpenAI usage: {}", e),
+            }
+        }
+        self.completed = true;
+        Ok(())
+    }
+
+    /// Emit pending tool calls, flagged incomplete if the stream was cut off
+    fn flush_tool_calls(&mut self, sink: &dyn EventSink) -> ProxyResult<()> {
+        let calls = if self.completed {
+            self.tool_calls.finish_all()
+        } else {
+            self.tool_calls.finish_all_incomplete()
+        };
+        for call in calls {
+            emit_tool_call(sink, call)?;
+        }
+        Ok(())
+    }
+
+    /// Emit the accumulated refusal, if the model declined
+    fn flush_refusal(&mut self, sink: &dyn EventSink) -> ProxyResult<()> {
+        if self.refusal.is_empty() {
+            return Ok(());
+        }
+        emit_refusal(sink, std::mem::take(&mut self.refusal))
+    }
 }
 
 #[async_trait]
 impl ProxyProvider for OpenAIProvider {
-    async fn stream(&self, window: Window, body: Value) -> ProxyResult<()> {
+    async fn stream(
+        &self,
+        sink: &dyn EventSink,
+        body: Value,
+        options: StreamOptions,
+    ) -> ProxyResult<()> {
         info!("Starting OpenAI stream request");
-        let client = reqwest::Client::new();
+        let mut state = OpenAIStream::new(&body);
+        let client = http_client()?;
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
         headers.insert(
@@ -63,112 +475,488 @@ impl ProxyProvider for OpenAIProvider {
             HeaderValue::from_str(&format!("Bearer {}", self.api_key))
                 .map_err(|e| ProxyError::ApiKey(format!("Invalid OpenAI API key format: {}", e)))?,
         );
+        apply_extra_headers(&mut headers, &options);
 
-        let response = client
+        let request = client
             .post("https://api.openai.com/v1/chat/completions")
             .headers(headers)
-            .json(&body)
-            .send()
-            .await?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_body = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Failed to read error body".to_string());
-            let error_msg = format!(
-                "OpenAI API request failed with status {}: {}",
-                status, error_body
-            );
-            emit_error(&window, &error_msg)?;
-            return Err(ProxyError::Status(status.as_u16()));
-        }
-        info!("OpenAI API request successful (status: {})", status);
-
-        let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
-
-        debug!("Starting to process OpenAI stream");
-        while let Some(item) = stream.next().await {
-            match item {
-                Ok(chunk) => {
-                    debug!("Received raw bytes chunk: {} bytes", chunk.len());
-                    match String::from_utf8(chunk.to_vec()) {
-                        Ok(chunk_string) => {
-                            buffer.push_str(&chunk_string);
-
-                            while let Some(pos) = buffer.find("\n\n") {
-                                let event_data = buffer[..pos].trim().to_string();
-                                buffer = buffer[pos + 2..].to_string(); // Skip "\n\n"
-
-                                for line in event_data.lines() {
-                                    if let Some(json_str) = line.strip_prefix("data: ") {
-                                        if json_str.trim() == "[DONE]" {
-                                            debug!("OpenAI [DONE] signal received");
-                                            continue;
-                                        }
-
-                                        match serde_json::from_str::<OpenAIChatCompletionChunk>(
-                                            json_str,
-                                        ) {
-                                            Ok(chunk_event) => {
-                                                debug!(
-                                                    "Processing chunk event ID: {}",
-                                                    chunk_event.id
-                                                );
-
-                                                for choice in chunk_event.choices {
-                                                    if let Some(content) = choice.delta.content {
-                                                        if !content.is_empty() {
-                                                            let text_json =
-                                                                serde_json::to_string(&content)
-                                                                    .map_err(ProxyError::Parse)?;
-                                                            emit_chunk(
-                                                                &window,
-                                                                format!("0:{}\n", text_json),
-                                                            )?;
-                                                        }
-                                                    }
-
-                                                    if let Some(reason) = choice.finish_reason {
-                                                        debug!(
-                                                            "Choice finished with reason: {}",
-                                                            reason
-                                                        );
-                                                    }
-                                                }
-                                            }
-                                            Err(e) => {
-                                                warn!("Failed to parse chunk event: {}", e);
-                                                emit_error(
-                                                    &window,
-                                                    format!("Failed to parse OpenAI JSON: {}", e),
-                                                )?;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let error_msg = format!("Failed to decode chunk as UTF-8: {}", e);
-                            error!("{}", error_msg);
-                            emit_error(&window, &error_msg)?;
-                        }
-                    }
-                }
-                Err(e) => {
-                    let error_msg = format!("Error reading stream chunk: {}", e);
-                    error!("{}", error_msg);
-                    emit_error(&window, &error_msg)?;
-                    return Err(ProxyError::Http(e));
-                }
-            }
-        }
+            .json(&body);
+        // Kept to resume the stream if the connection drops
+        let resume_request = request.try_clone();
+        let response = send_request(request, &options, "OpenAI").await?;
+        let response = check_status(response, sink, "openai", "OpenAI").await?;
+
+        let raw = if is_event_stream(&response) {
+            debug!("Starting to process OpenAI stream");
+            let read = read_sse(response, resume_request, sink, &options, |event| {
+                state.handle_event(event, sink)
+            })
+            .await;
 
+            // Partial tool calls and refusals are surfaced even when the stream failed
+            state.flush_tool_calls(sink)?;
+            state.flush_refusal(sink)?;
+            read?
+        } else {
+            let body = read_json_body(response, sink, "OpenAI").await?;
+            let raw = if options.capture_raw {
+                body.to_string()
+            } else {
+                String::new()
+            };
+            state.handle_complete(body, sink)?;
+            state.flush_tool_calls(sink)?;
+            state.flush_refusal(sink)?;
+            raw
+        };
         info!("OpenAI stream completed");
-        emit_end(&window)?;
+        if options.capture_raw {
+            emit_raw(sink, redact_secrets(&raw, &self.api_key))?;
+        }
+        if let Some(reason) = state.filtered_reason.take() {
+            emit_filtered(sink, "openai", reason)?;
+        }
+        if !state.completed {
+            emit_incomplete(sink, "openai")?;
+        }
+        emit_end(sink, state.finish_reason)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::proxy::test_support::{data_event, RecordingSink};
+    use crate::services::proxy::{StreamEvent, ToolCall};
+    use serde_json::json;
+
+    /// A streamed chunk with `fields` set over a minimal valid chunk
+    fn raw_chunk(fields: Value) -> SseEvent {
+        let mut chunk = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "gpt-4o",
+            "choices": [],
+        });
+        for (key, value) in fields.as_object().unwrap() {
+            chunk[key.as_str()] = value.clone();
+        }
+        data_event(&chunk.to_string())
+    }
+
+    /// A streamed chunk carrying a single choice
+    fn chunk(choice: Value) -> SseEvent {
+        raw_chunk(json!({"choices": [choice]}))
+    }
+
+    fn logprobs(sink: &RecordingSink) -> Vec<Vec<OpenAITokenLogprob>> {
+        sink.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::Logprobs { tokens } => Some(tokens),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn emits_logprobs_only_when_requested() {
+        let choice = json!({
+            "index": 0,
+            "delta": {"content": "Hi"},
+            "logprobs": {"content": [{
+                "token": "Hi",
+                "logprob": -0.25,
+                "bytes": [72, 105],
+                "top_logprobs": [{"token": "Hey", "logprob": -1.5, "bytes": null}],
+            }]},
+            "finish_reason": null,
+        });
+
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o", "logprobs": true}));
+        state.handle_event(chunk(choice.clone()), &sink).unwrap();
+        let emitted = logprobs(&sink);
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(emitted[0][0].token, "Hi");
+        assert_eq!(emitted[0][0].bytes, Some(vec![72, 105]));
+        assert_eq!(emitted[0][0].top_logprobs[0].token, "Hey");
+        assert_eq!(emitted[0][0].top_logprobs[0].bytes, None);
+
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        state.handle_event(chunk(choice), &sink).unwrap();
+        assert!(logprobs(&sink).is_empty());
+    }
+
+    #[test]
+    fn skips_chunks_without_logprob_content() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o", "logprobs": true}));
+        let choice = json!({"index": 0, "delta": {"content": ""}, "logprobs": {"content": null}});
+        state.handle_event(chunk(choice), &sink).unwrap();
+        assert!(logprobs(&sink).is_empty());
+    }
+
+    #[test]
+    fn reports_fingerprint_on_start_and_warns_when_it_changes() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        for fingerprint in ["fp_a", "fp_a", "fp_b"] {
+            let event = raw_chunk(json!({"system_fingerprint": fingerprint}));
+            state.handle_event(event, &sink).unwrap();
+        }
+
+        let starts: Vec<StreamStart> = sink
+            .events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::Start(start) => Some(start),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(starts.len(), 1);
+        assert_eq!(starts[0].system_fingerprint.as_deref(), Some("fp_a"));
+        let warnings = sink.warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("from fp_a to fp_b"));
+    }
+
+    #[test]
+    fn records_the_reason_for_filtered_output() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        let choice = json!({"index": 0, "delta": {}, "finish_reason": "content_filter"});
+        state.handle_event(chunk(choice), &sink).unwrap();
+        assert_eq!(state.finish_reason, Some(FinishReason::ContentFilter));
+        assert_eq!(state.filtered_reason.as_deref(), Some("content_filter"));
+
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        let choice = json!({"index": 0, "delta": {}, "finish_reason": "stop"});
+        state.handle_event(chunk(choice), &sink).unwrap();
+        assert_eq!(state.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(state.filtered_reason, None);
+    }
+
+    #[test]
+    fn completes_only_on_the_done_marker() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        let choice = json!({"index": 0, "delta": {"content": "Hi"}, "finish_reason": "stop"});
+        state.handle_event(chunk(choice), &sink).unwrap();
+        assert!(!state.completed);
+
+        state.handle_event(data_event("[DONE]"), &sink).unwrap();
+        assert!(state.completed);
+    }
+
+    #[test]
+    fn emits_usage_with_prediction_tokens() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        let usage = json!({
+            "prompt_tokens": 12,
+            "completion_tokens": 30,
+            "total_tokens": 42,
+            "completion_tokens_details": {
+                "accepted_prediction_tokens": 20,
+                "rejected_prediction_tokens": 4,
+            },
+        });
+        state
+            .handle_event(raw_chunk(json!({"usage": usage})), &sink)
+            .unwrap();
+
+        let usage: Vec<StreamUsage> = sink
+            .events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::Usage(usage) => Some(usage),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].provider, "openai");
+        assert_eq!(usage[0].input_tokens, Some(12));
+        assert_eq!(usage[0].output_tokens, Some(30));
+        assert_eq!(usage[0].total_tokens, Some(42));
+        assert_eq!(usage[0].accepted_prediction_tokens, Some(20));
+        assert_eq!(usage[0].rejected_prediction_tokens, Some(4));
+    }
+
+    #[test]
+    fn emits_the_role_once() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        for delta in [
+            json!({"role": "assistant", "content": ""}),
+            json!({"role": "assistant", "content": "Hi"}),
+        ] {
+            state
+                .handle_event(chunk(json!({"index": 0, "delta": delta})), &sink)
+                .unwrap();
+        }
+
+        let roles: Vec<String> = sink
+            .events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::Role { role } => Some(role),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(roles, ["assistant"]);
+    }
+
+    fn tool_calls(sink: &RecordingSink) -> Vec<ToolCall> {
+        sink.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::ToolCall(call) => Some(call),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flags_tool_calls_cut_off_before_done() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        let delta = json!({"tool_calls": [{
+            "index": 0,
+            "id": "call_1",
+            "function": {"name": "search", "arguments": "{\"q\": "},
+        }]});
+        state
+            .handle_event(chunk(json!({"index": 0, "delta": delta})), &sink)
+            .unwrap();
+        state.flush_tool_calls(&sink).unwrap();
+
+        let calls = tool_calls(&sink);
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].incomplete);
+        assert_eq!(calls[0].arguments, json!("{\"q\": "));
+    }
+
+    #[test]
+    fn assembles_interleaved_parallel_tool_calls() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        let fragments = [
+            json!([{"index": 0, "id": "call_a", "function": {"name": "get_weather", "arguments": ""}}]),
+            json!([{"index": 1, "id": "call_b", "function": {"name": "get_time", "arguments": ""}}]),
+            json!([
+                {"index": 0, "function": {"arguments": "{\"city\": "}},
+                {"index": 1, "function": {"arguments": "{\"zone\": "}},
+            ]),
+            json!([{"index": 1, "function": {"arguments": "\"UTC\"}"}}]),
+            json!([{"index": 0, "function": {"arguments": "\"Paris\"}"}}]),
+        ];
+        for tool_calls in fragments {
+            let delta = json!({"tool_calls": tool_calls});
+            state
+                .handle_event(chunk(json!({"index": 0, "delta": delta})), &sink)
+                .unwrap();
+        }
+        let finished = json!({"index": 0, "delta": {}, "finish_reason": "tool_calls"});
+        state.handle_event(chunk(finished), &sink).unwrap();
+        state.handle_event(data_event("[DONE]"), &sink).unwrap();
+        state.flush_tool_calls(&sink).unwrap();
+
+        let deltas: Vec<u32> = sink
+            .events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::ToolDelta(delta) => Some(delta.index),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(deltas, [0, 1, 0, 1, 1, 0]);
+
+        let calls = tool_calls(&sink);
+        assert_eq!(calls.len(), 2);
+        assert_eq!(
+            (calls[0].index, calls[0].id.as_str(), calls[0].name.as_str()),
+            (0, "call_a", "get_weather")
+        );
+        assert_eq!(calls[0].arguments, json!({"city": "Paris"}));
+        assert_eq!(
+            (calls[1].index, calls[1].id.as_str(), calls[1].name.as_str()),
+            (1, "call_b", "get_time")
+        );
+        assert_eq!(calls[1].arguments, json!({"zone": "UTC"}));
+        assert!(calls.iter().all(|call| !call.incomplete));
+    }
+
+    #[test]
+    fn handles_a_complete_json_reply() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        let body = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "Checking.",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "search", "arguments": "{\"q\": \"rust\"}"},
+                    }],
+                },
+                "finish_reason": "tool_calls",
+            }],
+            "usage": {"prompt_tokens": 3, "completion_tokens": 4, "total_tokens": 7},
+        });
+        state.handle_complete(body, &sink).unwrap();
+        state.flush_tool_calls(&sink).unwrap();
+
+        assert!(state.completed);
+        assert_eq!(state.finish_reason, Some(FinishReason::ToolCalls));
+        assert_eq!(
+            sink.names(),
+            [
+                "ai-stream-start",
+                "ai-stream-role",
+                "ai-stream-chunk",
+                "ai-stream-usage",
+                "ai-stream-tool-call",
+            ]
+        );
+        let calls = tool_calls(&sink);
+        assert_eq!(calls[0].name, "search");
+        assert_eq!(calls[0].arguments, json!({"q": "rust"}));
+        assert!(!calls[0].incomplete);
+    }
+
+    #[test]
+    fn tells_a_substituted_model_from_a_dated_snapshot() {
+        assert!(!is_substituted("gpt-4o", "gpt-4o"));
+        assert!(!is_substituted("gpt-4o", "gpt-4o-2024-08-06"));
+        assert!(!is_substituted("gpt-4o", ""));
+        assert!(is_substituted("gpt-4o", "gpt-4o-mini"));
+        assert!(is_substituted("gpt-4o", "gpt-4o-mini-2024-07-18"));
+        assert!(is_substituted("gpt-4o-2024-08-06", "gpt-4o"));
+    }
+
+    #[test]
+    fn reports_a_substituted_model_once() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        for _ in 0..2 {
+            state
+                .handle_event(raw_chunk(json!({"model": "gpt-4o-mini"})), &sink)
+                .unwrap();
+        }
+
+        let substitutions: Vec<ModelSubstitution> = sink
+            .events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::ModelSubstituted(substitution) => Some(substitution),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(substitutions.len(), 1);
+        assert_eq!(substitutions[0].requested, "gpt-4o");
+        assert_eq!(substitutions[0].served, "gpt-4o-mini");
+    }
+
+    fn now_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn warns_of_clock_skew_only_outside_the_tolerance() {
+        let state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        let now = now_secs();
+        let skew_warnings = |created: u64| {
+            let sink = RecordingSink::default();
+            state.check_clock_skew(created, &sink).unwrap();
+            sink.warnings().len()
+        };
+
+        assert_eq!(skew_warnings(now), 0);
+        assert_eq!(skew_warnings(now - 3), 0);
+        assert_eq!(skew_warnings(now + 3), 0);
+        assert_eq!(skew_warnings(now - 60), 1);
+        assert_eq!(skew_warnings(now + 60), 1);
+    }
+
+    #[test]
+    fn reports_the_clock_skew_at_start() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        let created = now_secs() - 60;
+        state
+            .handle_event(raw_chunk(json!({"created": created})), &sink)
+            .unwrap();
+
+        let events = sink.events();
+        let Some(StreamEvent::Start(start)) = events
+            .iter()
+            .find(|event| matches!(event, StreamEvent::Start(_)))
+        else {
+            panic!("expected a start event");
+        };
+        assert!(start.clock_skew_ms.is_some_and(|skew| skew >= 60_000));
+        assert_eq!(sink.warnings().len(), 1);
+    }
+
+    #[test]
+    fn skips_the_clock_check_without_a_timestamp() {
+        let sink = RecordingSink::default();
+        let state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        assert_eq!(state.check_clock_skew(0, &sink).unwrap(), None);
+        assert!(sink.events().is_empty());
+    }
+
+    fn refusals(sink: &RecordingSink) -> Vec<String> {
+        sink.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::Refusal { text } => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sends_a_streamed_refusal_once_as_a_whole() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        for fragment in ["I'm sorry, ", "I can't help ", "with that."] {
+            let delta = json!({"index": 0, "delta": {"refusal": fragment}});
+            state.handle_event(chunk(delta), &sink).unwrap();
+        }
+        let stopped = json!({"index": 0, "delta": {}, "finish_reason": "stop"});
+        state.handle_event(chunk(stopped), &sink).unwrap();
+        assert!(refusals(&sink).is_empty());
+        assert_eq!(sink.text(), "");
+
+        state.flush_refusal(&sink).unwrap();
+        state.flush_refusal(&sink).unwrap();
+        assert_eq!(refusals(&sink), ["I'm sorry, I can't help with that."]);
+    }
+
+    #[test]
+    fn sends_nothing_without_a_refusal() {
+        let sink = RecordingSink::default();
+        let mut state = OpenAIStream::new(&json!({"model": "gpt-4o"}));
+        let delta = json!({"index": 0, "delta": {"content": "Sure.", "refusal": null}});
+        state.handle_event(chunk(delta), &sink).unwrap();
+        state.flush_refusal(&sink).unwrap();
+
+        assert!(refusals(&sink).is_empty());
+        assert_eq!(sink.text(), "Sure.");
+    }
+}
@@ -0,0 +1,48 @@
+use crate::services::proxy::{EventSink, ProxyResult, StreamEvent};
+use std::sync::Mutex;
+
+/// Text a stream has generated so far, for streams started with `keep_partial`
+#[derive(Default)]
+pub struct PartialText {
+    /// `None` until the stream asks for its text to be kept
+    text: Mutex<Option<String>>,
+}
+
+impl PartialText {
+    fn enable(&self) {
+        if let Ok(mut text) = self.text.lock() {
+            text.get_or_insert_with(String::new);
+        }
+    }
+
+    /// The text delivered so far, or `None` if the stream isn't keeping it
+    pub fn text(&self) -> Option<String> {
+        self.text.lock().ok().and_then(|text| text.clone())
+    }
+}
+
+/// Wraps a sink to collect the text it delivers into a [`PartialText`]
+pub(crate) struct PartialTextSink<'a> {
+    inner: &'a dyn EventSink,
+    partial: &'a PartialText,
+}
+
+impl<'a> PartialTextSink<'a> {
+    pub(crate) fn new(inner: &'a dyn EventSink, partial: &'a PartialText) -> Self {
+        partial.enable();
+        Self { inner, partial }
+    }
+}
+
+impl EventSink for PartialTextSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        if let StreamEvent::Text { text } = &event {
+            if let Ok(mut partial) = self.partial.text.lock() {
+                if let Some(partial) = partial.as_mut() {
+                    partial.push_str(text);
+                }
+            }
+        }
+        self.inner.emit(event)
+    }
+}
@@ -0,0 +1,114 @@
+use crate::services::proxy::{EventSink, ProxyResult, StreamEvent};
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Most events held back while a stream is paused before it is resumed automatically
+pub const MAX_PAUSED_EVENTS: usize = 10_000;
+
+/// Pause flag for a stream, toggled by `pause_stream`/`resume_stream`
+#[derive(Default)]
+pub struct PauseControl {
+    paused: AtomicBool,
+    resumed: Notify,
+}
+
+impl PauseControl {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+        self.resumed.notify_one();
+    }
+
+    /// Wait until the stream is resumed
+    pub async fn resumed(&self) {
+        while self.is_paused() {
+            self.resumed.notified().await;
+        }
+    }
+}
+
+/// Holds events back while the stream is paused and flushes them on resume.
+///
+/// The provider keeps draining the upstream response meanwhile, so the connection
+/// isn't stalled by backpressure.
+pub struct PauseSink<'a> {
+    inner: &'a dyn EventSink,
+    control: Arc<PauseControl>,
+    buffer: Mutex<VecDeque<StreamEvent>>,
+}
+
+impl<'a> PauseSink<'a> {
+    pub fn new(inner: &'a dyn EventSink, control: Arc<PauseControl>) -> Self {
+        Self {
+            inner,
+            control,
+            buffer: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn control(&self) -> &PauseControl {
+        &self.control
+    }
+
+    /// Forward every held-back event
+    pub fn flush(&self) -> ProxyResult<()> {
+        let held: Vec<StreamEvent> = match self.buffer.lock() {
+            Ok(mut buffer) => buffer.drain(..).collect(),
+            Err(_) => return Ok(()),
+        };
+        if !held.is_empty() {
+            info!("Flushing {} events held while paused", held.len());
+        }
+        for event in held {
+            self.inner.emit(event)?;
+        }
+        Ok(())
+    }
+
+    /// Forward held-back events whenever the stream is resumed; never returns
+    pub async fn flush_on_resume(&self) {
+        loop {
+            self.control.resumed.notified().await;
+            if let Err(e) = self.flush() {
+                warn!("{}", e);
+            }
+        }
+    }
+}
+
+impl EventSink for PauseSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        if !self.control.is_paused() {
+            self.flush()?;
+            return self.inner.emit(event);
+        }
+
+        let held = match self.buffer.lock() {
+            Ok(mut buffer) => {
+                buffer.push_back(event);
+                buffer.len()
+            }
+            Err(_) => return Ok(()),
+        };
+        if held >= MAX_PAUSED_EVENTS {
+            self.inner.emit(StreamEvent::Warning {
+                message: format!(
+                    "Paused stream buffered {} events; resuming to bound memory",
+                    held
+                ),
+            })?;
+            self.control.resume();
+        }
+        Ok(())
+    }
+}
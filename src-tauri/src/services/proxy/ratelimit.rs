@@ -0,0 +1,181 @@
+use serde::Serialize;
+use std::time::Duration;
+use tauri_plugin_http::reqwest::header::HeaderMap;
+
+/// Rate-limit state reported by a provider in its response headers
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RateLimitInfo {
+    pub provider: String,
+    pub requests_remaining: Option<u64>,
+    pub tokens_remaining: Option<u64>,
+    /// Reset time as sent by the provider (a duration like `6m0s` for OpenAI, an
+    /// RFC 3339 timestamp for Anthropic)
+    pub requests_reset: Option<String>,
+    pub tokens_reset: Option<String>,
+    /// Seconds to wait before retrying, from `retry-after` or the reset durations
+    pub retry_after_secs: Option<f64>,
+}
+
+impl RateLimitInfo {
+    /// Parse rate-limit headers from either provider, returning `None` if there are none
+    pub fn from_headers(provider: &str, headers: &HeaderMap) -> Option<Self> {
+        let text = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_string())
+        };
+        let number = |name: &str| text(name).and_then(|value| value.parse::<u64>().ok());
+        let either = |openai: &str, anthropic: &str| text(openai).or_else(|| text(anthropic));
+
+        let info = Self {
+            provider: provider.to_string(),
+            requests_remaining: number("x-ratelimit-remaining-requests")
+                .or_else(|| number("anthropic-ratelimit-requests-remaining")),
+            tokens_remaining: number("x-ratelimit-remaining-tokens")
+                .or_else(|| number("anthropic-ratelimit-tokens-remaining")),
+            requests_reset: either(
+                "x-ratelimit-reset-requests",
+                "anthropic-ratelimit-requests-reset",
+            ),
+            tokens_reset: either(
+                "x-ratelimit-reset-tokens",
+                "anthropic-ratelimit-tokens-reset",
+            ),
+            retry_after_secs: None,
+        };
+
+        // `retry-after` is authoritative; otherwise wait for whichever limit resets last
+        let retry_after = text("retry-after")
+            .and_then(|value| value.parse::<f64>().ok())
+            .or_else(|| {
+                [&info.requests_reset, &info.tokens_reset]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|reset| parse_reset_duration(reset))
+                    .map(|reset| reset.as_secs_f64())
+                    .reduce(f64::max)
+            });
+
+        let info = Self {
+            retry_after_secs: retry_after.filter(|secs| secs.is_finite() && *secs >= 0.0),
+            ..info
+        };
+
+        let has_limits = info.requests_remaining.is_some()
+            || info.tokens_remaining.is_some()
+            || info.requests_reset.is_some()
+            || info.tokens_reset.is_some()
+            || info.retry_after_secs.is_some();
+        has_limits.then_some(info)
+    }
+
+    /// Delay before a retry is allowed, if the provider told us
+    pub fn retry_delay(&self) -> Option<Duration> {
+        self.retry_after_secs
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+    }
+}
+
+/// Parse an OpenAI-style reset duration such as `1s`, `6m0s`, `1h2m3.5s` or `20ms`
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return None;
+    }
+
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let scale = match &rest[..unit_len] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total += number * scale;
+    }
+
+    Duration::try_from_secs_f64(total).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri_plugin_http::reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn parses_openai_headers_and_waits_for_the_later_reset() {
+        let info = RateLimitInfo::from_headers(
+            "openai",
+            &headers(&[
+                ("x-ratelimit-remaining-requests", "0"),
+                ("x-ratelimit-remaining-tokens", "1500"),
+                ("x-ratelimit-reset-requests", "6m0s"),
+                ("x-ratelimit-reset-tokens", "1s"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(info.requests_remaining, Some(0));
+        assert_eq!(info.tokens_remaining, Some(1500));
+        assert_eq!(info.requests_reset.as_deref(), Some("6m0s"));
+        assert_eq!(info.retry_after_secs, Some(360.0));
+        assert_eq!(info.retry_delay(), Some(Duration::from_secs(360)));
+    }
+
+    #[test]
+    fn prefers_retry_after_over_reset_times() {
+        let info = RateLimitInfo::from_headers(
+            "anthropic",
+            &headers(&[
+                ("retry-after", "2.5"),
+                ("anthropic-ratelimit-requests-remaining", "3"),
+                ("anthropic-ratelimit-requests-reset", "2025-01-01T00:00:00Z"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(info.requests_remaining, Some(3));
+        assert_eq!(info.requests_reset.as_deref(), Some("2025-01-01T00:00:00Z"));
+        assert_eq!(info.retry_delay(), Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn ignores_responses_without_limits() {
+        assert!(RateLimitInfo::from_headers("openai", &HeaderMap::new()).is_none());
+        let negative = headers(&[("retry-after", "-1")]);
+        assert!(RateLimitInfo::from_headers("openai", &negative).is_none());
+    }
+
+    #[test]
+    fn parses_reset_durations() {
+        assert_eq!(parse_reset_duration("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(
+            parse_reset_duration("1h2m3.5s"),
+            Some(Duration::from_millis(3_723_500))
+        );
+        assert_eq!(
+            parse_reset_duration("20ms"),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(parse_reset_duration(""), None);
+        assert_eq!(parse_reset_duration("2025-01-01T00:00:00Z"), None);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::services::proxy::replay::ReplayBuffer;
+use crate::services::proxy::{EventSink, ProxyError, ProxyResult, StreamEvent};
+use std::sync::{Mutex, MutexGuard};
+
+/// Replacement destination for a stream's events, set by `reattach_stream`
+#[derive(Default)]
+pub struct RedirectTarget {
+    state: Mutex<RedirectState>,
+}
+
+#[derive(Default)]
+struct RedirectState {
+    sink: Option<Box<dyn EventSink>>,
+    /// Events delivered so far, to either destination
+    delivered: u64,
+}
+
+impl RedirectTarget {
+    fn lock(&self) -> ProxyResult<MutexGuard<'_, RedirectState>> {
+        self.state
+            .lock()
+            .map_err(|e| ProxyError::Emit(format!("Redirect lock poisoned: {}", e)))
+    }
+
+    /// Deliver the stream's remaining events to `sink`, after catching it up with the
+    /// buffered events already delivered. Returns the number of events replayed.
+    ///
+    /// Events recorded in `replay` but still on their way here are left out of the
+    /// catch-up, since they reach `sink` once the switch is done.
+    pub fn redirect(&self, sink: Box<dyn EventSink>, replay: &ReplayBuffer) -> ProxyResult<usize> {
+        let mut state = self.lock()?;
+        let events = replay.events_before(state.delivered);
+        let count = events.len();
+        for event in events {
+            sink.emit(event)?;
+        }
+        state.sink = Some(sink);
+        Ok(count)
+    }
+}
+
+/// Delivers to the client sink, or to the redirect target once one is set
+pub(crate) struct RedirectSink<'a> {
+    inner: &'a dyn EventSink,
+    target: &'a RedirectTarget,
+}
+
+impl<'a> RedirectSink<'a> {
+    pub(crate) fn new(inner: &'a dyn EventSink, target: &'a RedirectTarget) -> Self {
+        Self { inner, target }
+    }
+}
+
+impl EventSink for RedirectSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        let mut state = self.target.lock()?;
+        state.delivered += 1;
+        match &state.sink {
+            Some(sink) => sink.emit(event),
+            None => self.inner.emit(event),
+        }
+    }
+}
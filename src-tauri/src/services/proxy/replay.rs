@@ -0,0 +1,64 @@
+use crate::services::proxy::{EventSink, ProxyResult, StreamEvent};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Most recent events kept per active stream for `replay_stream`
+pub const REPLAY_BUFFER_EVENTS: usize = 1_000;
+
+/// Ring buffer of the most recent events of a stream
+#[derive(Default)]
+pub struct ReplayBuffer {
+    events: Mutex<VecDeque<StreamEvent>>,
+    /// Events recorded since the stream started, including those since dropped
+    recorded: AtomicU64,
+}
+
+impl ReplayBuffer {
+    fn record(&self, event: &StreamEvent) {
+        if let Ok(mut events) = self.events.lock() {
+            if events.len() == REPLAY_BUFFER_EVENTS {
+                events.pop_front();
+            }
+            events.push_back(event.clone());
+            self.recorded.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The buffered events, oldest first
+    pub fn events(&self) -> Vec<StreamEvent> {
+        self.events
+            .lock()
+            .map(|events| events.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The buffered events among the first `count` recorded, oldest first
+    pub fn events_before(&self, count: u64) -> Vec<StreamEvent> {
+        let Ok(events) = self.events.lock() else {
+            return Vec::new();
+        };
+        let later = self.recorded.load(Ordering::Relaxed).saturating_sub(count);
+        let keep = events.len().saturating_sub(later as usize);
+        events.iter().take(keep).cloned().collect()
+    }
+}
+
+/// Wraps a sink to keep a copy of each delivered event in a [`ReplayBuffer`]
+pub(crate) struct ReplaySink<'a> {
+    inner: &'a dyn EventSink,
+    buffer: &'a ReplayBuffer,
+}
+
+impl<'a> ReplaySink<'a> {
+    pub(crate) fn new(inner: &'a dyn EventSink, buffer: &'a ReplayBuffer) -> Self {
+        Self { inner, buffer }
+    }
+}
+
+impl EventSink for ReplaySink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        self.buffer.record(&event);
+        self.inner.emit(event)
+    }
+}
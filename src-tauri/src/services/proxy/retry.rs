@@ -0,0 +1,72 @@
+use crate::services::proxy::{ProxyError, ProxyResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+/// Status codes retried unless configured otherwise
+pub const DEFAULT_RETRYABLE_CODES: &[u16] = &[429, 500, 502, 503, 504];
+
+/// Which failed requests are retried, how often, and how long to wait in between
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub codes: BTreeSet<u16>,
+    /// Total attempts per request, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each one after
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            codes: DEFAULT_RETRYABLE_CODES.iter().copied().collect(),
+            max_attempts: 2,
+            base_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(codes: Vec<u16>, max_attempts: u32, base_delay_ms: u64) -> ProxyResult<Self> {
+        if let Some(code) = codes.iter().find(|code| !(100..=599).contains(*code)) {
+            return Err(ProxyError::Config(format!(
+                "{} is not a valid HTTP status code",
+                code
+            )));
+        }
+        if max_attempts == 0 {
+            return Err(ProxyError::Config(
+                "max_attempts must be at least 1".to_string(),
+            ));
+        }
+        Ok(Self {
+            codes: codes.into_iter().collect(),
+            max_attempts,
+            base_delay: Duration::from_millis(base_delay_ms),
+        })
+    }
+
+    /// Retries allowed after the first attempt
+    pub fn retries(&self) -> u32 {
+        self.max_attempts.saturating_sub(1)
+    }
+
+    /// Delay before retry number `retry` (0-based) of a request that failed with
+    /// `error`, or `None` if the error isn't retryable.
+    ///
+    /// A rate limit's own reset hint takes precedence over the backoff.
+    pub fn retry_delay(&self, error: &ProxyError, retry: u32) -> Option<Duration> {
+        match error {
+            ProxyError::RateLimited(after) if self.codes.contains(&429) => {
+                Some(after.unwrap_or_else(|| self.backoff(retry)))
+            }
+            ProxyError::Status(code) if self.codes.contains(code) => Some(self.backoff(retry)),
+            _ => None,
+        }
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry.min(16)))
+    }
+}
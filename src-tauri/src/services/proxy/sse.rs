@@ -0,0 +1,607 @@
+use crate::services::proxy::{
+    emit_structured_error, emit_warning, send_request, ErrorKind, EventSink, ProxyError,
+    ProxyResult, StreamOptions, DEFAULT_MAX_EVENT_BYTES,
+};
+use futures_util::StreamExt;
+use log::{debug, error, warn};
+use serde::de::IgnoredAny;
+use std::string::FromUtf8Error;
+use std::sync::atomic::Ordering;
+use tauri_plugin_http::reqwest::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+    RequestBuilder, Response,
+};
+
+/// Header asking the server to replay the events after the given id
+const LAST_EVENT_ID: &str = "Last-Event-ID";
+
+/// A single server-sent event
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    /// Value of the `event:` field, if present
+    pub event: Option<String>,
+    /// Value of the `id:` field, if present
+    pub id: Option<String>,
+    /// The `data:` lines of the event, joined with newlines
+    pub data: String,
+}
+
+/// Incremental parser splitting a byte stream into server-sent events.
+///
+/// Bytes are buffered until a full event arrives, so multi-byte characters split
+/// across network chunks decode correctly.
+///
+/// A blank line normally ends an event, but some servers send data with literal blank
+/// lines inside a JSON string. An event whose JSON data ends mid-value is therefore held
+/// back, and a following segment that doesn't start with a field line is taken as the
+/// rest of its data.
+#[derive(Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    /// Most recent event id seen, which carries over to later events without one
+    last_event_id: Option<String>,
+    /// Event whose data was cut off at a blank line, waiting for the rest
+    held: Option<SseEvent>,
+}
+
+impl SseParser {
+    /// Feed raw bytes, returning every event they complete
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Result<SseEvent, FromUtf8Error>> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let mut block: Vec<u8> = self.buffer.drain(..pos + 2).collect();
+            block.truncate(pos); // Drop the "\n\n"
+            let text = match String::from_utf8(block) {
+                Ok(text) => text,
+                Err(e) => {
+                    events.push(Err(e));
+                    continue;
+                }
+            };
+
+            let event = match self.held.take() {
+                Some(mut held) if !starts_with_field(&text) => {
+                    held.data = escape_string_newlines(&format!("{}\n\n{}", held.data, text));
+                    held
+                }
+                Some(held) => {
+                    // The rest never came; deliver what arrived
+                    events.push(Ok(self.deliver(held)));
+                    parse_event(&text)
+                }
+                None => parse_event(&text),
+            };
+            if is_truncated_json(&event.data) {
+                self.held = Some(event);
+            } else {
+                events.push(Ok(self.deliver(event)));
+            }
+        }
+        events
+    }
+
+    /// Take a held event at the end of the stream, when no more data can complete it
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let held = self.held.take()?;
+        Some(self.deliver(held))
+    }
+
+    fn deliver(&mut self, event: SseEvent) -> SseEvent {
+        if let Some(id) = &event.id {
+            self.last_event_id = Some(id.clone());
+        }
+        event
+    }
+
+    /// Bytes buffered towards an event whose boundary hasn't arrived yet
+    pub fn pending_len(&self) -> usize {
+        self.buffer.len() + self.held.as_ref().map_or(0, |held| held.data.len())
+    }
+
+    /// Id of the last event that carried one, for `Last-Event-ID` resumption
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Drop a partially received event, which the server resends on resumption
+    pub fn discard_pending(&mut self) {
+        self.buffer.clear();
+        self.held = None;
+    }
+}
+
+/// Whether a block opens with an SSE field or comment line, rather than continuing the
+/// previous event's data
+fn starts_with_field(block: &str) -> bool {
+    let line = block.lines().next().unwrap_or_default();
+    if line.starts_with(':') {
+        return true;
+    }
+    let field = line.split_once(':').map_or(line, |(field, _)| field);
+    matches!(field, "data" | "event" | "id" | "retry")
+}
+
+/// Whether `data` is JSON that ends before its value is complete
+fn is_truncated_json(data: &str) -> bool {
+    let trimmed = data.trim_start();
+    if !trimmed.starts_with('{') && !trimmed.starts_with('[') {
+        return false;
+    }
+    serde_json::from_str::<IgnoredAny>(trimmed).is_err_and(|e| e.is_eof())
+}
+
+/// Escape raw line breaks inside JSON strings, which JSON doesn't allow. Line breaks
+/// between values are left alone.
+fn escape_string_newlines(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut backslash = false;
+    for c in json.chars() {
+        match c {
+            '\n' if in_string => escaped.push_str("\\n"),
+            '\r' if in_string => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+        if backslash {
+            backslash = false;
+        } else if c == '\\' && in_string {
+            backslash = true;
+        } else if c == '"' {
+            in_string = !in_string;
+        }
+    }
+    escaped
+}
+
+/// Parse the fields of a single event block
+fn parse_event(block: &str) -> SseEvent {
+    let mut event = SseEvent::default();
+    let mut data_lines: Vec<&str> = Vec::new();
+
+    for line in block.lines() {
+        if line.is_empty() || line.starts_with(':') {
+            continue;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => data_lines.push(value),
+            "event" => event.event = Some(value.to_string()),
+            // Ids containing NUL are ignored, per the SSE spec
+            "id" if !value.contains('\0') => event.id = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    event.data = data_lines.join("\n");
+    event
+}
+
+/// Whether the response is a server-sent event stream.
+///
+/// A missing `content-type` is treated as a stream, as before this check existed.
+pub(crate) fn is_event_stream(response: &Response) -> bool {
+    match response.headers().get(CONTENT_TYPE) {
+        Some(value) => value
+            .to_str()
+            .map(|content_type| content_type.contains("text/event-stream"))
+            .unwrap_or(false),
+        None => true,
+    }
+}
+
+/// Fail on a body still compressed after the client's decoding.
+///
+/// The client advertises and transparently decodes gzip, brotli and deflate, removing
+/// the `content-encoding` header as it does. One that is still present names an
+/// encoding we can't decode, and parsing the bytes as text would only yield garbage.
+fn check_encoding(response: &Response, sink: &dyn EventSink) -> ProxyResult<()> {
+    let Some(encoding) = response.headers().get(CONTENT_ENCODING) else {
+        return Ok(());
+    };
+    let encoding = encoding.to_str().unwrap_or_default().trim();
+    if encoding.is_empty() || encoding.eq_ignore_ascii_case("identity") {
+        return Ok(());
+    }
+    let error = ProxyError::UnsupportedEncoding(encoding.to_string());
+    error!("{}", error);
+    emit_structured_error(sink, ErrorKind::Parse, error.to_string(), false)?;
+    Err(error)
+}
+
+/// Reconnect after the connection dropped, asking the server to replay the events after
+/// `last_event_id`. Returns `None` if the stream can't be resumed.
+async fn resume_stream(
+    request: &RequestBuilder,
+    last_event_id: &str,
+    sink: &dyn EventSink,
+    options: &StreamOptions,
+) -> Option<Response> {
+    let request = request.try_clone()?.header(LAST_EVENT_ID, last_event_id);
+    let message = format!(
+        "Stream connection dropped, resuming after event {}",
+        last_event_id
+    );
+    if let Err(e) = emit_warning(sink, message) {
+        warn!("{}", e);
+    }
+    match send_request(request, options, "stream resume").await {
+        Ok(response) if response.status().is_success() => {
+            match response.headers().get(CONTENT_ENCODING) {
+                Some(encoding) => {
+                    warn!("Resumed stream has undecodable encoding {:?}", encoding);
+                    None
+                }
+                None => Some(response),
+            }
+        }
+        Ok(response) => {
+            warn!("Stream resume rejected with status {}", response.status());
+            None
+        }
+        Err(e) => {
+            warn!("Failed to resume stream: {}", e);
+            None
+        }
+    }
+}
+
+/// Read a streaming response and pass each server-sent event to `on_event`.
+///
+/// If the connection drops after the server sent event ids, `request` is resent with a
+/// `Last-Event-ID` header, up to `options.max_resumes` times, and reading continues
+/// from the new response. Servers that don't send ids are never resumed.
+///
+/// Returning early, whether `on_event` fails or the event or output size limit is hit,
+/// drops the response and with it the upstream connection. That is how a stream is cut
+/// short: providers stop generating, and stop billing output tokens, once the client
+/// goes away. A request's output token limit is enforced upstream through `max_tokens`;
+/// the proxy has no other way to stop generation than closing the connection.
+///
+/// Returns the raw response text when `options.capture_raw` is set.
+pub(crate) async fn read_sse<F>(
+    response: Response,
+    request: Option<RequestBuilder>,
+    sink: &dyn EventSink,
+    options: &StreamOptions,
+    mut on_event: F,
+) -> ProxyResult<String>
+where
+    F: FnMut(SseEvent) -> ProxyResult<()> + Send,
+{
+    check_encoding(&response, sink)?;
+    let mut stream = response.bytes_stream();
+    let mut parser = SseParser::default();
+    let mut raw = Vec::new();
+    let max_event_bytes = options.max_event_bytes.unwrap_or(DEFAULT_MAX_EVENT_BYTES);
+    let mut received = 0;
+    let mut resumes = 0;
+
+    while let Some(item) = stream.next().await {
+        let chunk = match item {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                let resumable = request.as_ref().zip(parser.last_event_id());
+                if let Some((request, last_event_id)) =
+                    resumable.filter(|_| resumes < options.max_resumes)
+                {
+                    resumes += 1;
+                    warn!("Stream chunk read failed: {}", e);
+                    if let Some(response) =
+                        resume_stream(request, last_event_id, sink, options).await
+                    {
+                        stream = response.bytes_stream();
+                        parser.discard_pending();
+                        continue;
+                    }
+                }
+                let error_msg = format!("Error reading stream chunk: {}", e);
+                error!("{}", error_msg);
+                emit_structured_error(sink, ErrorKind::Network, error_msg, true)?;
+                return Err(ProxyError::Http(e));
+            }
+        };
+        debug!("Received raw bytes chunk: {} bytes", chunk.len());
+        received += chunk.len();
+        if let Some(bytes_streamed) = &options.bytes_streamed {
+            bytes_streamed.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }
+        if options.capture_raw {
+            raw.extend_from_slice(&chunk);
+        }
+
+        for event in parser.push(&chunk) {
+            match event {
+                Ok(event) => on_event(event)?,
+                Err(e) => {
+                    let error_msg = format!("Failed to decode event as UTF-8: {}", e);
+                    error!("{}", error_msg);
+                    emit_structured_error(sink, ErrorKind::Parse, error_msg, false)?;
+                }
+            }
+        }
+
+        if let Some(max_output_bytes) = options.max_output_bytes.filter(|max| received > *max) {
+            let error_msg = format!(
+                "Response exceeded the {} byte output limit ({} bytes received), aborting stream",
+                max_output_bytes, received
+            );
+            error!("{}", error_msg);
+            emit_structured_error(sink, ErrorKind::Upstream, error_msg, false)?;
+            return Err(ProxyError::OutputTooLarge(received, max_output_bytes));
+        }
+
+        let pending = parser.pending_len();
+        if pending > max_event_bytes {
+            let error_msg = format!(
+                "Server-sent event exceeded {} bytes without a boundary ({} bytes buffered), aborting stream",
+                max_event_bytes, pending
+            );
+            error!("{}", error_msg);
+            emit_structured_error(sink, ErrorKind::Upstream, error_msg, false)?;
+            return Err(ProxyError::EventTooLarge(pending, max_event_bytes));
+        }
+    }
+
+    if let Some(event) = parser.finish() {
+        on_event(event)?;
+    }
+    Ok(String::from_utf8_lossy(&raw).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::proxy::test_support::{MockResponse, MockServer, RecordingSink};
+    use crate::services::proxy::StreamEvent;
+    use std::time::Duration;
+    use tauri_plugin_http::reqwest;
+    use tokio_util::sync::CancellationToken;
+
+    #[test]
+    fn rejoins_json_split_by_a_blank_line_in_a_string() {
+        let mut parser = SseParser::default();
+        let events = parser.push(b"data: {\"text\": \"one\n\ntwo\"}\n\ndata: {}\n\n");
+        let events: Vec<SseEvent> = events.into_iter().map(Result::unwrap).collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data, r#"{"text": "one\n\ntwo"}"#);
+        let value: serde_json::Value = serde_json::from_str(&events[0].data).unwrap();
+        assert_eq!(value["text"], "one\n\ntwo");
+        assert_eq!(events[1].data, "{}");
+    }
+
+    #[test]
+    fn delivers_a_cut_off_event_when_the_next_one_starts() {
+        let mut parser = SseParser::default();
+        let events = parser.push(b"data: {\"text\": \"one\n\ndata: {}\n\n");
+        let data: Vec<String> = events
+            .into_iter()
+            .map(|event| event.unwrap().data)
+            .collect();
+        assert_eq!(data, [r#"{"text": "one"#, "{}"]);
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn delivers_a_held_event_at_the_end_of_the_stream() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b"data: [1,\n\n").is_empty());
+        assert_eq!(
+            parser.finish().map(|event| event.data).as_deref(),
+            Some("[1,")
+        );
+    }
+
+    #[test]
+    fn counts_bytes_waiting_for_an_event_boundary() {
+        let mut parser = SseParser::default();
+        assert_eq!(parser.push(b"data: one\n\ndata: tw").len(), 1);
+        assert_eq!(parser.pending_len(), "data: tw".len());
+
+        // Held back until the rest of its JSON arrives
+        assert_eq!(parser.push(b"o\n\ndata: {\"text\": \"line\n\n").len(), 1);
+        assert_eq!(parser.pending_len(), r#"{"text": "line"#.len());
+    }
+
+    #[tokio::test]
+    async fn aborts_an_event_over_the_size_limit() {
+        let body = format!("data: {{}}\n\ndata: \"{}", "x".repeat(200));
+        let server = MockServer::start(vec![MockResponse::new("text/event-stream", body)]).await;
+        let response = reqwest::get(&server.url).await.unwrap();
+
+        let sink = RecordingSink::default();
+        let options = StreamOptions {
+            max_event_bytes: Some(64),
+            ..Default::default()
+        };
+        let mut events = Vec::new();
+        let result = read_sse(response, None, &sink, &options, |event| {
+            events.push(event);
+            Ok(())
+        })
+        .await;
+
+        assert!(matches!(result, Err(ProxyError::EventTooLarge(pending, 64)) if pending > 64));
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            &sink.events()[..],
+            [StreamEvent::Error(error)] if error.kind == ErrorKind::Upstream
+        ));
+    }
+
+    #[tokio::test]
+    async fn tells_event_streams_from_other_responses() {
+        let server = MockServer::start(vec![
+            MockResponse::new("text/event-stream; charset=utf-8", "data: {}\n\n"),
+            MockResponse::new("application/json", "{}"),
+        ])
+        .await;
+        let stream = reqwest::get(&server.url).await.unwrap();
+        assert!(is_event_stream(&stream));
+        let json = reqwest::get(&server.url).await.unwrap();
+        assert!(!is_event_stream(&json));
+    }
+
+    #[tokio::test]
+    async fn cancelling_closes_the_upstream_connection() {
+        let mut server =
+            MockServer::start(vec![
+                MockResponse::new("text/event-stream", "data: {}\n\n").hold_open()
+            ])
+            .await;
+        let response = reqwest::get(&server.url).await.unwrap();
+
+        let sink = RecordingSink::default();
+        let options = StreamOptions::default();
+        let cancel = CancellationToken::new();
+        let read = read_sse(response, None, &sink, &options, |_| {
+            cancel.cancel();
+            Ok(())
+        });
+        assert!(cancel.run_until_cancelled(read).await.is_none());
+        assert!(server.disconnected_within(Duration::from_secs(5)).await);
+    }
+
+    #[tokio::test]
+    async fn resumes_a_dropped_stream_after_the_last_event_id() {
+        let server = MockServer::start(vec![
+            // Promises more than it sends, so the body read fails when it closes
+            MockResponse::new(
+                "text/event-stream",
+                "id: 1\ndata: {\"n\": 1}\n\ndata: {\"n\"",
+            )
+            .header("content-length", "1000"),
+            MockResponse::new("text/event-stream", "id: 2\ndata: {\"n\": 2}\n\n"),
+        ])
+        .await;
+        let request = reqwest::Client::new().get(&server.url);
+        let response = request.try_clone().unwrap().send().await.unwrap();
+
+        let sink = RecordingSink::default();
+        let options = StreamOptions {
+            max_resumes: 1,
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        read_sse(response, Some(request), &sink, &options, |event| {
+            data.push(event.data);
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(data, [r#"{"n": 1}"#, r#"{"n": 2}"#]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].to_lowercase().contains("last-event-id"));
+        assert!(requests[1].to_lowercase().contains("last-event-id: 1\r\n"));
+        assert_eq!(sink.warnings().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn reports_a_broken_connection_as_a_retryable_network_error() {
+        let server = MockServer::start(vec![MockResponse::new(
+            "text/event-stream",
+            "data: {\"n\": 1}\n\ndata: {\"n\"",
+        )
+        .header("content-length", "1000")])
+        .await;
+        let response = reqwest::get(&server.url).await.unwrap();
+
+        let sink = RecordingSink::default();
+        let mut data = Vec::new();
+        let result = read_sse(response, None, &sink, &StreamOptions::default(), |event| {
+            data.push(event.data);
+            Ok(())
+        })
+        .await;
+
+        assert!(matches!(result, Err(ProxyError::Http(_))));
+        assert_eq!(data, [r#"{"n": 1}"#]);
+        assert!(matches!(
+            &sink.events()[..],
+            [StreamEvent::Error(error)] if error.kind == ErrorKind::Network && error.retryable
+        ));
+    }
+
+    /// `data: {"n": 1}\n\n`, gzipped
+    const GZIPPED_EVENT: [u8; 36] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0x49, 0x2c, 0x49, 0xb4,
+        0x52, 0xa8, 0x56, 0xca, 0x53, 0xb2, 0x52, 0x30, 0xac, 0xe5, 0xe2, 0x02, 0x00, 0x83, 0x5a,
+        0x63, 0xf7, 0x10, 0x00, 0x00, 0x00,
+    ];
+
+    #[tokio::test]
+    async fn reads_a_gzipped_stream() {
+        let server = MockServer::start(vec![MockResponse::new("text/event-stream", GZIPPED_EVENT)
+            .header("content-encoding", "gzip")])
+        .await;
+        let response = reqwest::get(&server.url).await.unwrap();
+
+        let sink = RecordingSink::default();
+        let mut data = Vec::new();
+        read_sse(response, None, &sink, &StreamOptions::default(), |event| {
+            data.push(event.data);
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(data, [r#"{"n": 1}"#]);
+        assert!(sink.events().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_an_encoding_the_client_cannot_decode() {
+        let server = MockServer::start(vec![MockResponse::new("text/event-stream", GZIPPED_EVENT)
+            .header("content-encoding", "compress")])
+        .await;
+        let response = reqwest::get(&server.url).await.unwrap();
+
+        let sink = RecordingSink::default();
+        let mut events = 0;
+        let result = read_sse(response, None, &sink, &StreamOptions::default(), |_| {
+            events += 1;
+            Ok(())
+        })
+        .await;
+
+        assert!(
+            matches!(result, Err(ProxyError::UnsupportedEncoding(encoding)) if encoding == "compress")
+        );
+        assert_eq!(events, 0);
+        assert!(matches!(
+            &sink.events()[..],
+            [StreamEvent::Error(error)] if error.kind == ErrorKind::Parse
+        ));
+    }
+
+    #[tokio::test]
+    async fn closes_the_upstream_connection_at_the_output_limit() {
+        let body = "data: {\"text\": \"0123456789\"}\n\n".repeat(10);
+        let mut server = MockServer::start(vec![
+            MockResponse::new("text/event-stream", body).hold_open()
+        ])
+        .await;
+        let response = reqwest::get(&server.url).await.unwrap();
+
+        let sink = RecordingSink::default();
+        let options = StreamOptions {
+            max_output_bytes: Some(64),
+            ..Default::default()
+        };
+        let result = read_sse(response, None, &sink, &options, |_| Ok(())).await;
+
+        assert!(matches!(result, Err(ProxyError::OutputTooLarge(received, 64)) if received > 64));
+        assert!(matches!(
+            &sink.events()[..],
+            [StreamEvent::Error(error)] if error.kind == ErrorKind::Upstream
+        ));
+        assert!(server.disconnected_within(Duration::from_secs(5)).await);
+    }
+}
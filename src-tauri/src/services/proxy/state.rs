@@ -0,0 +1,222 @@
+use crate::services::proxy::active::ActiveStreams;
+use crate::services::proxy::alias::ModelAliases;
+use crate::services::proxy::circuit::CircuitBreakers;
+use crate::services::proxy::keys::KeyPool;
+use crate::services::proxy::limiter::StreamLimiter;
+use crate::services::proxy::metrics::Metrics;
+use crate::services::proxy::middleware::MiddlewareRegistry;
+use crate::services::proxy::models::{ContextWindows, ModelCache};
+use crate::services::proxy::retry::RetryPolicy;
+use crate::services::proxy::usage::UsageTracker;
+#[cfg(feature = "ws-server")]
+use crate::services::proxy::ws::WsServer;
+use crate::services::proxy::{StreamOptions, DEFAULT_MAX_EVENT_BYTES, DEFAULT_REQUEST_TIMEOUT};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri_plugin_http::reqwest::header::HeaderMap;
+
+/// Shared proxy state managed by Tauri
+#[derive(Default)]
+pub struct ProxyState {
+    pub limiter: StreamLimiter,
+    pub circuits: CircuitBreakers,
+    pub keys: KeyPool,
+    pub active: ActiveStreams,
+    pub usage: UsageTracker,
+    pub models: ModelCache,
+    pub context_windows: ContextWindows,
+    pub aliases: ModelAliases,
+    pub metrics: Metrics,
+    pub middlewares: MiddlewareRegistry,
+    proxy_logging: AtomicBool,
+    /// Request timeout in milliseconds; zero means the default
+    request_timeout_ms: AtomicU64,
+    retry_policy: Mutex<RetryPolicy>,
+    /// Single event size limit in bytes; zero means the default
+    max_event_bytes: AtomicU64,
+    /// Response size limit in bytes; zero means no limit
+    max_output_bytes: AtomicU64,
+    /// Standing headers sent with every request to a provider
+    provider_headers: Mutex<HashMap<String, HeaderMap>>,
+    /// Providers opted out of idempotency keys
+    idempotency_disabled: Mutex<HashSet<String>>,
+    /// Configured `anthropic-version`, if not the default
+    anthropic_version: Mutex<Option<String>>,
+    /// Output token limit filled into requests that set none, by provider
+    default_max_tokens: Mutex<HashMap<String, u64>>,
+    /// Directory mock transcripts are read from, once the app data dir is known
+    transcript_dir: Mutex<Option<PathBuf>>,
+    /// WebSocket gateway, while running
+    #[cfg(feature = "ws-server")]
+    ws_server: Mutex<Option<WsServer>>,
+}
+
+impl ProxyState {
+    /// Whether verbose proxy debugging (including raw response capture) is enabled
+    pub fn proxy_logging(&self) -> bool {
+        self.proxy_logging.load(Ordering::Relaxed)
+    }
+
+    pub fn set_proxy_logging(&self, enabled: bool) {
+        self.proxy_logging.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Time allowed for a provider to accept the connection and send response headers
+    pub fn request_timeout(&self) -> Duration {
+        match self.request_timeout_ms.load(Ordering::Relaxed) {
+            0 => DEFAULT_REQUEST_TIMEOUT,
+            ms => Duration::from_millis(ms),
+        }
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+            .lock()
+            .map(|policy| policy.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        if let Ok(mut current) = self.retry_policy.lock() {
+            *current = policy;
+        }
+    }
+
+    pub fn set_request_timeout(&self, timeout: Duration) {
+        self.request_timeout_ms
+            .store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Largest single server-sent event accepted before the stream is aborted
+    pub fn max_event_bytes(&self) -> usize {
+        match self.max_event_bytes.load(Ordering::Relaxed) {
+            0 => DEFAULT_MAX_EVENT_BYTES,
+            bytes => bytes as usize,
+        }
+    }
+
+    pub fn set_max_event_bytes(&self, max_bytes: usize) {
+        self.max_event_bytes
+            .store(max_bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Most bytes of a streamed response read before the stream is cut off, if limited
+    pub fn max_output_bytes(&self) -> Option<usize> {
+        match self.max_output_bytes.load(Ordering::Relaxed) {
+            0 => None,
+            bytes => Some(bytes as usize),
+        }
+    }
+
+    pub fn set_max_output_bytes(&self, max_bytes: Option<usize>) {
+        self.max_output_bytes
+            .store(max_bytes.unwrap_or(0) as u64, Ordering::Relaxed);
+    }
+
+    /// Default headers for a provider's requests
+    pub fn provider_headers(&self, provider: &str) -> HeaderMap {
+        self.provider_headers
+            .lock()
+            .ok()
+            .and_then(|headers| headers.get(provider).cloned())
+            .unwrap_or_default()
+    }
+
+    /// Replace a provider's default headers; an empty map removes them
+    pub fn set_provider_headers(&self, provider: &str, headers: HeaderMap) {
+        if let Ok(mut current) = self.provider_headers.lock() {
+            if headers.is_empty() {
+                current.remove(provider);
+            } else {
+                current.insert(provider.to_string(), headers);
+            }
+        }
+    }
+
+    /// Whether requests to the provider carry an idempotency key; on unless opted out
+    pub fn idempotency_keys(&self, provider: &str) -> bool {
+        self.idempotency_disabled
+            .lock()
+            .map(|disabled| !disabled.contains(provider))
+            .unwrap_or(true)
+    }
+
+    pub fn set_idempotency_keys(&self, provider: &str, enabled: bool) {
+        if let Ok(mut disabled) = self.idempotency_disabled.lock() {
+            if enabled {
+                disabled.remove(provider);
+            } else {
+                disabled.insert(provider.to_string());
+            }
+        }
+    }
+
+    pub fn anthropic_version(&self) -> Option<String> {
+        self.anthropic_version
+            .lock()
+            .ok()
+            .and_then(|version| version.clone())
+    }
+
+    /// Set the `anthropic-version` header; `None` restores the default
+    pub fn set_anthropic_version(&self, version: Option<String>) {
+        if let Ok(mut current) = self.anthropic_version.lock() {
+            *current = version;
+        }
+    }
+
+    pub fn transcript_dir(&self) -> Option<PathBuf> {
+        self.transcript_dir.lock().ok().and_then(|dir| dir.clone())
+    }
+
+    /// Set the directory the mock provider may read transcript files from
+    pub fn set_transcript_dir(&self, dir: PathBuf) {
+        if let Ok(mut current) = self.transcript_dir.lock() {
+            *current = Some(dir);
+        }
+    }
+
+    /// Swap the running WebSocket server, returning the previous one. Dropping it stops
+    /// it.
+    #[cfg(feature = "ws-server")]
+    pub fn replace_ws_server(&self, server: Option<WsServer>) -> Option<WsServer> {
+        self.ws_server
+            .lock()
+            .ok()
+            .and_then(|mut current| std::mem::replace(&mut *current, server))
+    }
+
+    pub fn default_max_tokens(&self, provider: &str) -> Option<u64> {
+        self.default_max_tokens
+            .lock()
+            .ok()
+            .and_then(|defaults| defaults.get(provider).copied())
+    }
+
+    /// Set the output token limit for requests that set none; `None` removes it
+    pub fn set_default_max_tokens(&self, provider: &str, max_tokens: Option<u64>) {
+        if let Ok(mut defaults) = self.default_max_tokens.lock() {
+            match max_tokens {
+                Some(max_tokens) => defaults.insert(provider.to_string(), max_tokens),
+                None => defaults.remove(provider),
+            };
+        }
+    }
+
+    /// Stream options reflecting the current proxy settings
+    pub fn stream_options(&self) -> StreamOptions {
+        StreamOptions {
+            capture_raw: self.proxy_logging(),
+            request_timeout: Some(self.request_timeout()),
+            max_event_bytes: Some(self.max_event_bytes()),
+            max_output_bytes: self.max_output_bytes(),
+            max_resumes: self.retry_policy().retries(),
+            anthropic_version: self.anthropic_version(),
+            transcript_dir: self.transcript_dir(),
+            ..Default::default()
+        }
+    }
+}
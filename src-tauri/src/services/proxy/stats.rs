@@ -0,0 +1,113 @@
+use crate::services::proxy::{EventSink, ProxyError, ProxyResult, StreamEvent};
+use log::info;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Timing summary for a single stream, sent just before the end event
+#[derive(Serialize, Debug, Clone)]
+pub struct StreamStats {
+    pub provider: String,
+    /// Time from sending the request to the first generated output
+    pub ttfb_ms: Option<u64>,
+    pub duration_ms: u64,
+    pub output_tokens: u64,
+    /// Whether `output_tokens` counts text chunks because the provider reported no usage
+    pub tokens_estimated: bool,
+    /// Output tokens per second, measured from the first output to the end
+    pub tokens_per_second: Option<f64>,
+    /// Clock skew reported on the start event, see [`StreamStart::clock_skew_ms`]
+    ///
+    /// [`StreamStart::clock_skew_ms`]: crate::services::proxy::StreamStart::clock_skew_ms
+    pub clock_skew_ms: Option<i64>,
+}
+
+#[derive(Default)]
+struct Measurements {
+    first_output: Option<Instant>,
+    text_chunks: u64,
+    reported_tokens: Option<u64>,
+    clock_skew_ms: Option<i64>,
+}
+
+/// Wraps a sink to measure a stream and emit its stats before the end event
+pub(crate) struct StatsSink<'a> {
+    inner: &'a dyn EventSink,
+    provider: String,
+    started: Instant,
+    measurements: Mutex<Measurements>,
+}
+
+impl<'a> StatsSink<'a> {
+    pub(crate) fn new(inner: &'a dyn EventSink, provider: &str) -> Self {
+        Self {
+            inner,
+            provider: provider.to_string(),
+            started: Instant::now(),
+            measurements: Mutex::new(Measurements::default()),
+        }
+    }
+
+    fn stats(&self, measurements: &Measurements) -> StreamStats {
+        let now = Instant::now();
+        let (output_tokens, tokens_estimated) = match measurements.reported_tokens {
+            Some(tokens) => (tokens, false),
+            None => (measurements.text_chunks, true),
+        };
+        let generation_secs = measurements
+            .first_output
+            .map(|first| now.duration_since(first).as_secs_f64());
+
+        StreamStats {
+            provider: self.provider.clone(),
+            ttfb_ms: measurements
+                .first_output
+                .map(|first| first.duration_since(self.started).as_millis() as u64),
+            duration_ms: now.duration_since(self.started).as_millis() as u64,
+            output_tokens,
+            tokens_estimated,
+            tokens_per_second: generation_secs
+                .filter(|secs| *secs > 0.0)
+                .map(|secs| output_tokens as f64 / secs),
+            clock_skew_ms: measurements.clock_skew_ms,
+        }
+    }
+}
+
+impl EventSink for StatsSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        let mut measurements = self
+            .measurements
+            .lock()
+            .map_err(|e| ProxyError::Emit(format!("Stats lock poisoned: {}", e)))?;
+
+        match &event {
+            StreamEvent::Text { .. } | StreamEvent::ToolDelta(_) => {
+                measurements.first_output.get_or_insert_with(Instant::now);
+                if matches!(event, StreamEvent::Text { .. }) {
+                    measurements.text_chunks += 1;
+                }
+            }
+            StreamEvent::Start(start) => {
+                measurements.clock_skew_ms = start.clock_skew_ms;
+            }
+            StreamEvent::Usage(usage) => {
+                if let Some(tokens) = usage.output_tokens {
+                    measurements.reported_tokens = Some(tokens);
+                }
+            }
+            StreamEvent::End { .. } => {
+                let stats = self.stats(&measurements);
+                info!(
+                    "Stream stats for {}: ttfb {:?}ms, {}ms total, {:?} tokens/s",
+                    stats.provider, stats.ttfb_ms, stats.duration_ms, stats.tokens_per_second
+                );
+                self.inner.emit(StreamEvent::Stats(stats))?;
+            }
+            _ => {}
+        }
+        drop(measurements);
+
+        self.inner.emit(event)
+    }
+}
@@ -0,0 +1,195 @@
+use crate::services::proxy::sse::SseEvent;
+use crate::services::proxy::{EventSink, ProxyResult, StreamEvent};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// Records every event it receives, for assertions on what a stream emitted
+#[derive(Default)]
+pub(crate) struct RecordingSink {
+    events: Mutex<Vec<StreamEvent>>,
+}
+
+impl RecordingSink {
+    pub(crate) fn events(&self) -> Vec<StreamEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Text of every text event, concatenated
+    pub(crate) fn text(&self) -> String {
+        self.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::Text { text } => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Window event names of the recorded events, in order
+    pub(crate) fn names(&self) -> Vec<&'static str> {
+        self.events().iter().map(StreamEvent::event_name).collect()
+    }
+
+    /// Messages of the warning events
+    pub(crate) fn warnings(&self) -> Vec<String> {
+        self.events()
+            .into_iter()
+            .filter_map(|event| match event {
+                StreamEvent::Warning { message } => Some(message),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl EventSink for RecordingSink {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        self.events.lock().unwrap().push(event);
+        Ok(())
+    }
+}
+
+/// An event carrying only `data`
+pub(crate) fn data_event(data: &str) -> SseEvent {
+    SseEvent {
+        data: data.to_string(),
+        ..Default::default()
+    }
+}
+
+/// A scripted `200 OK` response served by [`MockServer`]
+pub(crate) struct MockResponse {
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    hold_open: bool,
+}
+
+impl MockResponse {
+    pub(crate) fn new(content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            headers: vec![("content-type".to_string(), content_type.to_string())],
+            body: body.into(),
+            hold_open: false,
+        }
+    }
+
+    pub(crate) fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Keep the connection open after the body, like a provider still generating,
+    /// until the client hangs up
+    pub(crate) fn hold_open(mut self) -> Self {
+        self.hold_open = true;
+        self
+    }
+}
+
+/// Minimal HTTP server answering one connection per scripted response, in order.
+///
+/// Bodies are delimited by the connection closing unless a response sets its own
+/// `content-length`, which lets a test cut a body short.
+pub(crate) struct MockServer {
+    pub(crate) url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+    disconnects: mpsc::UnboundedReceiver<()>,
+}
+
+impl MockServer {
+    pub(crate) async fn start(responses: Vec<MockResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let (disconnected, disconnects) = mpsc::unbounded_channel();
+
+        let received = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let request = read_request(&mut socket).await;
+                received.lock().unwrap().push(request);
+
+                let mut head = "HTTP/1.1 200 OK\r\nconnection: close\r\n".to_string();
+                for (name, value) in &response.headers {
+                    head.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                head.push_str("\r\n");
+                let written = async {
+                    socket.write_all(head.as_bytes()).await?;
+                    socket.write_all(&response.body).await?;
+                    socket.flush().await
+                };
+                if written.await.is_err() {
+                    continue;
+                }
+
+                if response.hold_open {
+                    let mut buf = [0; 1024];
+                    while let Ok(read) = socket.read(&mut buf).await {
+                        if read == 0 {
+                            break;
+                        }
+                    }
+                    let _ = disconnected.send(());
+                } else {
+                    let _ = socket.shutdown().await;
+                }
+            }
+        });
+
+        Self {
+            url,
+            requests,
+            disconnects,
+        }
+    }
+
+    /// Heads of the requests received so far
+    pub(crate) fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Wait for the client to hang up on a held-open response, giving up after `limit`
+    pub(crate) async fn disconnected_within(&mut self, limit: Duration) -> bool {
+        tokio::time::timeout(limit, self.disconnects.recv())
+            .await
+            .is_ok_and(|disconnected| disconnected.is_some())
+    }
+}
+
+/// Read a request's head and body, returning the head
+async fn read_request(socket: &mut TcpStream) -> String {
+    let mut request = Vec::new();
+    let mut buf = [0; 4096];
+    let head_end = loop {
+        if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => return String::from_utf8_lossy(&request).into_owned(),
+            Ok(read) => request.extend_from_slice(&buf[..read]),
+        }
+    };
+
+    let head = String::from_utf8_lossy(&request[..head_end]).into_owned();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut remaining = content_length.saturating_sub(request.len() - head_end);
+    while remaining > 0 {
+        match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => remaining = remaining.saturating_sub(read),
+        }
+    }
+    head
+}
@@ -0,0 +1,183 @@
+use log::{debug, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Incremental fragment of a tool call's arguments as streamed by the provider
+#[derive(Serialize, Debug, Clone)]
+pub struct ToolCallDelta {
+    pub index: u32,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub arguments_delta: String,
+}
+
+/// A fully assembled tool call
+#[derive(Serialize, Debug, Clone)]
+pub struct ToolCall {
+    pub index: u32,
+    pub id: String,
+    pub name: String,
+    /// Parsed arguments, or the raw argument string if the call is incomplete
+    pub arguments: Value,
+    /// Set when the stream ended before the call was complete
+    pub incomplete: bool,
+}
+
+#[derive(Default, Debug)]
+struct PartialToolCall {
+    id: Option<String>,
+    name: Option<String>,
+    arguments: String,
+}
+
+/// Assembles streamed tool call fragments, keyed by the provider's tool call index.
+///
+/// With `parallel_tool_calls`, fragments of several calls may arrive interleaved; each
+/// index accumulates separately and is assembled into its own [`ToolCall`].
+#[derive(Default, Debug)]
+pub struct ToolCallAccumulator {
+    calls: BTreeMap<u32, PartialToolCall>,
+}
+
+impl ToolCallAccumulator {
+    /// Register a tool call whose id and name are known up front
+    pub fn start(&mut self, index: u32, id: Option<String>, name: Option<String>) {
+        debug!("Tool call {} started: {:?}", index, name);
+        let call = self.calls.entry(index).or_default();
+        if id.is_some() {
+            call.id = id;
+        }
+        if name.is_some() {
+            call.name = name;
+        }
+    }
+
+    /// Append an argument fragment, returning the delta to forward to the client
+    pub fn push(
+        &mut self,
+        index: u32,
+        id: Option<String>,
+        name: Option<String>,
+        fragment: &str,
+    ) -> ToolCallDelta {
+        self.start(index, id.clone(), name.clone());
+        if let Some(call) = self.calls.get_mut(&index) {
+            call.arguments.push_str(fragment);
+        }
+
+        ToolCallDelta {
+            index,
+            id,
+            name,
+            arguments_delta: fragment.to_string(),
+        }
+    }
+
+    /// Complete the tool call at `index`, if one was started
+    pub fn finish(&mut self, index: u32) -> Option<ToolCall> {
+        self.calls
+            .remove(&index)
+            .map(|call| Self::assemble(index, call))
+    }
+
+    /// Complete every pending tool call in index order
+    pub fn finish_all(&mut self) -> Vec<ToolCall> {
+        std::mem::take(&mut self.calls)
+            .into_iter()
+            .map(|(index, call)| Self::assemble(index, call))
+            .collect()
+    }
+
+    /// Hand back every pending tool call unparsed, for when the stream broke off mid-call.
+    ///
+    /// Arguments are left as the raw accumulated string, since a partial fragment
+    /// usually isn't valid JSON yet.
+    pub fn finish_all_incomplete(&mut self) -> Vec<ToolCall> {
+        std::mem::take(&mut self.calls)
+            .into_iter()
+            .map(|(index, call)| {
+                warn!("Tool call {} was cut off mid-arguments", index);
+                ToolCall {
+                    index,
+                    id: call.id.unwrap_or_default(),
+                    name: call.name.unwrap_or_default(),
+                    arguments: Value::String(call.arguments),
+                    incomplete: true,
+                }
+            })
+            .collect()
+    }
+
+    fn assemble(index: u32, call: PartialToolCall) -> ToolCall {
+        let arguments = if call.arguments.trim().is_empty() {
+            Value::Object(Default::default())
+        } else {
+            match serde_json::from_str(&call.arguments) {
+                Ok(arguments) => arguments,
+                Err(e) => {
+                    warn!("Tool call {} arguments are not valid JSON: {}", index, e);
+                    Value::String(call.arguments)
+                }
+            }
+        };
+
+        ToolCall {
+            index,
+            id: call.id.unwrap_or_default(),
+            name: call.name.unwrap_or_default(),
+            arguments,
+            incomplete: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn hands_back_cut_off_calls_with_raw_arguments() {
+        let mut calls = ToolCallAccumulator::default();
+        calls.push(
+            0,
+            Some("call_1".to_string()),
+            Some("search".to_string()),
+            "{\"q\": \"ru",
+        );
+
+        let cut_off = calls.finish_all_incomplete();
+        assert_eq!(cut_off.len(), 1);
+        assert_eq!(cut_off[0].id, "call_1");
+        assert_eq!(cut_off[0].name, "search");
+        assert_eq!(cut_off[0].arguments, json!("{\"q\": \"ru"));
+        assert!(cut_off[0].incomplete);
+        assert!(calls.finish_all().is_empty());
+    }
+
+    #[test]
+    fn assembles_completed_calls() {
+        let mut calls = ToolCallAccumulator::default();
+        calls.start(0, Some("call_1".to_string()), Some("search".to_string()));
+        calls.push(0, None, None, "{\"q\": ");
+        calls.push(0, None, None, "\"rust\"}");
+        calls.start(1, Some("call_2".to_string()), Some("now".to_string()));
+        calls.push(
+            2,
+            Some("call_3".to_string()),
+            Some("bad".to_string()),
+            "{oops",
+        );
+
+        let done = calls.finish_all();
+        assert_eq!(done.len(), 3);
+        assert_eq!(done[0].arguments, json!({"q": "rust"}));
+        assert!(!done[0].incomplete);
+        // No arguments at all means an empty object
+        assert_eq!(done[1].arguments, json!({}));
+        // Arguments that don't parse are kept as the raw string
+        assert_eq!(done[2].arguments, json!("{oops"));
+        assert!(!done[2].incomplete);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::services::proxy::models::ContextWindows;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Rough number of characters per token, used in place of a real tokenizer
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Tokens added per message for the role and framing
+const MESSAGE_OVERHEAD_TOKENS: u64 = 4;
+
+/// Output tokens reserved when the request sets no limit of its own
+const DEFAULT_OUTPUT_RESERVE: u64 = 4096;
+
+/// Request fields that cap the output length, by provider naming
+const MAX_OUTPUT_FIELDS: &[&str] = &["max_tokens", "max_completion_tokens", "max_output_tokens"];
+
+/// Payload of the trimmed event, sent when old messages were dropped before the request
+#[derive(Serialize, Debug, Clone)]
+pub struct StreamTrimmed {
+    /// Number of messages dropped from the start of the history
+    pub dropped: usize,
+    /// Estimated prompt size after trimming
+    pub estimated_tokens: u64,
+    pub context_window: u64,
+}
+
+/// Estimate the token count of a JSON value from its serialized length
+pub fn estimate_tokens(value: &Value) -> u64 {
+    value.to_string().len().div_ceil(CHARS_PER_TOKEN) as u64
+}
+
+fn message_tokens(message: &Value) -> u64 {
+    estimate_tokens(message) + MESSAGE_OVERHEAD_TOKENS
+}
+
+fn role(message: &Value) -> &str {
+    message
+        .get("role")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+fn is_system(message: &Value) -> bool {
+    matches!(role(message), "system" | "developer")
+}
+
+/// Whether a message only makes sense after an earlier one: a reply, or a tool result
+/// for a call that was dropped
+fn is_orphaned(message: &Value) -> bool {
+    if matches!(role(message), "assistant" | "tool") {
+        return true;
+    }
+    let blocks = message.get("content").and_then(Value::as_array);
+    blocks
+        .into_iter()
+        .flatten()
+        .any(|block| block.get("type").and_then(Value::as_str) == Some("tool_result"))
+}
+
+/// Drop the leading messages left orphaned once older history was dropped: the
+/// remaining history can't open with a reply or tool result. Returns their indices.
+fn drop_orphaned(messages: &[Value], keep: &mut [bool]) -> Vec<usize> {
+    let last = messages.len().saturating_sub(1);
+    let mut orphaned = Vec::new();
+    for (i, message) in messages.iter().enumerate().take(last) {
+        if !keep[i] || is_system(message) {
+            continue;
+        }
+        if !is_orphaned(message) {
+            break;
+        }
+        keep[i] = false;
+        orphaned.push(i);
+    }
+    orphaned
+}
+
+/// Number of messages in the request's `messages` array, or `None` if it has none
+pub fn count_messages(body: &Value) -> Option<usize> {
+    body.get("messages").and_then(Value::as_array).map(Vec::len)
+}
+
+/// Drop the oldest messages until at most `max` remain, keeping system messages and
+/// the latest message like [`trim_to_context`]. Returns the number dropped.
+pub fn trim_to_count(body: &mut Value, max: usize) -> usize {
+    let Some(messages) = body.get_mut("messages").and_then(Value::as_array_mut) else {
+        return 0;
+    };
+    let last = messages.len().saturating_sub(1);
+    let mut keep = vec![true; messages.len()];
+    let mut remaining = messages.len();
+
+    for (i, message) in messages.iter().enumerate().take(last) {
+        if remaining <= max {
+            break;
+        }
+        if is_system(message) {
+            continue;
+        }
+        keep[i] = false;
+        remaining -= 1;
+    }
+    if remaining < messages.len() {
+        drop_orphaned(messages, &mut keep);
+    }
+
+    let total = messages.len();
+    let mut keep = keep.into_iter();
+    messages.retain(|_| keep.next().unwrap_or(true));
+    let dropped = total - messages.len();
+    if dropped > 0 {
+        info!(
+            "Dropped {} messages to stay within {} messages",
+            dropped, max
+        );
+    }
+    dropped
+}
+
+/// Drop the oldest messages until the request, plus room for its output, is estimated
+/// to fit the model's context window.
+///
+/// System messages (and Anthropic's top-level `system` prompt) are always kept, as is
+/// the latest message. Returns `None` when nothing was dropped or the model's context
+/// window is unknown.
+pub fn trim_to_context(body: &mut Value, windows: &ContextWindows) -> Option<StreamTrimmed> {
+    let model = body.get("model").and_then(Value::as_str)?;
+    let context_window = windows.lookup(model)?;
+    let reserve = MAX_OUTPUT_FIELDS
+        .iter()
+        .find_map(|field| body.get(field).and_then(Value::as_u64))
+        .unwrap_or(DEFAULT_OUTPUT_RESERVE);
+    let budget = context_window.saturating_sub(reserve);
+
+    let mut messages = std::mem::take(body.get_mut("messages")?.as_array_mut()?);
+    // Everything besides the messages (system prompt, tools, ...) counts too
+    let mut total = estimate_tokens(body) + messages.iter().map(message_tokens).sum::<u64>();
+    let last = messages.len().saturating_sub(1);
+    let mut keep = vec![true; messages.len()];
+    let mut dropped = 0;
+
+    for (i, message) in messages.iter().enumerate().take(last) {
+        if total <= budget {
+            break;
+        }
+        if is_system(message) {
+            continue;
+        }
+        keep[i] = false;
+        total -= message_tokens(message);
+        dropped += 1;
+    }
+
+    if dropped > 0 {
+        for i in drop_orphaned(&messages, &mut keep) {
+            total -= message_tokens(&messages[i]);
+            dropped += 1;
+        }
+    }
+
+    let mut keep = keep.into_iter();
+    messages.retain(|_| keep.next().unwrap_or(true));
+    body["messages"] = Value::Array(messages);
+
+    if dropped == 0 {
+        return None;
+    }
+    if total > budget {
+        warn!(
+            "Request still estimated at {} tokens after trimming, over the {} token budget",
+            total, budget
+        );
+    }
+    info!(
+        "Dropped {} messages to fit the {} token context window",
+        dropped, context_window
+    );
+    Some(StreamTrimmed {
+        dropped,
+        estimated_tokens: total,
+        context_window,
+    })
+}
@@ -0,0 +1,168 @@
+use crate::services::proxy::{
+    apply_extra_headers, check_status, emit_audio, emit_end, emit_start, emit_structured_error,
+    http_client, send_request,
+};
+use crate::services::proxy::{
+    AudioChunk, ErrorKind, EventSink, FinishReason, ProxyError, ProxyProvider, ProxyResult,
+    StreamOptions, StreamStart,
+};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::StreamExt;
+use log::{debug, error, info};
+use serde_json::Value;
+use std::sync::atomic::Ordering;
+use tauri_plugin_http::reqwest::{
+    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    Response,
+};
+
+/// Format OpenAI uses when the request doesn't set `response_format`
+const DEFAULT_AUDIO_FORMAT: &str = "mp3";
+
+/// Streams synthesized speech from OpenAI's speech endpoint as audio chunks
+pub struct OpenAITtsProvider {
+    api_key: String,
+}
+
+impl OpenAITtsProvider {
+    pub fn new(api_key: String) -> Self {
+        Self { api_key }
+    }
+}
+
+fn mime_for_format(format: &str) -> &'static str {
+    match format {
+        "mp3" => "audio/mpeg",
+        "opus" => "audio/opus",
+        "aac" => "audio/aac",
+        "flac" => "audio/flac",
+        "wav" => "audio/wav",
+        "pcm" => "audio/pcm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Emit each chunk of the response body as base64 audio, returning the bytes received
+async fn read_audio(
+    response: Response,
+    format: &str,
+    sink: &dyn EventSink,
+    options: &StreamOptions,
+) -> ProxyResult<usize> {
+    let mime = mime_for_format(format);
+    let mut stream = response.bytes_stream();
+    let mut total_bytes = 0;
+    while let Some(item) = stream.next().await {
+        let chunk = match item {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                let error_msg = format!("Error reading audio chunk: {}", e);
+                error!("{}", error_msg);
+                emit_structured_error(sink, ErrorKind::Network, error_msg, true)?;
+                return Err(ProxyError::Http(e));
+            }
+        };
+        if let Some(bytes_streamed) = &options.bytes_streamed {
+            bytes_streamed.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }
+        total_bytes += chunk.len();
+
+        emit_audio(
+            sink,
+            AudioChunk {
+                format: format.to_string(),
+                mime: mime.to_string(),
+                data: STANDARD.encode(&chunk),
+            },
+        )?;
+    }
+    Ok(total_bytes)
+}
+
+#[async_trait]
+impl ProxyProvider for OpenAITtsProvider {
+    async fn stream(
+        &self,
+        sink: &dyn EventSink,
+        body: Value,
+        options: StreamOptions,
+    ) -> ProxyResult<()> {
+        let format = body
+            .get("response_format")
+            .and_then(Value::as_str)
+            .unwrap_or(DEFAULT_AUDIO_FORMAT)
+            .to_string();
+
+        let client = http_client()?;
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", self.api_key))
+                .map_err(|e| ProxyError::ApiKey(format!("Invalid OpenAI API key format: {}", e)))?,
+        );
+        apply_extra_headers(&mut headers, &options);
+
+        let request = client
+            .post("https://api.openai.com/v1/audio/speech")
+            .headers(headers)
+            .json(&body);
+        let response = send_request(request, &options, "OpenAI TTS").await?;
+        let response = check_status(response, sink, "openai-tts", "OpenAI TTS").await?;
+
+        debug!("Starting to process OpenAI TTS stream ({})", format);
+        emit_start(sink, StreamStart::default())?;
+        let total_bytes = read_audio(response, &format, sink, &options).await?;
+
+        info!("OpenAI TTS stream completed ({} bytes)", total_bytes);
+        emit_end(sink, Some(FinishReason::Stop))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::proxy::test_support::{MockResponse, MockServer, RecordingSink};
+    use crate::services::proxy::StreamEvent;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::Arc;
+    use tauri_plugin_http::reqwest;
+
+    #[test]
+    fn maps_formats_to_mime_types() {
+        assert_eq!(mime_for_format("mp3"), "audio/mpeg");
+        assert_eq!(mime_for_format("opus"), "audio/opus");
+        assert_eq!(mime_for_format("wav"), "audio/wav");
+        assert_eq!(mime_for_format("aiff"), "application/octet-stream");
+    }
+
+    #[tokio::test]
+    async fn streams_the_body_as_base64_audio_chunks() {
+        let audio: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let server = MockServer::start(vec![MockResponse::new("audio/ogg", audio.clone())]).await;
+        let response = reqwest::get(&server.url).await.unwrap();
+        let bytes_streamed = Arc::new(AtomicU64::new(0));
+        let options = StreamOptions {
+            bytes_streamed: Some(bytes_streamed.clone()),
+            ..Default::default()
+        };
+
+        let sink = RecordingSink::default();
+        let total = read_audio(response, "opus", &sink, &options).await.unwrap();
+        assert_eq!(total, audio.len());
+        assert_eq!(bytes_streamed.load(Ordering::Relaxed), audio.len() as u64);
+
+        let mut decoded = Vec::new();
+        for event in sink.events() {
+            let StreamEvent::Audio(chunk) = event else {
+                panic!("expected only audio events");
+            };
+            assert_eq!(chunk.format, "opus");
+            assert_eq!(chunk.mime, "audio/opus");
+            decoded.extend(STANDARD.decode(chunk.data).unwrap());
+        }
+        assert_eq!(decoded, audio);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::services::proxy::{EventSink, ProxyResult, StreamEvent};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Accumulated usage for one user
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct UserUsage {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// Per-user token usage, for quotas and cost allocation
+#[derive(Default)]
+pub struct UsageTracker {
+    users: Mutex<HashMap<String, UserUsage>>,
+}
+
+impl UsageTracker {
+    pub fn get(&self, user_id: &str) -> UserUsage {
+        self.users
+            .lock()
+            .ok()
+            .and_then(|users| users.get(user_id).cloned())
+            .unwrap_or_default()
+    }
+
+    fn record(&self, user_id: &str, update: impl FnOnce(&mut UserUsage)) {
+        if let Ok(mut users) = self.users.lock() {
+            update(users.entry(user_id.to_string()).or_default());
+        }
+    }
+}
+
+/// Tag the request with the user in the field the provider uses for abuse tracking
+pub fn tag_user(provider: &str, body: &mut Value, user_id: &str) {
+    let Some(body) = body.as_object_mut() else {
+        return;
+    };
+    match provider {
+        "anthropic" => {
+            let metadata = body.entry("metadata").or_insert_with(|| json!({}));
+            if let Some(metadata) = metadata.as_object_mut() {
+                metadata.insert("user_id".to_string(), json!(user_id));
+            }
+        }
+        _ => {
+            body.insert("user".to_string(), json!(user_id));
+        }
+    }
+}
+
+/// Wraps a sink to add the stream's usage to a user's totals
+pub(crate) struct UserUsageSink<'a> {
+    inner: &'a dyn EventSink,
+    tracker: &'a UsageTracker,
+    user_id: String,
+}
+
+impl<'a> UserUsageSink<'a> {
+    pub(crate) fn new(inner: &'a dyn EventSink, tracker: &'a UsageTracker, user_id: &str) -> Self {
+        tracker.record(user_id, |usage| usage.requests += 1);
+        Self {
+            inner,
+            tracker,
+            user_id: user_id.to_string(),
+        }
+    }
+}
+
+impl EventSink for UserUsageSink<'_> {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        if let StreamEvent::Usage(usage) = &event {
+            self.tracker.record(&self.user_id, |totals| {
+                totals.input_tokens += usage.input_tokens.unwrap_or(0);
+                totals.output_tokens += usage.output_tokens.unwrap_or(0);
+            });
+        }
+        self.inner.emit(event)
+    }
+}
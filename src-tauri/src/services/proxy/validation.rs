@@ -0,0 +1,136 @@
+use crate::services::proxy::{ProxyError, ProxyResult};
+use serde_json::Value;
+
+/// Checks one provider-specific payload field, describing the problem on failure
+type FieldValidator = fn(&Value) -> Result<(), String>;
+
+/// Provider-specific fields checked locally before the upstream call, as
+/// `(provider, field, validator)`. Add a row to cover a new field or provider.
+const FIELD_VALIDATORS: &[(&str, &str, FieldValidator)] = &[
+    ("openai", "safety_identifier", validate_openai_identifier),
+    ("openai", "user", validate_openai_identifier),
+    ("openai", "store", validate_boolean),
+    ("openai", "parallel_tool_calls", validate_boolean),
+    ("openai", "metadata", validate_openai_metadata),
+    ("anthropic", "metadata", validate_anthropic_metadata),
+    ("gemini", "safetySettings", validate_gemini_safety_settings),
+];
+
+const OPENAI_IDENTIFIER_MAX_LEN: usize = 64;
+const OPENAI_METADATA_MAX_KEYS: usize = 16;
+const OPENAI_METADATA_KEY_MAX_LEN: usize = 64;
+const OPENAI_METADATA_VALUE_MAX_LEN: usize = 512;
+
+const GEMINI_HARM_CATEGORIES: &[&str] = &[
+    "HARM_CATEGORY_HARASSMENT",
+    "HARM_CATEGORY_HATE_SPEECH",
+    "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+    "HARM_CATEGORY_DANGEROUS_CONTENT",
+    "HARM_CATEGORY_CIVIC_INTEGRITY",
+];
+
+const GEMINI_THRESHOLDS: &[&str] = &[
+    "HARM_BLOCK_THRESHOLD_UNSPECIFIED",
+    "BLOCK_LOW_AND_ABOVE",
+    "BLOCK_MEDIUM_AND_ABOVE",
+    "BLOCK_ONLY_HIGH",
+    "BLOCK_NONE",
+    "OFF",
+];
+
+/// Validate the provider-specific fields present in a request body
+pub fn validate_payload(provider: &str, body: &Value) -> ProxyResult<()> {
+    for (_, field, validator) in FIELD_VALIDATORS
+        .iter()
+        .filter(|(name, _, _)| *name == provider)
+    {
+        if let Some(value) = body.get(field) {
+            validator(value).map_err(|reason| {
+                ProxyError::InvalidPayload(format!("{} `{}`: {}", provider, field, reason))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_openai_identifier(value: &Value) -> Result<(), String> {
+    let identifier = value.as_str().ok_or("must be a string")?;
+    if identifier.len() > OPENAI_IDENTIFIER_MAX_LEN {
+        return Err(format!(
+            "must be at most {} characters",
+            OPENAI_IDENTIFIER_MAX_LEN
+        ));
+    }
+    Ok(())
+}
+
+fn validate_boolean(value: &Value) -> Result<(), String> {
+    match value {
+        Value::Bool(_) | Value::Null => Ok(()),
+        _ => Err("must be a boolean".to_string()),
+    }
+}
+
+fn validate_openai_metadata(value: &Value) -> Result<(), String> {
+    let metadata = match value {
+        Value::Null => return Ok(()),
+        value => value.as_object().ok_or("must be an object")?,
+    };
+    if metadata.len() > OPENAI_METADATA_MAX_KEYS {
+        return Err(format!(
+            "has {} keys, at most {} are allowed",
+            metadata.len(),
+            OPENAI_METADATA_MAX_KEYS
+        ));
+    }
+    for (key, value) in metadata {
+        if key.chars().count() > OPENAI_METADATA_KEY_MAX_LEN {
+            return Err(format!(
+                "key `{}` is longer than {} characters",
+                key, OPENAI_METADATA_KEY_MAX_LEN
+            ));
+        }
+        let value = value
+            .as_str()
+            .ok_or_else(|| format!("value of `{}` must be a string", key))?;
+        if value.chars().count() > OPENAI_METADATA_VALUE_MAX_LEN {
+            return Err(format!(
+                "value of `{}` is longer than {} characters",
+                key, OPENAI_METADATA_VALUE_MAX_LEN
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_anthropic_metadata(value: &Value) -> Result<(), String> {
+    let metadata = value.as_object().ok_or("must be an object")?;
+    for (key, value) in metadata {
+        match key.as_str() {
+            "user_id" if value.is_string() || value.is_null() => {}
+            "user_id" => return Err("`user_id` must be a string".to_string()),
+            other => return Err(format!("unknown key `{}`", other)),
+        }
+    }
+    Ok(())
+}
+
+fn validate_gemini_safety_settings(value: &Value) -> Result<(), String> {
+    let settings = value.as_array().ok_or("must be an array")?;
+    for (i, setting) in settings.iter().enumerate() {
+        let category = setting.get("category").and_then(Value::as_str);
+        match category {
+            Some(category) if GEMINI_HARM_CATEGORIES.contains(&category) => {}
+            Some(category) => return Err(format!("[{}] unknown category `{}`", i, category)),
+            None => return Err(format!("[{}] missing `category`", i)),
+        }
+
+        let threshold = setting.get("threshold").and_then(Value::as_str);
+        match threshold {
+            Some(threshold) if GEMINI_THRESHOLDS.contains(&threshold) => {}
+            Some(threshold) => return Err(format!("[{}] unknown threshold `{}`", i, threshold)),
+            None => return Err(format!("[{}] missing `threshold`", i)),
+        }
+    }
+    Ok(())
+}
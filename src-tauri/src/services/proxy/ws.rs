@@ -0,0 +1,321 @@
+//! WebSocket gateway serving proxy streams to remote frontends.
+//!
+//! Clients connect with the shared token, either as `Authorization: Bearer <token>` or
+//! a `token` query parameter for browsers, which can't set headers. Each text frame is
+//! a [`WsRequest`]; the stream's [`StreamEvent`]s come back as `event` frames tagged
+//! with the request id, followed by a `done` frame carrying the outcome. Several
+//! requests may stream at once on one connection, and closing the connection cancels
+//! them.
+
+use crate::completion::stream_with_state;
+use crate::services::proxy::{
+    build_extra_headers, EventSink, ProxyError, ProxyResult, ProxyState, StreamEvent, StreamOptions,
+};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tauri::{AppHandle, Manager, Runtime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::sync::CancellationToken;
+
+/// Interface the server binds to unless told otherwise
+pub const DEFAULT_WS_HOST: &str = "127.0.0.1";
+
+/// A stream request sent by a client as a text frame
+#[derive(Deserialize, Debug)]
+pub struct WsRequest {
+    /// Client-chosen id echoed on every frame of the stream
+    pub request_id: String,
+    pub provider: String,
+    pub payload: Value,
+    #[serde(default)]
+    pub extra_headers: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub stream_id: Option<String>,
+    #[serde(default)]
+    pub user_id: Option<String>,
+}
+
+/// A frame sent to the client
+#[derive(Serialize)]
+#[serde(tag = "frame", rename_all = "snake_case")]
+enum WsFrame<'a> {
+    /// An event of a request's stream
+    Event {
+        request_id: &'a str,
+        event: StreamEvent,
+    },
+    /// The request finished; `error` is set if it failed
+    Done {
+        request_id: &'a str,
+        error: Option<String>,
+    },
+    /// A text frame that isn't a valid request
+    Rejected { error: String },
+}
+
+fn send_frame(tx: &UnboundedSender<Message>, frame: &WsFrame) -> ProxyResult<()> {
+    let json = serde_json::to_string(frame)?;
+    tx.send(Message::text(json))
+        .map_err(|_| ProxyError::Emit("WebSocket client disconnected".to_string()))
+}
+
+/// Delivers a request's events to its WebSocket connection
+struct WsSink {
+    request_id: String,
+    tx: UnboundedSender<Message>,
+}
+
+impl EventSink for WsSink {
+    fn emit(&self, event: StreamEvent) -> ProxyResult<()> {
+        send_frame(
+            &self.tx,
+            &WsFrame::Event {
+                request_id: &self.request_id,
+                event,
+            },
+        )
+    }
+}
+
+/// A running WebSocket server; it stops when this handle is dropped
+pub struct WsServer {
+    addr: SocketAddr,
+    shutdown: CancellationToken,
+}
+
+impl WsServer {
+    /// Address the server is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for WsServer {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// Start serving proxy streams on `host:port`, authenticating clients with `token`
+pub async fn start<R: Runtime>(
+    app: AppHandle<R>,
+    host: &str,
+    port: u16,
+    token: String,
+) -> ProxyResult<WsServer> {
+    if token.is_empty() {
+        return Err(ProxyError::Config(
+            "WebSocket token must not be empty".to_string(),
+        ));
+    }
+
+    let listener = TcpListener::bind((host, port))
+        .await
+        .map_err(|e| ProxyError::Config(format!("Failed to bind {}:{}: {}", host, port, e)))?;
+    let addr = listener
+        .local_addr()
+        .map_err(|e| ProxyError::Config(format!("Failed to read bound address: {}", e)))?;
+    let shutdown = CancellationToken::new();
+
+    info!("WebSocket server listening on {}", addr);
+    tauri::async_runtime::spawn(accept_loop(
+        app,
+        listener,
+        Arc::new(token),
+        shutdown.clone(),
+    ));
+    Ok(WsServer { addr, shutdown })
+}
+
+async fn accept_loop<R: Runtime>(
+    app: AppHandle<R>,
+    listener: TcpListener,
+    token: Arc<String>,
+    shutdown: CancellationToken,
+) {
+    while let Some(accepted) = shutdown.run_until_cancelled(listener.accept()).await {
+        match accepted {
+            Ok((stream, peer)) => {
+                tauri::async_runtime::spawn(serve_connection(
+                    app.clone(),
+                    stream,
+                    peer,
+                    token.clone(),
+                    shutdown.child_token(),
+                ));
+            }
+            Err(e) => warn!("Failed to accept WebSocket connection: {}", e),
+        }
+    }
+    info!("WebSocket server stopped");
+}
+
+/// Whether the handshake carries the shared token
+fn is_authorized(request: &Request, token: &str) -> bool {
+    let bearer = request
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let query = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+    });
+    bearer
+        .into_iter()
+        .chain(query)
+        .any(|given| tokens_match(given, token))
+}
+
+/// Compare tokens without stopping at the first differing byte
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn serve_connection<R: Runtime>(
+    app: AppHandle<R>,
+    stream: TcpStream,
+    peer: SocketAddr,
+    token: Arc<String>,
+    connection: CancellationToken,
+) {
+    let authorize = |request: &Request, response: Response| {
+        if is_authorized(request, &token) {
+            return Ok(response);
+        }
+        let mut rejection = ErrorResponse::new(Some("Invalid or missing token".to_string()));
+        *rejection.status_mut() = StatusCode::UNAUTHORIZED;
+        Err(rejection)
+    };
+    let ws = match tokio_tungstenite::accept_hdr_async(stream, authorize).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            warn!("WebSocket handshake with {} failed: {}", peer, e);
+            return;
+        }
+    };
+    info!("WebSocket client {} connected", peer);
+
+    let (mut outgoing, mut incoming) = ws.split();
+    let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+    let writer = tauri::async_runtime::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if let Err(e) = outgoing.send(message).await {
+                debug!("Failed to write to WebSocket client {}: {}", peer, e);
+                break;
+            }
+        }
+    });
+
+    while let Some(Some(message)) = connection.run_until_cancelled(incoming.next()).await {
+        match message {
+            Ok(Message::Text(text)) => handle_request(&app, text.as_str(), &tx, &connection),
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("WebSocket client {} errored: {}", peer, e);
+                break;
+            }
+        }
+    }
+
+    // Cancels the connection's streams, which release their senders so the writer ends
+    connection.cancel();
+    drop(tx);
+    let _ = writer.await;
+    info!("WebSocket client {} disconnected", peer);
+}
+
+fn handle_request<R: Runtime>(
+    app: &AppHandle<R>,
+    text: &str,
+    tx: &UnboundedSender<Message>,
+    connection: &CancellationToken,
+) {
+    let request: WsRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = format!("Invalid request frame: {}", e);
+            if let Err(e) = send_frame(tx, &WsFrame::Rejected { error }) {
+                debug!("{}", e);
+            }
+            return;
+        }
+    };
+    info!(
+        "Received WebSocket stream request {} for provider: {}",
+        request.request_id, request.provider
+    );
+
+    let app = app.clone();
+    let tx = tx.clone();
+    let cancel = connection.clone();
+    tauri::async_runtime::spawn(async move {
+        let request_id = request.request_id.clone();
+        let sink = WsSink {
+            request_id: request_id.clone(),
+            tx: tx.clone(),
+        };
+        let Some(result) = cancel
+            .run_until_cancelled(run_request(&app, request, &sink))
+            .await
+        else {
+            debug!("WebSocket stream {} cancelled", request_id);
+            return;
+        };
+        let done = WsFrame::Done {
+            request_id: &request_id,
+            error: result.err().map(|e| e.to_string()),
+        };
+        if let Err(e) = send_frame(&tx, &done) {
+            debug!("{}", e);
+        }
+    });
+}
+
+async fn run_request<R: Runtime>(
+    app: &AppHandle<R>,
+    request: WsRequest,
+    sink: &WsSink,
+) -> ProxyResult<()> {
+    if !request.payload.is_object() {
+        return Err(ProxyError::InvalidPayload(
+            "Payload must be a JSON object".to_string(),
+        ));
+    }
+
+    let proxy_state = app.state::<ProxyState>();
+    let extra_headers = match &request.extra_headers {
+        Some(headers) => build_extra_headers(headers)?,
+        None => Default::default(),
+    };
+    let options = StreamOptions {
+        extra_headers,
+        stream_id: request.stream_id,
+        user_id: request.user_id,
+        ..proxy_state.stream_options()
+    };
+    stream_with_state(
+        &proxy_state,
+        &request.provider,
+        request.payload,
+        options,
+        sink,
+    )
+    .await
+}